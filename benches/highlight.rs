@@ -0,0 +1,81 @@
+//! Parsing and highlighting Python documents of different sizes.
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use druid::text::EditableText;
+use lyranos::codetext::CodeText;
+use lyranos::text;
+
+/// Python with the usual mix of definitions, strings, comments and nested
+/// blocks, repeated to make the larger documents.
+const CHUNK: &str = r#"import os
+
+
+class Counter:
+    """Count the lines of the files in a directory."""
+
+    def __init__(self, root):
+        self.root = root
+        self.counts = {}
+
+    def count(self, name):
+        # Binary files are skipped.
+        path = os.path.join(self.root, name)
+        try:
+            with open(path) as f:
+                self.counts[name] = sum(1 for _ in f)
+        except UnicodeDecodeError:
+            print(f"skipping {name!r}\n")
+        return self.counts.get(name, 0)
+"#;
+
+/// The documents measured, by name: a small one and ones of about a
+/// thousand and ten thousand lines.
+fn documents() -> Vec<(&'static str, String)> {
+    let chunk_lines = CHUNK.lines().count();
+    vec![
+        ("small", CHUNK.to_owned()),
+        ("1k", CHUNK.repeat(1_000 / chunk_lines)),
+        ("10k", CHUNK.repeat(10_000 / chunk_lines)),
+    ]
+}
+
+/// Updating after an edit: typing a character in the middle of the document,
+/// which reparses and highlights incrementally, and parsing and highlighting
+/// from scratch.
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    for (name, source) in documents() {
+        let code = CodeText::new(source);
+        let middle = text::line_start(&code.buffer, code.len() / 2);
+        group.bench_with_input(BenchmarkId::new("edit", name), &code, |b, code| {
+            b.iter_batched(
+                || code.clone(),
+                |mut code| code.edit(middle..middle, "x"),
+                BatchSize::SmallInput,
+            )
+        });
+        group.bench_with_input(BenchmarkId::new("reparse_full", name), &code, |b, code| {
+            b.iter_batched(
+                || code.clone(),
+                |mut code| code.reparse_full(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+/// Running the highlight query over the whole syntax tree.
+fn highlight_spans(c: &mut Criterion) {
+    let mut group = c.benchmark_group("highlight_spans");
+    for (name, source) in documents() {
+        let code = CodeText::new(source);
+        group.bench_with_input(BenchmarkId::from_parameter(name), &code, |b, code| {
+            b.iter(|| code.highlight_spans())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, update, highlight_spans);
+criterion_main!(benches);
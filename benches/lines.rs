@@ -0,0 +1,27 @@
+//! Finding lines by number, as going to a line does, by scanning the text
+//! and with the line index of the document.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use lyranos::codetext::CodeText;
+use lyranos::text;
+
+/// A line of Python, repeated to make documents of different lengths.
+const LINE: &str = "    total = sum(len(line) for line in lines)  # count\n";
+
+fn go_to_line(c: &mut Criterion) {
+    let mut group = c.benchmark_group("go_to_line");
+    for lines in [1_000, 10_000] {
+        let code = CodeText::new(LINE.repeat(lines));
+        let target = lines * 9 / 10;
+        group.bench_with_input(BenchmarkId::new("scan", lines), &code, |b, code| {
+            b.iter(|| text::line_column_offset(&code.buffer, target, 0))
+        });
+        group.bench_with_input(BenchmarkId::new("index", lines), &code, |b, code| {
+            b.iter(|| code.line_range(target).map(|range| range.start))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, go_to_line);
+criterion_main!(benches);
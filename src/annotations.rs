@@ -0,0 +1,93 @@
+//! Tick marks on the scrollbar track showing where notable lines are in the
+//! document.
+
+use druid::widget::prelude::*;
+use druid::{Color, Rect};
+
+use crate::AppState;
+
+/// The height of a tick mark.
+const TICK_HEIGHT: f64 = 2.0;
+
+/// Wraps a vertical [`Scroll`](druid::widget::Scroll) of the editor and draws
+/// tick marks over its scrollbar track. Each line is mapped to the track
+/// proportionally to its index, so the marks line up with the scrollbar
+/// rather than with the text.
+pub struct ScrollAnnotations<W> {
+    inner: W,
+}
+
+impl<W> ScrollAnnotations<W> {
+    pub fn new(inner: W) -> Self {
+        ScrollAnnotations { inner }
+    }
+}
+
+/// The lines to mark and the color of their marks, in the categories enabled
+/// in the settings.
+fn annotations(data: &AppState) -> Vec<(usize, Color)> {
+    let mut annotations = Vec::new();
+    if data.settings.annotate_syntax_errors {
+        for error in data.code.syntax_errors() {
+            let line = data.code.line_of(error.start);
+            annotations.push((line, data.theme.error.clone()));
+        }
+        for &start in data.code.inconsistent_indentation() {
+            let line = data.code.line_of(start);
+            annotations.push((line, data.theme.warning.clone()));
+        }
+    }
+    for diagnostic in data.diagnostics.iter() {
+        let line = data.code.line_of(diagnostic.range.start);
+        annotations.push((line, data.theme.diagnostic(diagnostic.severity).clone()));
+    }
+    annotations
+}
+
+impl<W: Widget<AppState>> Widget<AppState> for ScrollAnnotations<W> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        self.inner.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        self.inner.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        self.inner.update(ctx, old_data, data, env);
+        if !old_data.code.same(&data.code)
+            || !old_data.settings.same(&data.settings)
+            || !old_data.theme.same(&data.theme)
+        {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        env: &Env,
+    ) -> Size {
+        self.inner.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        self.inner.paint(ctx, data, env);
+        let annotations = annotations(data);
+        if annotations.is_empty() {
+            return;
+        }
+        let size = ctx.size();
+        let width = env.get(druid::theme::SCROLLBAR_WIDTH);
+        let pad = env.get(druid::theme::SCROLLBAR_PAD);
+        let x1 = size.width - pad;
+        let line_count = data.code.line_count() as f64;
+        for (line, color) in annotations {
+            let y = (line as f64 + 0.5) / line_count * (size.height - TICK_HEIGHT);
+            let tick = Rect::new(x1 - width, y, x1, y + TICK_HEIGHT);
+            ctx.fill(tick, &color);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! The commit that last changed each line of a file in a git repository,
+//! shown in the gutter.
+
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+#[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+use std::{fs, path::Path, thread};
+
+use druid::{Data, Selector};
+#[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+use druid::{ExtEventSink, Target};
+#[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+use git2::Repository;
+
+/// The number of characters of author names shown.
+const AUTHOR_CHARS: usize = 12;
+
+/// Sent with the commits of the lines of a file once they are known, empty
+/// if it is not in a git repository.
+pub const BLAME_READY: Selector<(PathBuf, Arc<Vec<Option<LineCommit>>>)> =
+    Selector::new("lyranos.blame-ready");
+
+/// How the commit that last changed each line is shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum BlameMode {
+    /// Not at all.
+    Off,
+    /// In the status bar while hovering the line number.
+    Hover,
+    /// In a column of the gutter.
+    Column,
+}
+
+impl FromStr for BlameMode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(BlameMode::Off),
+            "hover" => Ok(BlameMode::Hover),
+            "column" => Ok(BlameMode::Column),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The commit a line was last changed in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    not(all(feature = "blame", not(target_arch = "wasm32"))),
+    allow(dead_code)
+)]
+pub struct LineCommit {
+    /// The first seven digits of the commit hash.
+    pub short_hash: String,
+    /// The name of the author of the commit.
+    pub author: String,
+}
+
+impl LineCommit {
+    /// The text shown for the line, e.g. `1a2b3c4 Ada Lovelace`, with long
+    /// author names cut short.
+    pub fn label(&self) -> String {
+        let author: String = self.author.chars().take(AUTHOR_CHARS).collect();
+        format!("{} {}", self.short_hash, author)
+    }
+}
+
+/// The commit that last changed each line of the file at `path` as saved,
+/// `None` for lines changed since. Returns `None` if the file is not
+/// committed to a git repository.
+#[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+pub fn blame_file(path: &Path) -> Option<Vec<Option<LineCommit>>> {
+    let path = path.canonicalize().ok()?;
+    let repository = Repository::discover(path.parent()?).ok()?;
+    let workdir = repository.workdir()?.canonicalize().ok()?;
+    let relative = path.strip_prefix(workdir).ok()?;
+    let contents = fs::read(&path).ok()?;
+    let committed = repository.blame_file(relative, None).ok()?;
+    let blame = committed.blame_buffer(&contents).ok()?;
+    let mut commits = vec![None; contents.split(|&b| b == b'\n').count()];
+    for hunk in blame.iter() {
+        let id = hunk.final_commit_id();
+        if id.is_zero() {
+            continue;
+        }
+        let commit = LineCommit {
+            short_hash: id.to_string()[..7].to_owned(),
+            author: hunk.final_signature().name().unwrap_or("").to_owned(),
+        };
+        // Hunks count lines from one.
+        let lines = commits
+            .iter_mut()
+            .skip(hunk.final_start_line().saturating_sub(1))
+            .take(hunk.lines_in_hunk());
+        for line in lines {
+            *line = Some(commit.clone());
+        }
+    }
+    Some(commits)
+}
+
+/// Find the commits of the lines of the file at `path` on a thread of its
+/// own, sending them with [`BLAME_READY`] when done.
+#[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+pub fn spawn(path: PathBuf, sink: ExtEventSink) {
+    thread::spawn(move || {
+        let commits = blame_file(&path).unwrap_or_default();
+        let _ = sink.submit_command(BLAME_READY, (path, Arc::new(commits)), Target::Auto);
+    });
+}
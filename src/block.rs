@@ -0,0 +1,165 @@
+//! Rectangular selections of the same columns across several lines.
+
+use std::ops::Range;
+
+use crate::text;
+
+/// A selection of the same columns on consecutive lines, made by dragging
+/// with Alt held, for editing tabular text. Positions are zero-based lines
+/// and columns, counted in characters, so a tab counts as one column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSelection {
+    /// The line and column the selection was started at.
+    pub anchor: (usize, usize),
+    /// The line and column the selection extends to.
+    pub active: (usize, usize),
+}
+
+impl BlockSelection {
+    pub fn new(anchor: (usize, usize), active: (usize, usize)) -> Self {
+        BlockSelection { anchor, active }
+    }
+
+    /// The selected lines.
+    pub fn lines(&self) -> Range<usize> {
+        self.anchor.0.min(self.active.0)..self.anchor.0.max(self.active.0) + 1
+    }
+
+    /// The selected columns, empty if the selection is a column of carets.
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.1.min(self.active.1)..self.anchor.1.max(self.active.1)
+    }
+
+    /// The byte range of the selected columns on each selected line that
+    /// exists, clamped to the end of the line.
+    pub fn ranges(&self, text: &str) -> Vec<Range<usize>> {
+        let columns = self.columns();
+        let lines = self.lines();
+        text::line_starts(text)
+            .skip(lines.start)
+            .take(lines.len())
+            .map(|start| {
+                let line = line_content(text, start);
+                let offset = |column| column_offset(line, column) + start;
+                offset(columns.start)..offset(columns.end)
+            })
+            .collect()
+    }
+
+    /// The edits replacing the selected columns of every line with `typed`,
+    /// and the column of carets after it. Lines ending before the columns
+    /// are padded with spaces, so that `typed` lines up on all of them.
+    pub fn insert(&self, text: &str, typed: &str) -> (Vec<(Range<usize>, String)>, Self) {
+        let columns = self.columns();
+        let edits = self
+            .ranges(text)
+            .into_iter()
+            .map(|range| {
+                let width = line_content(text, text::line_start(text, range.start))
+                    .chars()
+                    .count();
+                let padding = " ".repeat(columns.start.saturating_sub(width));
+                (range, padding + typed)
+            })
+            .collect();
+        let column = columns.start + typed.chars().count();
+        (edits, self.with_column(column))
+    }
+
+    /// The edits made by Backspace, removing the selected columns of every
+    /// line, or the character before them if the selection is a column of
+    /// carets, and the column of carets after them.
+    pub fn delete_backward(&self, text: &str) -> (Vec<(Range<usize>, String)>, Self) {
+        let columns = self.columns();
+        if !columns.is_empty() {
+            let edits = self
+                .ranges(text)
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| (range, String::new()))
+                .collect();
+            return (edits, self.with_column(columns.start));
+        }
+        if columns.start == 0 {
+            return (Vec::new(), *self);
+        }
+        let edits = self
+            .ranges(text)
+            .into_iter()
+            .filter_map(|range| {
+                let start = text::line_start(text, range.start);
+                let line = line_content(text, start);
+                // Lines ending before the carets have nothing to delete.
+                if line.chars().count() < columns.start {
+                    return None;
+                }
+                let before = text[start..range.start].chars().next_back()?;
+                Some((range.start - before.len_utf8()..range.start, String::new()))
+            })
+            .collect();
+        (edits, self.with_column(columns.start - 1))
+    }
+
+    /// A column of carets at `column` on the same lines.
+    fn with_column(&self, column: usize) -> Self {
+        BlockSelection::new((self.anchor.0, column), (self.active.0, column))
+    }
+}
+
+/// The line starting at `start`, without its line break.
+fn line_content(text: &str, start: usize) -> &str {
+    let line = &text[start..text::line_end(text, start)];
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// The offset of the character at `column` in `line`, or its length if it
+/// is shorter.
+fn column_offset(line: &str, column: usize) -> usize {
+    line.char_indices()
+        .nth(column)
+        .map_or(line.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `text` with `edits` applied, which do not overlap.
+    fn apply(text: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+        let mut text = text.to_owned();
+        edits.sort_by_key(|(range, _)| range.start);
+        for (range, new) in edits.into_iter().rev() {
+            text.replace_range(range, &new);
+        }
+        text
+    }
+
+    #[test]
+    fn ranges() {
+        let block = BlockSelection::new((1, 2), (0, 1));
+        assert_eq!(block.ranges("é€x\r\nab"), [2..5, 9..10]);
+        assert_eq!(BlockSelection::new((0, 0), (5, 0)).ranges("a\nb").len(), 2);
+    }
+
+    #[test]
+    fn insert() {
+        let table = "a,b\nccc,d\n\nee,f\n";
+        let (edits, carets) = BlockSelection::new((0, 1), (3, 1)).insert(table, "|");
+        let typed = apply(table, edits);
+        assert_eq!(typed, "a|,b\nc|cc,d\n |\ne|e,f\n");
+        assert_eq!(carets, BlockSelection::new((0, 2), (3, 2)));
+        // Typing over selected columns replaces them.
+        let (edits, _) = BlockSelection::new((0, 0), (1, 2)).insert("abc\nde\n", "x");
+        assert_eq!(apply("abc\nde\n", edits), "xc\nx\n");
+    }
+
+    #[test]
+    fn delete_backward() {
+        let text = "a|,b\nc|cc,d\n |\ne|e,f\n";
+        let (edits, carets) = BlockSelection::new((0, 2), (3, 2)).delete_backward(text);
+        assert_eq!(apply(text, edits), "a,b\nccc,d\n \nee,f\n");
+        assert_eq!(carets, BlockSelection::new((0, 1), (3, 1)));
+        let (edits, _) = BlockSelection::new((0, 0), (1, 0)).delete_backward(text);
+        assert!(edits.is_empty());
+    }
+}
@@ -0,0 +1,96 @@
+//! Bookmarked lines of a document.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use druid::Data;
+
+use crate::text;
+
+/// The zero-based indices of the bookmarked lines, kept on the same lines
+/// of text as the document is edited.
+#[derive(Debug, Clone, Default, Data)]
+pub struct Bookmarks {
+    lines: Arc<BTreeSet<usize>>,
+}
+
+impl Bookmarks {
+    /// Returns `true` if `line` is bookmarked.
+    pub fn contains(&self, line: usize) -> bool {
+        self.lines.contains(&line)
+    }
+
+    /// Add a bookmark to `line` or remove the one it has.
+    pub fn toggle(&mut self, line: usize) {
+        let lines = Arc::make_mut(&mut self.lines);
+        if !lines.remove(&line) {
+            lines.insert(line);
+        }
+    }
+
+    /// The first bookmarked line after `line`, wrapping around at the end.
+    pub fn next(&self, line: usize) -> Option<usize> {
+        let mut after = self.lines.range(line + 1..);
+        after.next().or_else(|| self.lines.iter().next()).copied()
+    }
+
+    /// The last bookmarked line before `line`, wrapping around at the start.
+    pub fn prev(&self, line: usize) -> Option<usize> {
+        let mut before = self.lines.range(..line);
+        before
+            .next_back()
+            .or_else(|| self.lines.iter().next_back())
+            .copied()
+    }
+
+    /// Move the bookmarks along with their lines when `range` of `text` is
+    /// replaced by `new`. Text inserted at the start of a line moves the line
+    /// down, and the bookmarks of lines joined with the one before them move
+    /// to that line.
+    pub fn edited(&mut self, text: &str, range: Range<usize>, new: &str) {
+        let removed = text[range.clone()].matches('\n').count();
+        let inserted = new.matches('\n').count();
+        if self.lines.is_empty() || (removed == 0 && inserted == 0) {
+            return;
+        }
+        let start_line = text::line_index(text, range.start);
+        let first_moved = if removed == 0 && range.start == text::line_start(text, range.start) {
+            start_line
+        } else {
+            start_line + 1
+        };
+        self.lines = Arc::new(
+            self.lines
+                .iter()
+                .map(|&line| {
+                    if line < first_moved {
+                        line
+                    } else if line <= start_line + removed && line > start_line {
+                        start_line
+                    } else {
+                        line - removed + inserted
+                    }
+                })
+                .collect(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_line_above() {
+        let text = "a\nb\nc\n";
+        let mut bookmarks = Bookmarks::default();
+        bookmarks.toggle(1);
+        bookmarks.edited(text, 2..2, "x\n");
+        assert!(!bookmarks.contains(1));
+        assert!(bookmarks.contains(2));
+        // Lines inserted below the bookmark leave it in place.
+        bookmarks.edited("a\nx\nb\nc\n", 6..6, "y\n");
+        assert!(bookmarks.contains(2));
+    }
+}
@@ -0,0 +1,129 @@
+//! A bar showing the definitions enclosing the caret.
+
+use std::time::Duration;
+
+use druid::piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::{FontFamily, TimerToken};
+
+use crate::editor;
+use crate::AppState;
+
+/// How long the caret has to rest before the breadcrumbs are updated.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+const FONT_SIZE: f64 = 13.0;
+const PADDING: f64 = 4.0;
+const SEPARATOR: &str = " > ";
+
+/// The chain of definitions enclosing the caret, e.g.
+/// `scope_test > do_nonlocal`. Clicking a definition moves the caret to it.
+pub struct Breadcrumbs {
+    /// The name and start offset of each enclosing definition.
+    crumbs: Vec<(String, usize)>,
+    /// The laid out crumbs and separators, with the offset to jump to when
+    /// clicked (`None` for separators) and their x position.
+    layouts: Vec<(PietTextLayout, Option<usize>, f64)>,
+    timer: TimerToken,
+}
+
+impl Breadcrumbs {
+    pub fn new() -> Self {
+        Breadcrumbs {
+            crumbs: Vec::new(),
+            layouts: Vec::new(),
+            timer: TimerToken::INVALID,
+        }
+    }
+}
+
+impl Widget<AppState> for Breadcrumbs {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
+        match event {
+            Event::Timer(token) if *token == self.timer => {
+                let crumbs = data.code.scope_chain(data.selection.active);
+                if crumbs != self.crumbs {
+                    self.crumbs = crumbs;
+                    ctx.request_layout();
+                    ctx.request_paint();
+                }
+            }
+            Event::MouseDown(mouse) => {
+                let clicked = self
+                    .layouts
+                    .iter()
+                    .find(|(layout, _, x)| {
+                        mouse.pos.x >= *x && mouse.pos.x < x + layout.size().width
+                    })
+                    .and_then(|&(_, offset, _)| offset);
+                if let Some(offset) = clicked {
+                    ctx.submit_command(editor::SET_CARET.with(offset));
+                    ctx.set_handled();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        _env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.crumbs = data.code.scope_chain(data.selection.active);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        if !old_data.selection.same(&data.selection) || !old_data.code.same(&data.code) {
+            self.timer = ctx.request_timer(DEBOUNCE);
+        }
+        if !old_data.theme.same(&data.theme) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &AppState,
+        env: &Env,
+    ) -> Size {
+        let color = env.get(druid::theme::TEXT_COLOR);
+        let mut build = |text: String| {
+            ctx.text()
+                .new_text_layout(text)
+                .font(FontFamily::SYSTEM_UI, FONT_SIZE)
+                .text_color(color.clone())
+                .build()
+                .unwrap()
+        };
+        self.layouts.clear();
+        let mut x = PADDING;
+        for (i, (name, offset)) in self.crumbs.iter().enumerate() {
+            if i > 0 {
+                let separator = build(SEPARATOR.to_owned());
+                let width = separator.size().width;
+                self.layouts.push((separator, None, x));
+                x += width;
+            }
+            let crumb = build(name.clone());
+            let width = crumb.size().width;
+            self.layouts.push((crumb, Some(*offset), x));
+            x += width;
+        }
+        // Keep the height when there are no crumbs, so the editor below does
+        // not jump as the caret moves in and out of definitions.
+        let height = build(SEPARATOR.to_owned()).size().height + 2.0 * PADDING;
+        bc.constrain(Size::new(bc.max().width, height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {
+        for (layout, _, x) in &self.layouts {
+            ctx.draw_text(layout, (*x, PADDING));
+        }
+    }
+}
@@ -1,39 +1,165 @@
 //! Rich text with style spans.
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fs;
 use std::ops::Range;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 use druid::piet::{
     PietTextLayoutBuilder, TextAttribute, TextLayoutBuilder, TextStorage as PietTextStorage,
 };
-use druid::text::{EditableText, EnvUpdateCtx, Link, StringCursor, TextStorage};
-use druid::{Color, Data, Env};
-use tree_sitter::{InputEdit, Parser, Point, Query, QueryCursor, Tree};
+use druid::text::{EditableText, EnvUpdateCtx, Link, Selection, StringCursor, TextStorage};
+use druid::{Color, Data, Env, Key};
+use tree_sitter::{InputEdit, Node, Parser, Point, Query, QueryCursor, Tree};
+
+use crate::bookmarks::Bookmarks;
+use crate::config;
+use crate::conflicts::{self, ConflictRegion};
+use crate::history::{self, Edit, History};
+use crate::language::Language;
+use crate::lines::LineIndex;
+use crate::scopes::{self, VariableScope};
+use crate::settings::Settings;
+use crate::text;
+
+/// Whether invisible and control characters are marked.
+pub const SHOW_INVISIBLES: Key<bool> = Key::new("lyranos.show-invisibles");
+/// The color of the invisible and control characters marked when
+/// [`SHOW_INVISIBLES`] is set.
+pub const INVISIBLE_COLOR: Key<Color> = Key::new("lyranos.invisible-color");
+/// The color of captures without a color mapping.
+pub const UNMAPPED_CAPTURE_COLOR: Key<Color> = Key::new("lyranos.unmapped-capture-color");
+/// Whether captures without a color mapping are underlined, to help grammar
+/// and theme authors spot them.
+pub const DEBUG_UNMAPPED_CAPTURES: Key<bool> = Key::new("lyranos.debug-unmapped-captures");
+/// Whether Python variables are colored by the scope they resolve to.
+pub const COLOR_VARIABLE_SCOPES: Key<bool> = Key::new("lyranos.color-variable-scopes");
+/// The color of variables bound in the function they are used in.
+pub const VARIABLE_LOCAL_COLOR: Key<Color> = Key::new("lyranos.variable-local-color");
+/// The color of variables bound in an enclosing function.
+pub const VARIABLE_ENCLOSING_COLOR: Key<Color> = Key::new("lyranos.variable-enclosing-color");
+/// The color of variables bound in the module, and of builtins.
+pub const VARIABLE_GLOBAL_COLOR: Key<Color> = Key::new("lyranos.variable-global-color");
 
 /// Text with optional style spans.
 #[derive(Clone)]
 pub struct CodeText {
     pub buffer: String,
-    attrs: Arc<Vec<TextAttribute>>,
+    language: Language,
     links: Arc<[Link]>,
+    /// `None` if the document is shown as plain text.
+    syntax: Option<Syntax>,
+    tree: Option<Tree>,
+    /// The highlighted ranges of the document, sorted by start, kept up to
+    /// date incrementally as the document is edited.
+    spans: Arc<Vec<Span>>,
+    /// The escape sequences in string literals, found again after every
+    /// parse.
+    escapes: Arc<Vec<Range<usize>>>,
+    /// The starts of the lines indented inconsistently with their block,
+    /// found again after every parse.
+    misindented: Arc<Vec<usize>>,
+    /// The range touched by edits since the spans were last updated.
+    dirty: Option<Range<usize>>,
+    /// Shared by all clones, which are versions of the same document.
+    history: Rc<RefCell<History>>,
+    /// The bookmarked lines, moved along with the text by every edit.
+    pub bookmarks: Bookmarks,
+    /// The line starts, kept up to date by every edit.
+    lines: Arc<LineIndex>,
+    /// The lines whose indentation mixes tabs and spaces, found again after
+    /// every edit.
+    mixed_indentation: Arc<Vec<usize>>,
+    /// The unresolved merge conflicts, found again after every edit.
+    conflicts: Arc<Vec<ConflictRegion>>,
+    /// The variable references with the scope they resolve to, resolved
+    /// again after every parse.
+    scopes: Arc<Vec<(Range<usize>, VariableScope)>>,
+    /// How long the last parse took.
+    parse_duration: Duration,
+    /// How long recomputing the spans took after the last parse.
+    highlight_duration: Duration,
+}
+
+/// The parser and highlight query of a syntax highlighted document.
+#[derive(Clone)]
+struct Syntax {
+    /// The attribute of each capture, indexed by capture index and resolved
+    /// once at construction so layout does no capture name matching. `None`
+    /// for captures without a color mapping.
+    attrs: Arc<[Option<TextAttribute>]>,
+    /// The index of the `escape` capture, whose attribute escape sequences
+    /// are given.
+    escape: Option<u32>,
     parser: Rc<Mutex<Parser>>,
     query: Rc<Query>,
-    tree: Option<Tree>,
+    /// The query capturing foldable regions, `None` if the language has none
+    /// or it failed to compile.
+    fold_query: Option<Rc<Query>>,
+    /// Why the user's queries or the bundled folds query failed to compile,
+    /// one message each.
+    query_errors: Rc<[String]>,
 }
 
-impl CodeText {
-    /// Create a new `CodeText` object with the provided text.
-    pub fn new(buffer: String) -> Self {
+/// A highlighted range of the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Span {
+    range: Range<usize>,
+    /// The index of the capture in the highlight query.
+    capture: u32,
+}
+
+/// A named definition in the document, as listed in an outline.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The byte range of the whole definition.
+    pub range: Range<usize>,
+    /// The number of definitions enclosing this one.
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+}
+
+impl Syntax {
+    fn new(language: Language) -> Self {
         let mut parser = Parser::new();
-        let language = tree_sitter_python::language();
-        parser.set_language(language).unwrap();
-        let query_source = tree_sitter_python::HIGHLIGHT_QUERY;
-        let query = Query::new(language, query_source).unwrap();
+        let grammar = language.grammar();
+        parser.set_language(grammar).unwrap();
+        let mut query_errors = Vec::new();
+        let mut load_user_query = |kind: &str| match user_query(language, kind)? {
+            Ok(query) => Some(query),
+            Err(err) => {
+                query_errors.push(err);
+                None
+            }
+        };
+        let query = load_user_query("").unwrap_or_else(|| {
+            Query::new(grammar, language.highlight_query()).expect("invalid bundled query")
+        });
+        let fold_query = load_user_query("folds").or_else(|| {
+            let source = language.fold_query()?;
+            Query::new(grammar, source)
+                .map_err(|err| {
+                    query_errors.push(format!(
+                        "Invalid bundled {} folds query: line {}: {}",
+                        language.name(),
+                        err.row + 1,
+                        err.message
+                    ))
+                })
+                .ok()
+        });
         // Colors from One Monokai theme: https://github.com/azemoh/vscode-one-monokai
-        let attrs = query
+        let attrs: Vec<_> = query
             .capture_names()
             .iter()
             .map(|name| match name.as_str() {
@@ -54,21 +180,259 @@ impl CodeText {
                 "embedded" => color("#c678dd"),
                 "operator" => color("#e06c75"),
                 "keyword" => color("#e06c75"),
-                _ => TextAttribute::Underline(true),
+                _ => None,
             })
             .collect();
+        Syntax {
+            attrs: attrs.into(),
+            escape: query.capture_index_for_name("escape"),
+            parser: Rc::new(Mutex::new(parser)),
+            query: Rc::new(query),
+            fold_query: fold_query.map(Rc::new),
+            query_errors: query_errors.into(),
+        }
+    }
+
+    /// The spans of all captures within `range` of `tree`, clipped to it.
+    fn spans(&self, tree: &Tree, text: &str, range: Range<usize>) -> Vec<Span> {
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(range.clone());
+        let captures = cursor.captures(&self.query, tree.root_node(), text.as_bytes());
+        let mut spans = Vec::new();
+        let mut last_node_id: usize = 0;
+        for (query_match, capture_id) in captures {
+            let capture = query_match.captures[capture_id];
+            if capture.node.id() == last_node_id {
+                continue;
+            }
+            last_node_id = capture.node.id();
+            let start = capture.node.start_byte().max(range.start);
+            let end = capture.node.end_byte().min(range.end);
+            if start < end {
+                spans.push(Span {
+                    range: start..end,
+                    capture: capture.index,
+                });
+            }
+        }
+        spans
+    }
+}
+
+impl CodeText {
+    /// Create a new `CodeText` object with the provided text.
+    pub fn new(buffer: String) -> Self {
+        Self::with_syntax(
+            buffer,
+            Language::Python,
+            Some(Syntax::new(Language::Python)),
+            history::DEFAULT_LIMIT,
+        )
+    }
+
+    /// Create a new `CodeText` object in `language`, highlighted unless the
+    /// text exceeds the configured size limit.
+    pub fn with_language(buffer: String, language: Language, settings: &Settings) -> Self {
+        let syntax = if buffer.len() > settings.highlight_size_limit {
+            None
+        } else {
+            Some(Syntax::new(language))
+        };
+        Self::with_syntax(buffer, language, syntax, settings.undo_limit)
+    }
+
+    fn with_syntax(
+        buffer: String,
+        language: Language,
+        syntax: Option<Syntax>,
+        undo_limit: usize,
+    ) -> Self {
+        let lines = Arc::new(LineIndex::new(&buffer));
         let mut code_text = CodeText {
             buffer,
-            attrs: Arc::new(attrs),
+            language,
             links: Arc::new([]),
-            parser: Rc::new(Mutex::new(parser)),
-            query: Rc::new(query),
+            syntax,
             tree: None,
+            spans: Arc::new(Vec::new()),
+            escapes: Arc::new(Vec::new()),
+            misindented: Arc::new(Vec::new()),
+            dirty: None,
+            history: Rc::new(RefCell::new(History::new(undo_limit))),
+            bookmarks: Bookmarks::default(),
+            lines,
+            mixed_indentation: Arc::new(Vec::new()),
+            conflicts: Arc::new(Vec::new()),
+            scopes: Arc::new(Vec::new()),
+            parse_duration: Duration::ZERO,
+            highlight_duration: Duration::ZERO,
         };
         code_text.update();
         code_text
     }
 
+    /// The language of the text.
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// The number of lines, including the empty line after a final line
+    /// break.
+    pub fn line_count(&self) -> usize {
+        self.lines.line_count()
+    }
+
+    /// The range of the zero-based `line`, without its line break.
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        self.lines.line_range(&self.buffer, line)
+    }
+
+    /// The zero-based line containing `offset`.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.lines.line_of(offset)
+    }
+
+    /// The zero-based line and UTF-16 column of `offset`, the position of
+    /// the language server protocol.
+    pub fn utf16_position(&self, offset: usize) -> (usize, usize) {
+        self.lines.utf16_position(&self.buffer, offset)
+    }
+
+    /// The offset of a zero-based line and UTF-16 column, clamped to the
+    /// line and the text.
+    pub fn utf16_offset(&self, position: (usize, usize)) -> usize {
+        self.lines.utf16_offset(&self.buffer, position)
+    }
+
+    /// The zero-based lines whose indentation mixes tabs and spaces, as
+    /// found by [`text::mixed_indentation_lines`].
+    pub fn mixed_indentation_lines(&self) -> &[usize] {
+        &self.mixed_indentation
+    }
+
+    /// Returns `true` if the text is syntax highlighted.
+    pub fn is_highlighted(&self) -> bool {
+        self.syntax.is_some()
+    }
+
+    /// Why the queries of the language failed to compile when the document
+    /// was created, one message each.
+    pub fn query_errors(&self) -> &[String] {
+        self.syntax
+            .as_ref()
+            .map_or(&[][..], |syntax| &syntax.query_errors)
+    }
+
+    /// The name of the highlight capture applied at `offset`, e.g. `keyword`.
+    /// Where captures overlap, the one rendered on top is returned.
+    pub fn capture_name_at(&self, offset: usize) -> Option<&str> {
+        let syntax = self.syntax.as_ref()?;
+        // Spans are sorted by start and later spans are applied on top.
+        let span = self
+            .spans
+            .iter()
+            .take_while(|span| span.range.start <= offset)
+            .filter(|span| span.range.contains(&offset))
+            .last()?;
+        Some(&syntax.query.capture_names()[span.capture as usize])
+    }
+
+    /// The highlighted ranges of the whole document with the names of their
+    /// captures, computed afresh from the syntax tree rather than taken from
+    /// the spans kept up to date across edits. Empty if the document is not
+    /// highlighted.
+    pub fn highlight_spans(&self) -> Vec<(Range<usize>, &str)> {
+        let (syntax, tree) = match (&self.syntax, &self.tree) {
+            (Some(syntax), Some(tree)) => (syntax, tree),
+            _ => return Vec::new(),
+        };
+        let names = syntax.query.capture_names();
+        syntax
+            .spans(tree, &self.buffer, 0..self.buffer.len())
+            .into_iter()
+            .map(|span| (span.range, names[span.capture as usize].as_str()))
+            .collect()
+    }
+
+    /// The highlighted ranges whose capture has a background color in
+    /// `backgrounds`, which maps capture names to colors, with that color.
+    pub fn capture_backgrounds<'a>(
+        &self,
+        backgrounds: &'a [(String, Color)],
+    ) -> Vec<(Range<usize>, &'a Color)> {
+        let syntax = match self.syntax {
+            Some(ref syntax) if !backgrounds.is_empty() => syntax,
+            _ => return Vec::new(),
+        };
+        // Resolved once per capture rather than once per span.
+        let colors: Vec<Option<&Color>> = syntax
+            .query
+            .capture_names()
+            .iter()
+            .map(|name| {
+                backgrounds
+                    .iter()
+                    .find(|(capture, _)| capture == name)
+                    .map(|(_, color)| color)
+            })
+            .collect();
+        self.spans
+            .iter()
+            .filter_map(|span| Some((span.range.clone(), colors[span.capture as usize]?)))
+            .collect()
+    }
+
+    /// The names of the highlight captures without a color mapping.
+    pub fn unmapped_captures(&self) -> Vec<&str> {
+        match self.syntax {
+            Some(ref syntax) => syntax
+                .query
+                .capture_names()
+                .iter()
+                .zip(syntax.attrs.iter())
+                .filter(|(_, attr)| attr.is_none())
+                .map(|(name, _)| name.as_str())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The highlight captures without a color mapping that are applied to the
+    /// document, sorted by name, with the number of times each is applied.
+    pub fn applied_unmapped_captures(&self) -> Vec<(&str, usize)> {
+        let syntax = match self.syntax {
+            Some(ref syntax) => syntax,
+            None => return Vec::new(),
+        };
+        let mut counts = vec![0; syntax.attrs.len()];
+        for span in self.spans.iter() {
+            counts[span.capture as usize] += 1;
+        }
+        let mut unmapped: Vec<_> = syntax
+            .query
+            .capture_names()
+            .iter()
+            .zip(syntax.attrs.iter())
+            .zip(counts)
+            .filter(|((_, attr), count)| attr.is_none() && *count > 0)
+            .map(|((name, _), count)| (name.as_str(), count))
+            .collect();
+        unmapped.sort_unstable_by_key(|&(name, _)| name);
+        unmapped
+    }
+
+    /// How long parsing the document took after the last edit, zero if it is
+    /// not highlighted.
+    pub fn last_parse_duration(&self) -> Duration {
+        self.parse_duration
+    }
+
+    /// How long highlighting the edited part of the document took after the
+    /// last edit, zero if it is not highlighted.
+    pub fn last_highlight_duration(&self) -> Duration {
+        self.highlight_duration
+    }
+
     /// The length of the buffer, in utf8 code units.
     pub fn len(&self) -> usize {
         self.buffer.len()
@@ -79,27 +443,1001 @@ impl CodeText {
         self.buffer.is_empty()
     }
 
+    /// All function and class definitions in document order.
+    pub fn symbols(&self) -> Vec<Symbol> {
+        let mut symbols = Vec::new();
+        if let Some(ref tree) = self.tree {
+            self.collect_symbols(tree.root_node(), 0, &mut symbols);
+        }
+        symbols
+    }
+
+    fn collect_symbols(&self, node: Node, depth: usize, symbols: &mut Vec<Symbol>) {
+        let mut cursor = node.walk();
+        for child in node.children(&mut cursor) {
+            let kind = definition_kind(child);
+            let name = child
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(self.buffer.as_bytes()).ok());
+            match (kind, name) {
+                (Some(kind), Some(name)) => {
+                    symbols.push(Symbol {
+                        name: name.to_owned(),
+                        kind,
+                        range: child.byte_range(),
+                        depth,
+                    });
+                    self.collect_symbols(child, depth + 1, symbols);
+                }
+                _ => self.collect_symbols(child, depth, symbols),
+            }
+        }
+    }
+
+    /// The names and starts of the definitions enclosing `offset`, outermost
+    /// first.
+    pub fn scope_chain(&self, offset: usize) -> Vec<(String, usize)> {
+        let mut chain = Vec::new();
+        let mut node = match self.tree {
+            Some(ref tree) => tree.root_node().descendant_for_byte_range(offset, offset),
+            None => None,
+        };
+        while let Some(current) = node {
+            let name = current
+                .child_by_field_name("name")
+                .and_then(|name| name.utf8_text(self.buffer.as_bytes()).ok());
+            if let (Some(_), Some(name)) = (definition_kind(current), name) {
+                chain.push((name.to_owned(), current.start_byte()));
+            }
+            node = current.parent();
+        }
+        chain.reverse();
+        chain
+    }
+
+    /// The function or class definition named by the identifier at `offset`.
+    /// Names are not resolved through scopes, so the first definition with
+    /// the name is returned.
+    pub fn definition_of(&self, offset: usize) -> Option<Symbol> {
+        let name = &self.buffer[self.identifier_at(offset)?];
+        self.symbols()
+            .into_iter()
+            .find(|symbol| symbol.name == name)
+    }
+
+    /// The start of the first top-level definition after `offset`.
+    pub fn next_definition(&self, offset: usize) -> Option<usize> {
+        self.symbols()
+            .into_iter()
+            .filter(|symbol| symbol.depth == 0)
+            .map(|symbol| symbol.range.start)
+            .find(|&start| start > offset)
+    }
+
+    /// The start of the last top-level definition before `offset`.
+    pub fn prev_definition(&self, offset: usize) -> Option<usize> {
+        self.symbols()
+            .into_iter()
+            .filter(|symbol| symbol.depth == 0)
+            .map(|symbol| symbol.range.start)
+            .filter(|&start| start < offset)
+            .last()
+    }
+
+    /// The range of the word at `offset`, which is empty if there is none.
+    pub fn word_range(&self, offset: usize) -> Range<usize> {
+        let is_word_char = |c| self.language.is_word_char(c);
+        let start = self.buffer[..offset]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word_char(c))
+            .last()
+            .map_or(offset, |(i, _)| i);
+        let end = self.buffer[offset..]
+            .find(|c| !is_word_char(c))
+            .map_or(self.buffer.len(), |i| offset + i);
+        start..end
+    }
+
+    /// The whole lines of the block containing `offset`, e.g. the body of the
+    /// function or loop the caret is in. Falls back to the surrounding lines
+    /// of the same indentation if the text is not parsed.
+    pub fn current_block_range(&self, offset: usize) -> Range<usize> {
+        let block = self.tree.as_ref().and_then(|tree| {
+            let mut node = tree.root_node().descendant_for_byte_range(offset, offset)?;
+            while !matches!(node.kind(), "block" | "statement_block") {
+                node = node.parent()?;
+            }
+            // Use the statements, as a JavaScript block includes its braces.
+            let mut cursor = node.walk();
+            let statements: Vec<Node> = node.named_children(&mut cursor).collect();
+            Some(statements.first()?.start_byte()..statements.last()?.end_byte())
+        });
+        match block {
+            Some(block) => {
+                text::line_start(&self.buffer, block.start)..text::line_end(&self.buffer, block.end)
+            }
+            None => text::indented_block(&self.buffer, offset),
+        }
+    }
+
+    /// The range of the compound statement containing `offset`, from its
+    /// keyword, or its first decorator, to the end of its body. A caret in
+    /// the indentation of a line belongs to the statement the line starts.
+    pub fn block_range_at(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let offset = self.skip_indentation(offset);
+        let mut node = root.descendant_for_byte_range(offset, offset)?;
+        while !is_compound_statement(node.kind()) {
+            node = node.parent()?;
+        }
+        if let Some(parent) = node
+            .parent()
+            .filter(|parent| parent.kind() == "decorated_definition")
+        {
+            node = parent;
+        }
+        Some(node.byte_range())
+    }
+
+    /// The start of the next or previous statement in the block of the
+    /// statement at `offset`, skipping comments. Returns `None` at the last
+    /// or first statement of the block.
+    pub fn sibling_statement(&self, offset: usize, forward: bool) -> Option<usize> {
+        let root = self.tree.as_ref()?.root_node();
+        let offset = self.skip_indentation(offset);
+        let mut node = root.descendant_for_byte_range(offset, offset)?;
+        if is_statement_list(node.kind()) {
+            // Between statements, e.g. on a blank line.
+            let mut cursor = node.walk();
+            let statements: Vec<Node> = node
+                .named_children(&mut cursor)
+                .filter(|child| child.kind() != "comment")
+                .collect();
+            let target = if forward {
+                statements
+                    .iter()
+                    .find(|statement| statement.start_byte() > offset)
+            } else {
+                statements
+                    .iter()
+                    .rev()
+                    .find(|statement| statement.end_byte() <= offset)
+            };
+            return target.map(|statement| statement.start_byte());
+        }
+        while !is_statement_list(node.parent()?.kind()) {
+            node = node.parent()?;
+        }
+        loop {
+            node = if forward {
+                node.next_named_sibling()?
+            } else {
+                node.prev_named_sibling()?
+            };
+            if node.kind() != "comment" {
+                return Some(node.start_byte());
+            }
+        }
+    }
+
+    /// The range of the innermost node of the given kind, e.g.
+    /// `function_definition`, containing `offset`.
+    pub fn enclosing_of_kind(&self, offset: usize, kind: &str) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let offset = self.skip_indentation(offset);
+        let mut node = root.descendant_for_byte_range(offset, offset)?;
+        while node.kind() != kind {
+            node = node.parent()?;
+        }
+        Some(node.byte_range())
+    }
+
+    /// The range of the statement containing `offset`, or of the innermost
+    /// statement of the block containing it if it is between statements.
+    pub fn enclosing_statement(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let offset = self.skip_indentation(offset);
+        let mut node = root.descendant_for_byte_range(offset, offset)?;
+        while !is_statement_list(node.parent()?.kind()) {
+            node = node.parent()?;
+        }
+        Some(node.byte_range())
+    }
+
+    /// `offset`, or the start of the code on its line if it is in the
+    /// indentation.
+    fn skip_indentation(&self, offset: usize) -> usize {
+        let line_start = text::line_start(&self.buffer, offset);
+        if self.buffer[line_start..offset].trim().is_empty() {
+            let line = &self.buffer[line_start..text::line_end(&self.buffer, offset)];
+            line_start + text::indentation(line).len()
+        } else {
+            offset
+        }
+    }
+
+    /// The range of the outermost string or the comment containing `offset`,
+    /// including quotes and string prefixes.
+    pub fn enclosing_string_or_comment(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let mut node = root.descendant_for_byte_range(offset, offset);
+        let mut enclosing = None;
+        while let Some(current) = node {
+            if matches!(current.kind(), "string" | "template_string" | "comment") {
+                enclosing = Some(current.byte_range());
+            }
+            node = current.parent();
+        }
+        enclosing
+    }
+
+    /// The lines of the block of line comments around `offset`: the
+    /// consecutive lines holding nothing but a comment, with the same
+    /// indentation as the line of `offset`. The range ends before the line
+    /// break of the last line.
+    pub fn comment_block(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let prefix = self.language.line_comment().trim_end();
+        // The indentation of `line` if it is a comment alone.
+        let comment_line = |line: usize| {
+            let range = self.line_range(line)?;
+            let content = &self.buffer[range.clone()];
+            let indent = text::indentation(content);
+            let start = range.start + indent.len();
+            if !content[indent.len()..].starts_with(prefix) {
+                return None;
+            }
+            let node = root.descendant_for_byte_range(start, start + 1)?;
+            (node.kind() == "comment" && node.start_byte() == start).then(|| indent)
+        };
+        let line = self.line_of(offset);
+        let indent = comment_line(line)?;
+        let mut first = line;
+        while first > 0 && comment_line(first - 1) == Some(indent) {
+            first -= 1;
+        }
+        let mut last = line;
+        while comment_line(last + 1) == Some(indent) {
+            last += 1;
+        }
+        Some(self.line_range(first)?.start..self.line_range(last)?.end)
+    }
+
+    /// The range of the innermost string literal containing `offset`.
+    pub fn string_at(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let mut node = root.descendant_for_byte_range(offset, offset);
+        while let Some(current) = node {
+            if matches!(current.kind(), "string" | "template_string") {
+                return Some(current.byte_range());
+            }
+            node = current.parent();
+        }
+        None
+    }
+
+    /// The ranges of the string literals implicitly concatenated with the one
+    /// at `offset`, e.g. `'a' 'b'` in Python, in document order. Returns
+    /// `None` if there is no such concatenation or comments are part of it.
+    pub fn concatenated_strings_at(&self, offset: usize) -> Option<Vec<Range<usize>>> {
+        let root = self.tree.as_ref()?.root_node();
+        let mut node = root.descendant_for_byte_range(offset, offset);
+        while let Some(current) = node {
+            if current.kind() == "concatenated_string" {
+                let mut cursor = current.walk();
+                let parts: Vec<Node> = current.named_children(&mut cursor).collect();
+                return parts
+                    .iter()
+                    .all(|part| part.kind() == "string")
+                    .then(|| parts.iter().map(|part| part.byte_range()).collect());
+            }
+            node = current.parent();
+        }
+        None
+    }
+
+    /// The range of the identifier at `offset`, which may also be at its end.
+    pub fn identifier_at(&self, offset: usize) -> Option<Range<usize>> {
+        let root = self.tree.as_ref()?.root_node();
+        let offsets = std::iter::once(offset).chain(offset.checked_sub(1));
+        offsets
+            .filter_map(|offset| root.descendant_for_byte_range(offset, offset))
+            .find(|node| node.kind().ends_with("identifier"))
+            .map(|node| node.byte_range())
+    }
+
+    /// The ranges of all identifiers named `name`, leaving out occurrences
+    /// of the name in strings and comments.
+    pub fn identifier_occurrences(&self, name: &str) -> Vec<Range<usize>> {
+        let source = self.buffer.as_bytes();
+        self.nodes_where(|node| {
+            node.kind().ends_with("identifier") && node.utf8_text(source) == Ok(name)
+        })
+    }
+
+    /// The byte ranges of all nodes of the given kind, e.g.
+    /// `function_definition`, in document order.
+    pub fn nodes_of_kind(&self, kind: &str) -> Vec<Range<usize>> {
+        self.nodes_where(|node| node.kind() == kind)
+    }
+
+    /// The byte ranges of the Python import statements none of whose names are
+    /// used, in document order. Empty for languages other than Python.
+    ///
+    /// This is a heuristic without semantic analysis: a name counts as used
+    /// if an identifier of that name occurs outside of imports anywhere in the
+    /// document, whatever its scope. Names that are only referenced in
+    /// strings, e.g. in `__all__`, and modules imported for their side effects
+    /// or to re-export names are reported as unused. Statements importing
+    /// several names are only reported if none of them is used, and wildcard
+    /// imports never are.
+    pub fn unused_imports(&self) -> Vec<Range<usize>> {
+        let root = match self.tree {
+            Some(ref tree) if self.language == Language::Python => tree.root_node(),
+            _ => return Vec::new(),
+        };
+        let source = self.buffer.as_bytes();
+        let imports = self.nodes_where(|node| {
+            matches!(node.kind(), "import_statement" | "import_from_statement")
+        });
+        let in_import = |name: &Range<usize>| {
+            imports
+                .iter()
+                .any(|import| import.start <= name.start && name.end <= import.end)
+        };
+        let is_used = |name: &str| {
+            self.identifier_occurrences(name)
+                .iter()
+                .any(|occurrence| !in_import(occurrence))
+        };
+        imports
+            .iter()
+            .filter(|import| {
+                root.descendant_for_byte_range(import.start, import.end)
+                    .and_then(|statement| imported_names(statement, source))
+                    .map_or(false, |names| !names.into_iter().any(is_used))
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The whole lines of the Python import statements at the top of the
+    /// document, after any docstring and comments, up to the first statement
+    /// that is not an import. `None` if the document does not start with
+    /// imports or is not Python.
+    pub fn import_block(&self) -> Option<Range<usize>> {
+        let root = match self.tree {
+            Some(ref tree) if self.language == Language::Python => tree.root_node(),
+            _ => return None,
+        };
+        let is_import = |node: &Node| {
+            matches!(
+                node.kind(),
+                "import_statement" | "import_from_statement" | "future_import_statement"
+            )
+        };
+        let mut cursor = root.walk();
+        let mut children = root.named_children(&mut cursor).skip_while(|child| {
+            child.kind() == "comment"
+                || (child.kind() == "expression_statement"
+                    && child.named_child(0).map_or(false, |c| c.kind() == "string"))
+        });
+        let first = children.next().filter(is_import)?;
+        let last = children
+            .take_while(|child| is_import(child) || child.kind() == "comment")
+            .filter(is_import)
+            .last()
+            .unwrap_or(first);
+        let start = text::line_start(&self.buffer, first.start_byte());
+        Some(start..text::line_end(&self.buffer, last.end_byte()))
+    }
+
+    /// The byte ranges of the escape sequences in string literals, leaving out
+    /// raw strings, whose backslashes are literal.
+    pub fn string_escapes(&self) -> &[Range<usize>] {
+        &self.escapes
+    }
+
+    /// Find the escape sequences in the string literals of the tree.
+    fn find_string_escapes(&self) -> Vec<Range<usize>> {
+        let literals = self.nodes_where(|node| matches!(node.kind(), "string" | "template_string"));
+        let mut escapes = Vec::new();
+        for literal in literals {
+            let source = &self.buffer[literal.clone()];
+            let quote = source.find(|c| matches!(c, '"' | '\'' | '`'));
+            if source[..quote.unwrap_or(0)].contains(|c| c == 'r' || c == 'R') {
+                continue;
+            }
+            let start = literal.start;
+            escapes.extend(
+                text::escape_sequences(source)
+                    .into_iter()
+                    .map(|escape| start + escape.start..start + escape.end),
+            );
+        }
+        escapes
+    }
+
+    /// The byte ranges of the number literals within `range`, or containing
+    /// it if it is empty, in document order.
+    pub fn number_literals(&self, range: Range<usize>) -> Vec<Range<usize>> {
+        self.nodes_where(|node| {
+            let within = if range.is_empty() {
+                node.start_byte() <= range.start && range.end <= node.end_byte()
+            } else {
+                range.start <= node.start_byte() && node.end_byte() <= range.end
+            };
+            within && matches!(node.kind(), "integer" | "float" | "number")
+        })
+    }
+
+    /// The byte ranges of all nodes matching `predicate`, in document order.
+    fn nodes_where(&self, predicate: impl Fn(Node) -> bool) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if let Some(ref tree) = self.tree {
+            collect_nodes(tree.root_node(), &predicate, &mut ranges);
+        }
+        ranges
+    }
+
+    /// The next occurrence of the text in `range` after it, wrapping around at
+    /// the end of the document. Returns `None` if the text occurs only once.
+    pub fn next_occurrence(&self, range: Range<usize>) -> Option<Range<usize>> {
+        let needle = self.buffer.get(range.clone())?;
+        if needle.is_empty() {
+            return None;
+        }
+        let start = self.buffer[range.end..]
+            .find(needle)
+            .map(|i| range.end + i)
+            .or_else(|| self.buffer[..range.start].find(needle))?;
+        Some(start..start + needle.len())
+    }
+
+    /// The byte ranges of all string literals and comments, in document order.
+    fn string_and_comment_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges = Vec::new();
+        if let Some(ref tree) = self.tree {
+            collect_strings_and_comments(tree.root_node(), &mut ranges);
+        }
+        ranges
+    }
+
+    /// The offsets of all matching pairs of brackets, ignoring brackets inside
+    /// of string literals and comments.
+    pub fn bracket_pairs(&self) -> Vec<(usize, usize)> {
+        let excluded = self.string_and_comment_ranges();
+        let mut excluded = excluded.iter().peekable();
+        let mut open = Vec::new();
+        let mut pairs = Vec::new();
+        for (i, b) in self.buffer.bytes().enumerate() {
+            while excluded.peek().map_or(false, |range| range.end <= i) {
+                excluded.next();
+            }
+            if excluded.peek().map_or(false, |range| range.contains(&i)) {
+                continue;
+            }
+            match b {
+                b'(' | b'[' | b'{' => open.push((i, b)),
+                b')' | b']' | b'}' => {
+                    let opening = match b {
+                        b')' => b'(',
+                        b']' => b'[',
+                        _ => b'{',
+                    };
+                    // Skip unmatched closing brackets.
+                    if open.last().map_or(false, |&(_, o)| o == opening) {
+                        let (start, _) = open.pop().unwrap();
+                        pairs.push((start, i));
+                    }
+                }
+                _ => {}
+            }
+        }
+        pairs.sort_unstable();
+        pairs
+    }
+
+    /// The offset of the bracket matching the one at `offset`.
+    pub fn matching_bracket(&self, offset: usize) -> Option<usize> {
+        self.bracket_pairs().into_iter().find_map(|(open, close)| {
+            if open == offset {
+                Some(close)
+            } else if close == offset {
+                Some(open)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// The variable references in the document with the scope they resolve
+    /// to. Empty for languages other than Python.
+    pub fn variable_scopes(&self) -> &[(Range<usize>, VariableScope)] {
+        &self.scopes
+    }
+
+    /// The byte ranges of all syntax errors in the document, i.e. `ERROR`
+    /// nodes and nodes inserted by the parser to recover from an error.
+    pub fn syntax_errors(&self) -> Vec<Range<usize>> {
+        let mut errors = Vec::new();
+        if let Some(ref tree) = self.tree {
+            if tree.root_node().has_error() {
+                collect_errors(tree.root_node(), &mut errors);
+            }
+        }
+        errors
+    }
+
+    /// The byte ranges of the regions that can be folded, spanning more than
+    /// one line, in document order. Empty if the language has no folds query.
+    pub fn fold_ranges(&self) -> Vec<Range<usize>> {
+        let (query, tree) = match (&self.syntax, &self.tree) {
+            (Some(syntax), Some(tree)) => match syntax.fold_query {
+                Some(ref query) => (query, tree),
+                None => return Vec::new(),
+            },
+            _ => return Vec::new(),
+        };
+        let mut cursor = QueryCursor::new();
+        let mut ranges: Vec<_> = cursor
+            .captures(query, tree.root_node(), self.buffer.as_bytes())
+            .map(|(query_match, index)| query_match.captures[index].node)
+            .filter(|node| node.start_position().row < node.end_position().row)
+            .map(|node| node.byte_range())
+            .collect();
+        ranges.dedup();
+        ranges
+    }
+
+    /// The starts of the lines whose indentation does not match the other
+    /// statements of the block they are in, which Python rejects with an
+    /// `IndentationError`. Empty for languages other than Python.
+    pub fn inconsistent_indentation(&self) -> &[usize] {
+        &self.misindented
+    }
+
+    /// Find the lines that [`CodeText::inconsistent_indentation`] returns.
+    fn find_inconsistent_indentation(&self) -> Vec<usize> {
+        let mut lines = Vec::new();
+        if let Some(ref tree) = self.tree {
+            if self.language == Language::Python {
+                collect_misindented(tree.root_node(), &self.buffer, &mut lines);
+            }
+        }
+        lines.sort_unstable();
+        lines.dedup();
+        lines
+    }
+
+    /// Apply several non-overlapping edits at once, reparsing only once.
+    ///
+    /// All ranges refer to the text before any of the edits are applied.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any two ranges overlap.
+    pub fn edit_batch(&mut self, mut edits: Vec<(Range<usize>, String)>) {
+        edits.sort_by_key(|(range, _)| range.start);
+        for pair in edits.windows(2) {
+            assert!(
+                pair[0].0.end <= pair[1].0.start,
+                "overlapping edits {:?} and {:?}",
+                pair[0].0,
+                pair[1].0
+            );
+        }
+        // Going from right to left keeps the ranges of the remaining edits
+        // valid.
+        let mut applied = Vec::with_capacity(edits.len());
+        for (range, new) in edits.into_iter().rev() {
+            self.edit_tree(range.clone(), &new);
+            self.move_positions(range.clone(), &new);
+            let old = self.buffer[range.clone()].to_owned();
+            self.buffer.replace_range(range.clone(), &new);
+            applied.push(Edit {
+                start: range.start,
+                old,
+                new,
+            });
+        }
+        self.history.borrow_mut().record_group(applied);
+        self.update();
+    }
+
+    /// Replace the whole text with `new` as a single undo step, parsing it
+    /// from scratch, and return where `caret` ends up: in place within the
+    /// unchanged start and end of the text, and otherwise on the same line
+    /// and column as far as they exist.
+    pub fn replace_all(&mut self, new: String, caret: usize) -> usize {
+        let (prefix, suffix) = text::common_affixes(&self.buffer, &new);
+        let range = prefix..self.buffer.len() - suffix;
+        let caret = caret.min(self.buffer.len());
+        if range.is_empty() && new.len() == self.buffer.len() {
+            return caret;
+        }
+        let new_caret = if caret <= range.start {
+            caret
+        } else if caret >= range.end {
+            caret - range.end + new.len() - suffix
+        } else {
+            let line = self.line_of(caret);
+            let column = caret - text::line_start(&self.buffer, caret);
+            text::line_column_offset(&new, line, column)
+        };
+        let replacement = new[prefix..new.len() - suffix].to_owned();
+        self.move_positions(range.clone(), &replacement);
+        let edit = Edit {
+            start: range.start,
+            old: self.buffer[range].to_owned(),
+            new: replacement,
+        };
+        self.buffer = new;
+        {
+            let mut history = self.history.borrow_mut();
+            history.record_group(vec![edit]);
+            history.record_selections(Selection::caret(caret), Selection::caret(new_caret));
+        }
+        self.reparse_full();
+        new_caret
+    }
+
+    /// The unresolved merge conflicts in the document.
+    pub fn conflict_regions(&self) -> &[ConflictRegion] {
+        &self.conflicts
+    }
+
+    /// Discard the syntax tree and parse and highlight the document from
+    /// scratch, recovering from any error in reusing the tree across edits.
+    pub fn reparse_full(&mut self) {
+        self.tree = None;
+        self.dirty = None;
+        self.update();
+    }
+
+    /// The number of edits made to the document so far, which changes with
+    /// every edit except undo and redo.
+    pub fn revision(&self) -> usize {
+        self.history.borrow().revision()
+    }
+
+    /// Record the selections before and after the edits made since the
+    /// selections were last recorded, to restore them on undo and redo.
+    pub fn record_selections(&self, before: Selection, after: Selection) {
+        self.history.borrow_mut().record_selections(before, after);
+    }
+
+    /// Undo the last group of edits, returning the selection from before it,
+    /// or a caret after the last undone edit if it is unknown.
+    pub fn undo(&mut self) -> Option<Selection> {
+        let (edits, before) = self.history.borrow_mut().undo()?;
+        let caret = self.apply_history(edits);
+        Some(before.unwrap_or_else(|| Selection::caret(caret)))
+    }
+
+    /// Redo the last undone group of edits, returning the selection after
+    /// it, or a caret after the last redone edit if it is unknown.
+    pub fn redo(&mut self) -> Option<Selection> {
+        let (edits, after) = self.history.borrow_mut().redo()?;
+        let caret = self.apply_history(edits);
+        Some(after.unwrap_or_else(|| Selection::caret(caret)))
+    }
+
+    /// Apply edits from the history without recording them, returning the
+    /// offset after the last one.
+    fn apply_history(&mut self, edits: Vec<Edit>) -> usize {
+        let mut caret = 0;
+        for edit in edits {
+            let range = edit.start..edit.start + edit.old.len();
+            self.edit_tree(range.clone(), &edit.new);
+            self.move_positions(range.clone(), &edit.new);
+            self.buffer.replace_range(range, &edit.new);
+            caret = edit.start + edit.new.len();
+        }
+        self.update();
+        caret
+    }
+
+    /// Move the bookmarks and line starts along with an edit replacing
+    /// `range` with `new`, before it is applied to the buffer.
+    fn move_positions(&mut self, range: Range<usize>, new: &str) {
+        self.bookmarks.edited(&self.buffer, range.clone(), new);
+        Arc::make_mut(&mut self.lines).edited(range, new);
+    }
+
+    /// Edit the previous tree for better performance when reparsing.
+    /// Not sure if this is 100% correct.
+    fn edit_tree(&mut self, range: Range<usize>, new: &str) {
+        if let Some(ref mut tree) = self.tree {
+            let buffer = self.buffer.as_bytes();
+            let start_position = advance_point(Point::new(0, 0), &buffer[..range.start]);
+            let old_end_position = advance_point(start_position, &buffer[range.clone()]);
+            let new_end_position = advance_point(start_position, new.as_bytes());
+            tree.edit(&InputEdit {
+                start_byte: range.start,
+                old_end_byte: range.end,
+                new_end_byte: range.start + new.len(),
+                start_position,
+                old_end_position,
+                new_end_position,
+            });
+            self.shift_spans(range, new.len());
+        }
+    }
+
+    /// Move the spans after an edit replacing `range` with `new_len` bytes and
+    /// mark the edited range as dirty. Spans overlapping the edit are only
+    /// approximated, they are recomputed by the next update.
+    fn shift_spans(&mut self, range: Range<usize>, new_len: usize) {
+        let new_end = range.start + new_len;
+        let shift = |offset: usize| {
+            if offset >= range.end {
+                offset - range.end + new_end
+            } else {
+                offset.min(new_end)
+            }
+        };
+        for span in Arc::make_mut(&mut self.spans) {
+            span.range = shift(span.range.start)..shift(span.range.end);
+        }
+        self.dirty = Some(match self.dirty.take() {
+            Some(dirty) => shift(dirty.start).min(range.start)..shift(dirty.end).max(new_end),
+            None => range.start..new_end,
+        });
+    }
+
+    /// Recompute what is derived from the text after it changed.
     fn update(&mut self) {
-        let mut parser = self.parser.lock().unwrap();
+        self.mixed_indentation = Arc::new(text::mixed_indentation_lines(&self.buffer));
+        self.conflicts = Arc::new(conflicts::conflict_regions(&self.buffer));
+        self.update_highlights();
+    }
+
+    /// Reparse the text and highlight the parts of it that changed.
+    fn update_highlights(&mut self) {
+        let syntax = match self.syntax {
+            Some(ref syntax) => syntax,
+            None => return,
+        };
+        let mut parser = syntax.parser.lock().unwrap();
         let start = Instant::now();
-        self.tree = parser.parse(&self.buffer, self.tree.as_ref());
-        eprintln!(
-            "Parsed document in {} us",
-            Instant::now().duration_since(start).as_micros()
-        );
+        let mut old_tree = self.tree.take();
+        self.tree = parser.parse(&self.buffer, old_tree.as_ref());
+        // A tree extending past the text was edited incorrectly, so the text
+        // is parsed again without it.
+        let end = self.tree.as_ref().map(|tree| tree.root_node().end_byte());
+        if old_tree.is_some() && end.map_or(false, |end| end > self.buffer.len()) {
+            old_tree = None;
+            self.tree = parser.parse(&self.buffer, None);
+        }
+        self.parse_duration = start.elapsed();
+
+        let tree = match self.tree {
+            Some(ref tree) => tree,
+            None => return,
+        };
+        let start = Instant::now();
+        self.scopes = Arc::new(match self.language {
+            Language::Python => scopes::resolve_python(tree.root_node(), self.buffer.as_bytes()),
+            _ => Vec::new(),
+        });
+        // Only the edited text and the ranges whose syntactic structure
+        // changed need to be highlighted again, e.g. opening a triple-quoted
+        // string changes everything up to the next quote. Whole lines are
+        // recomputed so that captures spanning the boundary stay consistent.
+        let invalid = match (old_tree, self.dirty.take()) {
+            (Some(old_tree), Some(dirty)) => {
+                let changed = old_tree.changed_ranges(tree).fold(dirty, |changed, range| {
+                    changed.start.min(range.start_byte)..changed.end.max(range.end_byte)
+                });
+                let end = changed.end.min(self.buffer.len());
+                text::line_start(&self.buffer, changed.start)..text::line_end(&self.buffer, end)
+            }
+            _ => 0..self.buffer.len(),
+        };
+        let mut spans = Vec::with_capacity(self.spans.len());
+        for span in self.spans.iter() {
+            let Span { range, capture } = span.clone();
+            if range.start < invalid.start {
+                spans.push(Span {
+                    range: range.start..range.end.min(invalid.start),
+                    capture,
+                });
+            }
+            if range.end > invalid.end {
+                spans.push(Span {
+                    range: range.start.max(invalid.end)..range.end,
+                    capture,
+                });
+            }
+        }
+        spans.extend(syntax.spans(tree, &self.buffer, invalid.clone()));
+        // Stable, so enclosing spans stay in front of the spans they contain
+        // and are overridden by them.
+        spans.sort_by_key(|span| span.range.start);
+        self.spans = Arc::new(spans);
+        // Not every grammar captures all escape sequences, so they are found
+        // in the strings themselves.
+        self.escapes = Arc::new(self.find_string_escapes());
+        self.misindented = Arc::new(self.find_inconsistent_indentation());
+        self.highlight_duration = start.elapsed();
+    }
+}
+
+/// The user's query of the given `kind` for `language`, loaded from the
+/// config directory if there is one, or why it failed to compile. The
+/// highlight query, whose kind is empty, is loaded from e.g.
+/// `queries/python.scm` and other kinds from e.g. `queries/python.folds.scm`.
+/// Queries are read whenever a document is opened, so changes apply to the
+/// next document.
+fn user_query(language: Language, kind: &str) -> Option<Result<Query, String>> {
+    let file_name = if kind.is_empty() {
+        format!("{}.scm", language.name())
+    } else {
+        format!("{}.{}.scm", language.name(), kind)
+    };
+    let path = config::config_dir()?.join("queries").join(file_name);
+    let source = fs::read_to_string(&path).ok()?;
+    let query = Query::new(language.grammar(), &source).map_err(|err| {
+        format!(
+            "Failed to load {}: line {}: {}",
+            path.display(),
+            err.row + 1,
+            err.message
+        )
+    });
+    Some(query)
+}
+
+/// The position reached by moving from `point` over `text`.
+fn advance_point(mut point: Point, text: &[u8]) -> Point {
+    match text.iter().rposition(|&b| b == b'\n') {
+        Some(last_newline) => {
+            point.row += text.iter().filter(|&&b| b == b'\n').count();
+            point.column = text.len() - last_newline - 1;
+        }
+        None => point.column += text.len(),
+    }
+    point
+}
+
+/// The kind of symbol `node` defines, if any.
+fn definition_kind(node: Node) -> Option<SymbolKind> {
+    match node.kind() {
+        "function_definition" | "function_declaration" | "method_definition" => {
+            Some(SymbolKind::Function)
+        }
+        "class_definition" | "class_declaration" => Some(SymbolKind::Class),
+        _ => None,
+    }
+}
+
+fn collect_strings_and_comments(node: Node, ranges: &mut Vec<Range<usize>>) {
+    if matches!(node.kind(), "string" | "template_string" | "comment") {
+        ranges.push(node.byte_range());
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_strings_and_comments(child, ranges);
+    }
+}
+
+/// The names bound by the Python import statement `statement`, `None` for
+/// wildcard imports. `import a.b` binds `a`, `import a as b` binds `b`.
+fn imported_names<'a>(statement: Node, source: &'a [u8]) -> Option<Vec<&'a str>> {
+    let mut cursor = statement.walk();
+    if statement
+        .named_children(&mut cursor)
+        .any(|child| child.kind() == "wildcard_import")
+    {
+        return None;
+    }
+    let names = statement
+        .children_by_field_name("name", &mut cursor)
+        .filter_map(|name| match name.kind() {
+            "aliased_import" => name.child_by_field_name("alias"),
+            _ => name.named_child(0),
+        })
+        .filter_map(|name| name.utf8_text(source).ok())
+        .collect();
+    Some(names)
+}
+
+fn collect_nodes(node: Node, predicate: &impl Fn(Node) -> bool, ranges: &mut Vec<Range<usize>>) {
+    if predicate(node) {
+        ranges.push(node.byte_range());
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_nodes(child, predicate, ranges);
+    }
+}
+
+/// Collect the starts of the lines under `node` that begin a statement at a
+/// column other than that of the first statement of their block. Comments
+/// may be indented freely and statements continuing a line are skipped.
+fn collect_misindented(node: Node, buffer: &str, lines: &mut Vec<usize>) {
+    let mut cursor = node.walk();
+    let statements: Vec<Node> = node
+        .named_children(&mut cursor)
+        .filter(|child| child.kind() != "comment")
+        .collect();
+    if matches!(node.kind(), "module" | "block") {
+        let starts_line = |statement: &Node| {
+            let start = text::line_start(buffer, statement.start_byte());
+            let line = &buffer[start..statement.start_byte()];
+            line.trim().is_empty().then(|| start)
+        };
+        let mut level = None;
+        for statement in &statements {
+            if let Some(start) = starts_line(statement) {
+                let column = statement.start_position().column;
+                match level {
+                    None => level = Some(column),
+                    Some(level) if level != column => lines.push(start),
+                    _ => {}
+                }
+            }
+        }
+    }
+    for child in statements {
+        collect_misindented(child, buffer, lines);
     }
 }
 
-const fn color(hex: &str) -> TextAttribute {
+/// Returns `true` for the kinds of nodes whose children are statements.
+fn is_statement_list(kind: &str) -> bool {
+    matches!(
+        kind,
+        "module" | "block" | "program" | "statement_block" | "class_body"
+    )
+}
+
+/// Returns `true` for nodes of statements with a body, e.g. `if_statement`.
+fn is_compound_statement(kind: &str) -> bool {
+    matches!(
+        kind,
+        "function_definition"
+            | "function_declaration"
+            | "method_definition"
+            | "class_definition"
+            | "class_declaration"
+            | "if_statement"
+            | "for_statement"
+            | "for_in_statement"
+            | "while_statement"
+            | "do_statement"
+            | "try_statement"
+            | "with_statement"
+            | "switch_statement"
+    )
+}
+
+fn collect_errors(node: Node, errors: &mut Vec<Range<usize>>) {
+    if node.is_error() || node.is_missing() {
+        errors.push(node.byte_range());
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.has_error() {
+            collect_errors(child, errors);
+        }
+    }
+}
+
+const fn color(hex: &str) -> Option<TextAttribute> {
     match Color::from_hex_str(hex) {
-        Ok(color) => TextAttribute::TextColor(color),
-        _ => TextAttribute::Underline(true),
+        Ok(color) => Some(TextAttribute::TextColor(color)),
+        _ => None,
     }
 }
 
 impl Data for CodeText {
     fn same(&self, other: &Self) -> bool {
-        self.buffer == other.buffer
+        self.buffer == other.buffer && self.bookmarks.same(&other.bookmarks)
     }
 }
 
@@ -113,35 +1451,63 @@ impl TextStorage for CodeText {
     fn add_attributes(
         &self,
         mut builder: PietTextLayoutBuilder,
-        _env: &Env,
+        env: &Env,
     ) -> PietTextLayoutBuilder {
         // Compute new attributes based on detected captures.
-        if let Some(ref tree) = self.tree {
-            let start = Instant::now();
-            let mut cursor = QueryCursor::new();
-            let captures = cursor.captures(&self.query, tree.root_node(), self.buffer.as_bytes());
-            let mut last_node_id: usize = 0;
-            for (query_match, capture_id) in captures {
-                let capture = query_match.captures[capture_id];
-                if capture.node.id() == last_node_id {
-                    continue;
+        if let Some(ref syntax) = self.syntax {
+            let unmapped_color = env.get(UNMAPPED_CAPTURE_COLOR);
+            let debug_unmapped = env.get(DEBUG_UNMAPPED_CAPTURES);
+            for span in self.spans.iter() {
+                let attr = match syntax.attrs[span.capture as usize] {
+                    Some(ref attr) => clone_attr(attr),
+                    None if debug_unmapped => TextAttribute::Underline(true),
+                    None => TextAttribute::TextColor(unmapped_color.clone()),
+                };
+                builder = builder.range_attribute(span.range.clone(), attr);
+            }
+            let escape = syntax
+                .escape
+                .and_then(|i| syntax.attrs[i as usize].as_ref());
+            if let Some(escape) = escape {
+                for range in self.escapes.iter() {
+                    builder = builder.range_attribute(range.clone(), clone_attr(escape));
                 }
-                last_node_id = capture.node.id();
-                let range = capture.node.byte_range();
+            }
+        }
+        if env.get(COLOR_VARIABLE_SCOPES) {
+            let local = env.get(VARIABLE_LOCAL_COLOR);
+            let enclosing = env.get(VARIABLE_ENCLOSING_COLOR);
+            let global = env.get(VARIABLE_GLOBAL_COLOR);
+            for (range, scope) in self.scopes.iter() {
+                let color = match scope {
+                    VariableScope::Local => &local,
+                    VariableScope::Enclosing => &enclosing,
+                    VariableScope::Global => &global,
+                };
                 builder =
-                    builder.range_attribute(range, clone_attr(&self.attrs[capture.index as usize]));
+                    builder.range_attribute(range.clone(), TextAttribute::TextColor(color.clone()));
+            }
+        }
+        if env.get(SHOW_INVISIBLES) {
+            let invisible_color = env.get(INVISIBLE_COLOR);
+            for (range, _) in text::suspicious_characters(&self.buffer) {
+                builder = builder
+                    .range_attribute(range.clone(), TextAttribute::Underline(true))
+                    .range_attribute(range, TextAttribute::TextColor(invisible_color.clone()));
             }
-            eprintln!(
-                "Updated attributes in {} us",
-                Instant::now().duration_since(start).as_micros()
-            );
         }
         builder
     }
 
-    fn env_update(&self, _ctx: &EnvUpdateCtx) -> bool {
-        // self.attrs.env_update(ctx)
-        false
+    fn env_update(&self, ctx: &EnvUpdateCtx) -> bool {
+        ctx.env_key_changed(&SHOW_INVISIBLES)
+            || ctx.env_key_changed(&INVISIBLE_COLOR)
+            || ctx.env_key_changed(&UNMAPPED_CAPTURE_COLOR)
+            || ctx.env_key_changed(&DEBUG_UNMAPPED_CAPTURES)
+            || ctx.env_key_changed(&COLOR_VARIABLE_SCOPES)
+            || ctx.env_key_changed(&VARIABLE_LOCAL_COLOR)
+            || ctx.env_key_changed(&VARIABLE_ENCLOSING_COLOR)
+            || ctx.env_key_changed(&VARIABLE_GLOBAL_COLOR)
     }
 
     fn links(&self) -> &[Link] {
@@ -149,6 +1515,8 @@ impl TextStorage for CodeText {
     }
 }
 
+/// `TextAttribute` does not implement `Clone`. None of its variants own heap
+/// data (font families are `Arc`-backed), so this never allocates.
 fn clone_attr(attr: &TextAttribute) -> TextAttribute {
     match attr {
         TextAttribute::FontFamily(family) => TextAttribute::FontFamily(family.clone()),
@@ -168,55 +1536,13 @@ impl EditableText for CodeText {
 
     fn edit(&mut self, range: Range<usize>, new: impl Into<String>) {
         let new: String = new.into();
-        // Edit previous tree for better performance.
-        // Not sure if this is 100% correct.
-        if let Some(ref mut tree) = self.tree {
-            let start = Instant::now();
-            let buffer = self.buffer.as_bytes();
-            let mut line = 10;
-            let mut col = 10;
-            for i in 0..range.start {
-                if buffer[i] == '\n' as u8 {
-                    line += 1;
-                    col = 0;
-                } else {
-                    col += 1
-                }
-            }
-            let start_position = Point::new(line, col);
-            for i in range.start..range.end {
-                if buffer[i] == '\n' as u8 {
-                    line += 1;
-                    col = 0;
-                } else {
-                    col += 1
-                }
-            }
-            let old_end_position = Point::new(line, col);
-            line = start_position.row;
-            col = start_position.column;
-            for c in new.as_bytes() {
-                if *c == '\n' as u8 {
-                    line += 1;
-                    col = 0;
-                } else {
-                    col += 1
-                }
-            }
-            let new_end_position = Point::new(line, col);
-            tree.edit(&InputEdit {
-                start_byte: range.start,
-                old_end_byte: range.end,
-                new_end_byte: range.start + new.len(),
-                start_position,
-                old_end_position,
-                new_end_position,
-            });
-            eprintln!(
-                "Edited tree in {} us",
-                Instant::now().duration_since(start).as_micros()
-            );
-        }
+        self.history.borrow_mut().record(Edit {
+            start: range.start,
+            old: self.buffer[range.clone()].to_owned(),
+            new: new.clone(),
+        });
+        self.edit_tree(range.clone(), &new);
+        self.move_positions(range.clone(), &new);
         self.buffer.edit(range, new);
         self.update();
     }
@@ -230,11 +1556,29 @@ impl EditableText for CodeText {
     }
 
     fn prev_word_offset(&self, offset: usize) -> Option<usize> {
-        self.buffer.prev_word_offset(offset)
+        // Skip separators, then the word before them.
+        let mut passed_word = false;
+        for (i, c) in self.buffer.get(..offset)?.char_indices().rev() {
+            if self.language.is_word_char(c) {
+                passed_word = true;
+            } else if passed_word {
+                return Some(i + c.len_utf8());
+            }
+        }
+        Some(0)
     }
 
     fn next_word_offset(&self, offset: usize) -> Option<usize> {
-        self.buffer.next_word_offset(offset)
+        // Skip separators, then the word after them.
+        let mut passed_word = false;
+        for (i, c) in self.buffer.get(offset..)?.char_indices() {
+            if self.language.is_word_char(c) {
+                passed_word = true;
+            } else if passed_word {
+                return Some(offset + i);
+            }
+        }
+        Some(self.buffer.len())
     }
 
     fn prev_grapheme_offset(&self, offset: usize) -> Option<usize> {
@@ -269,3 +1613,302 @@ impl EditableText for CodeText {
         Self::new(s.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The sample document shown when started with `--demo`.
+    const SAMPLE: &str = include_str!("sample.py");
+
+    /// The scope of the variable reference starting where `needle` is found
+    /// in `code`.
+    fn scope_at(code: &CodeText, needle: &str) -> Option<VariableScope> {
+        let start = code.buffer.find(needle).unwrap();
+        let scopes = code.variable_scopes();
+        let found = scopes.iter().find(|(range, _)| range.start == start);
+        found.map(|&(_, scope)| scope)
+    }
+
+    #[test]
+    fn variable_scopes() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        assert_eq!(
+            scope_at(&code, "spam = 'local spam'"),
+            Some(VariableScope::Local)
+        );
+        assert_eq!(
+            scope_at(&code, "spam = 'nonlocal spam'"),
+            Some(VariableScope::Enclosing)
+        );
+        assert_eq!(
+            scope_at(&code, "spam = 'global spam'"),
+            Some(VariableScope::Global)
+        );
+        // `scope_test`'s own `spam`, which `do_local` does not change.
+        assert_eq!(
+            scope_at(&code, "spam)\n    do_nonlocal()"),
+            Some(VariableScope::Local)
+        );
+    }
+
+    #[test]
+    fn undo_typed_word() {
+        let mut code = CodeText::new("print()\n".to_owned());
+        for (i, c) in "say hello".char_indices() {
+            code.edit(6 + i..6 + i, c.to_string());
+        }
+        assert_eq!(code.buffer, "print(say hello)\n");
+        code.undo();
+        assert!(!code.buffer.contains("hello"));
+        assert_eq!(code.buffer, "print(say )\n");
+    }
+
+    #[test]
+    fn string_escapes() {
+        let source = "s = 'a\\n\\t\\x41\\u00e9\\N{EN DASH}\\101\\\\'\nr = r'\\n'\n";
+        let code = CodeText::new(source.to_owned());
+        let escapes: Vec<&str> = code
+            .string_escapes()
+            .iter()
+            .map(|range| &source[range.clone()])
+            .collect();
+        assert_eq!(
+            escapes,
+            [
+                "\\n",
+                "\\t",
+                "\\x41",
+                "\\u00e9",
+                "\\N{EN DASH}",
+                "\\101",
+                "\\\\"
+            ]
+        );
+    }
+
+    #[test]
+    fn unused_imports() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let unused: Vec<&str> = code
+            .unused_imports()
+            .into_iter()
+            .map(|range| &SAMPLE[range])
+            .collect();
+        assert_eq!(unused, ["import antigravity"]);
+        let used =
+            CodeText::new("import os\nfrom sys import argv, path\nos.exit(argv)\n".to_owned());
+        assert!(used.unused_imports().is_empty());
+    }
+
+    #[test]
+    fn sibling_statement() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        // Within `scope_test`, from `do_local()` to the `print` after it and
+        // back.
+        let call = SAMPLE.find("    do_local()").unwrap() + 4;
+        let print = SAMPLE[call..].find("print").unwrap() + call;
+        assert_eq!(code.sibling_statement(call, true), Some(print));
+        assert_eq!(code.sibling_statement(print, false), Some(call));
+    }
+
+    #[test]
+    fn symbols() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let symbols = code.symbols();
+        let names: Vec<(&str, usize)> = symbols
+            .iter()
+            .map(|symbol| (symbol.name.as_str(), symbol.depth))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                ("scope_test", 0),
+                ("do_local", 1),
+                ("do_nonlocal", 1),
+                ("do_global", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn enclosing_of_kind() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let inside = SAMPLE.find("'local spam'").unwrap();
+        let do_local = SAMPLE.find("def do_local").unwrap();
+        let selected = code.enclosing_of_kind(inside, "function_definition");
+        assert_eq!(selected.map(|range| range.start), Some(do_local));
+        assert_eq!(code.enclosing_of_kind(0, "function_definition"), None);
+    }
+
+    #[test]
+    fn reparse_matches_fresh_parse() {
+        // Edits spanning lines and multi-byte characters, and a string quote
+        // changing the structure of everything after it.
+        let mut edited = CodeText::new(SAMPLE.to_owned());
+        edited.edit(0..0, "\"\"\"é\r\n");
+        edited.edit(3..6, "ü\n\n");
+        edited.edit(0..3, "");
+        edited.edit(SAMPLE.len() / 2..SAMPLE.len() / 2, "\"\"\"\n");
+        edited.reparse_full();
+        let fresh = CodeText::new(edited.buffer.clone());
+        for offset in 0..=fresh.len() {
+            if fresh.buffer.is_char_boundary(offset) {
+                assert_eq!(
+                    edited.capture_name_at(offset),
+                    fresh.capture_name_at(offset),
+                    "at {}",
+                    offset
+                );
+            }
+        }
+        assert_eq!(edited.syntax_errors(), fresh.syntax_errors());
+    }
+
+    #[test]
+    fn undo_replace_all() {
+        let mut code = CodeText::new(SAMPLE.to_owned());
+        let caret = SAMPLE.find("def do_local").unwrap();
+        code.replace_all(SAMPLE.replace("spam", "eggs"), caret);
+        assert!(!code.buffer.contains("spam"));
+        assert_eq!(code.undo(), Some(Selection::caret(caret)));
+        assert_eq!(code.buffer, SAMPLE);
+    }
+
+    #[test]
+    fn edit_batch() {
+        let table = "a,b\nccc,d\n\nee,f\n";
+        let mut code = CodeText::new(table.to_owned());
+        let edits = vec![(10..10, " |".to_owned()), (1..1, "|".to_owned())];
+        code.edit_batch(edits);
+        assert_eq!(code.buffer, "a|,b\nccc,d\n |\nee,f\n");
+        assert_eq!(code.line_range(2), Some(11..13));
+        code.undo();
+        assert_eq!(code.buffer, table);
+    }
+
+    #[test]
+    fn convert_line_endings() {
+        for (eol, converted) in [("\n", "a\nb\nc\nd\n"), ("\r\n", "a\r\nb\r\nc\r\nd\r\n")] {
+            let mut code = CodeText::new("a\r\nb\nc\rd\r\n".to_owned());
+            let caret = code.replace_all(text::normalize_eol(&code.buffer, eol), 3);
+            assert_eq!(code.buffer, converted);
+            // The caret stays before `b`.
+            assert_eq!(caret, converted.find('b').unwrap());
+        }
+    }
+
+    #[test]
+    fn comment_block() {
+        let comment = "    # Reflow the words of this\n    # comment to fit the width.\n    #\n    #     kept(as, is)\n";
+        let code = CodeText::new(format!("x = 1\n{}y = 2  # no\n", comment));
+        // The four comment lines, without the trailing comment after them.
+        assert_eq!(code.comment_block(10), Some(6..comment.len() + 5));
+        assert_eq!(code.comment_block(0), None);
+    }
+
+    #[test]
+    fn number_literals() {
+        let code = CodeText::new("limit = 1000000 + len('1000')\n".to_owned());
+        assert_eq!(code.number_literals(0..code.len()), [8..15]);
+        // An empty range finds the literal the caret is in.
+        assert_eq!(code.number_literals(10..10), [8..15]);
+        assert!(code.number_literals(0..5).is_empty());
+    }
+
+    /// Syntax errors in the sample or captures without a color point to a
+    /// grammar or query update the sample and the colors were not adjusted
+    /// to.
+    #[test]
+    fn sample_is_valid_and_colored() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        assert_eq!(code.syntax_errors(), Vec::<Range<usize>>::new());
+        assert_eq!(code.unmapped_captures(), Vec::<&str>::new());
+        assert!(!code.highlight_spans().is_empty());
+    }
+
+    #[test]
+    fn scope_chain() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let scope_test = SAMPLE.find("def scope_test").unwrap();
+        let do_local = SAMPLE.find("def do_local").unwrap();
+        let local = SAMPLE.find("'local spam'").unwrap();
+        assert_eq!(
+            code.scope_chain(local),
+            [
+                ("scope_test".to_owned(), scope_test),
+                ("do_local".to_owned(), do_local)
+            ]
+        );
+        let call = SAMPLE.find("do_local()\n").unwrap();
+        assert_eq!(
+            code.scope_chain(call),
+            [("scope_test".to_owned(), scope_test)]
+        );
+        assert!(code
+            .scope_chain(SAMPLE.find("print('In").unwrap())
+            .is_empty());
+    }
+
+    #[test]
+    fn rename_identifiers() {
+        let mut code = CodeText::new(SAMPLE.to_owned());
+        let occurrences = code.identifier_occurrences("spam");
+        assert_eq!(occurrences.len(), 10);
+        let edits = occurrences
+            .into_iter()
+            .map(|range| (range, "eggs".to_owned()))
+            .collect();
+        code.edit_batch(edits);
+        // The strings mentioning spam are left alone.
+        let renamed = SAMPLE.replace("spam", "eggs").replace(" eggs'", " spam'");
+        assert_eq!(code.buffer, renamed);
+    }
+
+    #[test]
+    fn inconsistent_indentation() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        assert!(code.inconsistent_indentation().is_empty());
+        // `b` is dedented to a level no enclosing block uses.
+        let text = "if x:\n        a = 1\n    b = 2\nc = 3\n";
+        let code = CodeText::new(text.to_owned());
+        assert_eq!(
+            code.inconsistent_indentation(),
+            [text.find("    b").unwrap()]
+        );
+    }
+
+    #[test]
+    fn enclosing_string() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let start = SAMPLE.find("f\"Hello").unwrap();
+        let string = start..start + "f\"Hello {a + 1}\"".len();
+        // The interpolation is part of the outermost string.
+        let interpolated = SAMPLE.find("a + 1").unwrap();
+        assert_eq!(
+            code.enclosing_string_or_comment(interpolated),
+            Some(string.clone())
+        );
+        assert_eq!(code.enclosing_string_or_comment(start + 3), Some(string));
+        assert_eq!(
+            code.enclosing_string_or_comment(SAMPLE.find("x =").unwrap()),
+            None
+        );
+    }
+
+    #[test]
+    fn block_range_at() {
+        let code = CodeText::new(SAMPLE.to_owned());
+        let start = SAMPLE.find("def scope_test").unwrap();
+        let last = "print('After global assignment:', spam)";
+        let end = SAMPLE.find(last).unwrap() + last.len();
+        assert_eq!(code.block_range_at(start + 5), Some(start..end));
+        let statement = SAMPLE.find("spam = 'test spam'").unwrap();
+        assert_eq!(code.block_range_at(statement), Some(start..end));
+        // The indentation belongs to the nested function the line starts.
+        let nested = SAMPLE.find("def do_local").unwrap();
+        let nested_end = SAMPLE.find("'local spam'").unwrap() + "'local spam'".len();
+        assert_eq!(code.block_range_at(nested - 2), Some(nested..nested_end));
+        assert_eq!(code.block_range_at(SAMPLE.find("a = 42.5").unwrap()), None);
+    }
+}
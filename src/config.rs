@@ -0,0 +1,111 @@
+//! Loading of user configuration files.
+//!
+//! Configuration files use a simple line-based `key = value` format. Empty
+//! lines and lines starting with `#` are ignored, so values may themselves
+//! contain `#` (e.g. hex colors).
+
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// An error encountered while parsing a configuration file.
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    /// The 1-based line the error occurred on.
+    pub line: usize,
+    pub message: String,
+}
+
+impl ConfigError {
+    pub fn new(line: usize, message: impl Into<String>) -> Self {
+        ConfigError {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+/// The directory user configuration is loaded from, e.g. `~/.config/lyranos`.
+pub fn config_dir() -> Option<PathBuf> {
+    let base = if let Some(dir) = env::var_os("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = env::var_os("APPDATA") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(env::var_os("HOME")?).join(".config")
+    };
+    Some(base.join("lyranos"))
+}
+
+/// Parses the `key = value` entries of a configuration file, together with
+/// their 1-based line numbers.
+pub fn parse_entries(source: &str) -> Result<Vec<(usize, &str, &str)>, ConfigError> {
+    let mut entries = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => entries.push((index + 1, key.trim(), value.trim())),
+            None => return Err(ConfigError::new(index + 1, "expected `key = value`")),
+        }
+    }
+    Ok(entries)
+}
+
+/// Parse `value` as the value of the setting on `line`.
+pub fn parse_value<T: FromStr>(line: usize, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::new(line, format!("invalid value `{}`", value)))
+}
+
+/// Resolve the escape sequences `\n`, `\t` and `\\` in `value`, allowing
+/// multiline values.
+pub fn unescape(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some(c) => result.push(c),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Load the file `name` from the config directory using `parse`, falling back
+/// to the default if there is none. Returns why loading failed if the file is
+/// invalid.
+pub fn load_user<T: Default>(
+    name: &str,
+    parse: impl Fn(&str) -> Result<T, ConfigError>,
+) -> Result<T, String> {
+    let path = match config_dir() {
+        Some(dir) => dir.join(name),
+        None => return Ok(T::default()),
+    };
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(_) => return Ok(T::default()),
+    };
+    parse(&source).map_err(|err| format!("Failed to load {}: {}", path.display(), err))
+}
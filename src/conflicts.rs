@@ -0,0 +1,156 @@
+//! Unresolved merge conflicts left in a document by version control.
+
+use std::ops::Range;
+
+use crate::text;
+
+/// A conflict delimited by marker lines, as written by git:
+///
+/// ```text
+/// <<<<<<< HEAD
+/// our lines
+/// ||||||| base
+/// the common ancestor's lines, only with diff3 conflict style
+/// =======
+/// their lines
+/// >>>>>>> branch
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictRegion {
+    /// The whole conflict, from the start of the `<<<<<<<` line to the end of
+    /// the `>>>>>>>` line, including its line break.
+    pub range: Range<usize>,
+    /// Our lines, following the `<<<<<<<` line.
+    pub ours: Range<usize>,
+    /// The common ancestor's lines, following the `|||||||` line if there is
+    /// one.
+    pub base: Option<Range<usize>>,
+    /// Their lines, following the `=======` line.
+    pub theirs: Range<usize>,
+}
+
+/// How a conflict is resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// Keep our lines.
+    Ours,
+    /// Keep their lines.
+    Theirs,
+    /// Keep our lines followed by their lines.
+    Both,
+}
+
+impl ConflictRegion {
+    /// The lines replacing the conflict in `text` to resolve it, without the
+    /// marker lines.
+    pub fn resolved(&self, text: &str, resolution: Resolution) -> String {
+        let ours = &text[self.ours.clone()];
+        let theirs = &text[self.theirs.clone()];
+        match resolution {
+            Resolution::Ours => ours.to_owned(),
+            Resolution::Theirs => theirs.to_owned(),
+            Resolution::Both => format!("{}{}", ours, theirs),
+        }
+    }
+
+    /// The ranges of the marker lines, including their line breaks.
+    pub fn markers(&self) -> Vec<Range<usize>> {
+        let mut markers = vec![self.range.start..self.ours.start];
+        if let Some(ref base) = self.base {
+            markers.push(self.ours.end..base.start);
+        }
+        let separator = self.base.as_ref().map_or(self.ours.end, |base| base.end);
+        markers.push(separator..self.theirs.start);
+        markers.push(self.theirs.end..self.range.end);
+        markers
+    }
+}
+
+/// The complete conflicts in `text`, in order. Markers that do not form a
+/// complete conflict are ignored.
+pub fn conflict_regions(text: &str) -> Vec<ConflictRegion> {
+    let mut regions = Vec::new();
+    // The start of the conflict, the start of our lines and, once reached,
+    // the start of the base and their lines.
+    let mut open: Option<(usize, usize)> = None;
+    let mut base: Option<(usize, usize)> = None;
+    let mut theirs: Option<(usize, usize)> = None;
+    for start in text::line_starts(text) {
+        let end = text::line_end(text, start);
+        let next = (end + 1).min(text.len());
+        let line = text[start..end].trim_end();
+        if line.starts_with("<<<<<<<") {
+            open = Some((start, next));
+            base = None;
+            theirs = None;
+        } else if open.is_none() {
+            continue;
+        } else if line.starts_with("|||||||") && base.is_none() && theirs.is_none() {
+            base = Some((start, next));
+        } else if line == "=======" && theirs.is_none() {
+            theirs = Some((start, next));
+        } else if line.starts_with(">>>>>>>") && theirs.is_some() {
+            let (range_start, ours_start) = open.take().unwrap();
+            let (separator, theirs_start) = theirs.take().unwrap();
+            let ours_end = base.map_or(separator, |(base_start, _)| base_start);
+            regions.push(ConflictRegion {
+                range: range_start..next,
+                ours: ours_start..ours_end,
+                base: base.take().map(|(_, base_start)| base_start..separator),
+                theirs: theirs_start..start,
+            });
+        }
+        if next == text.len() {
+            break;
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MERGED: &str = "a\n<<<<<<< HEAD\nb\n=======\nc\n>>>>>>> topic\nd\n";
+
+    #[test]
+    fn regions() {
+        assert_eq!(
+            conflict_regions(MERGED),
+            [ConflictRegion {
+                range: 2..41,
+                ours: 15..17,
+                base: None,
+                theirs: 25..27,
+            }]
+        );
+        let diff3 = "<<<<<<< HEAD\nb\n||||||| base\na\n=======\nc\n>>>>>>> topic";
+        let regions = conflict_regions(diff3);
+        assert_eq!(regions.len(), 1);
+        assert_eq!(&diff3[regions[0].ours.clone()], "b\n");
+        assert_eq!(
+            regions[0].base.clone().map(|base| &diff3[base]),
+            Some("a\n")
+        );
+        assert_eq!(&diff3[regions[0].theirs.clone()], "c\n");
+        assert_eq!(regions[0].range, 0..diff3.len());
+        // Without the closing marker there is no conflict.
+        assert!(conflict_regions("<<<<<<< HEAD\nb\n=======\nc\n").is_empty());
+    }
+
+    #[test]
+    fn resolve() {
+        for (resolution, resolved) in [
+            (Resolution::Ours, "a\nb\nd\n"),
+            (Resolution::Theirs, "a\nc\nd\n"),
+            (Resolution::Both, "a\nb\nc\nd\n"),
+        ] {
+            let mut text = MERGED.to_owned();
+            for conflict in conflict_regions(MERGED).into_iter().rev() {
+                let kept = conflict.resolved(&text, resolution);
+                text.replace_range(conflict.range, &kept);
+            }
+            assert_eq!(text, resolved, "{:?}", resolution);
+        }
+    }
+}
@@ -0,0 +1,23 @@
+//! Problems in the document reported by a language server.
+
+use std::ops::Range;
+
+/// How serious a diagnostic is.
+#[cfg_attr(not(feature = "lsp"), allow(dead_code))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+/// A problem in a range of the document.
+// Diagnostics are only reported with the `lsp` feature.
+#[cfg_attr(not(feature = "lsp"), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
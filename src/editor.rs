@@ -0,0 +1,1339 @@
+//! The code editor widget.
+
+use druid::kurbo::{BezPath, Line};
+use druid::piet::{PietText, PietTextLayout, Text, TextLayout, TextLayoutBuilder};
+use druid::text::{EditableText, ImeInvalidation, Selection};
+use druid::widget::prelude::*;
+use druid::widget::TextBox;
+use druid::{
+    Color, FontDescriptor, FontFamily, HotKey, KbKey, KeyEvent, MouseEvent, Point, RawMods, Rect,
+    Selector, SysMods, WidgetPod,
+};
+
+use std::ops::Range;
+
+use crate::blame::BlameMode;
+use crate::block::BlockSelection;
+use crate::codetext::CodeText;
+use crate::finder::SymbolFinder;
+use crate::gutter::{self, GutterClick};
+use crate::language::Language;
+use crate::modes::{Mode, Modes};
+use crate::pairs::{self, PairAction};
+use crate::snippet::{self, Session};
+use crate::text;
+use crate::AppState;
+
+/// Place the caret at the given offset, clamped to the document, and scroll it
+/// into view once the document is laid out.
+pub const SET_CARET: Selector<usize> = Selector::new("lyranos.set-caret");
+
+/// Select a range of the document, clamped to its length, and scroll the
+/// caret into view.
+pub const SET_SELECTION: Selector<Selection> = Selector::new("lyranos.set-selection");
+const SCROLL_TO_CARET: Selector = Selector::new("lyranos.scroll-to-caret");
+/// Sent up to the enclosing [`Typewriter`](crate::typewriter::Typewriter)
+/// with the caret's rectangle in window coordinates, instead of scrolling the
+/// caret into view, when typewriter scrolling is enabled.
+pub const CENTER_CARET: Selector<Rect> = Selector::new("lyranos.center-caret");
+
+const FONT_SIZE: f64 = 16.0;
+/// Horizontal space around the line numbers in the gutter.
+const GUTTER_PADDING: f64 = 8.0;
+/// The diameter of the bookmark markers, which fit in the gutter padding.
+const BOOKMARK_SIZE: f64 = 6.0;
+/// The node kinds selected by Ctrl+Alt+F in the supported languages.
+const FUNCTION_KINDS: &[&str] = &[
+    "function_definition",
+    "function_declaration",
+    "method_definition",
+    "function",
+    "arrow_function",
+];
+/// The node kinds selected by Ctrl+Alt+C in the supported languages.
+const CLASS_KINDS: &[&str] = &["class_definition", "class_declaration"];
+/// The maximum number of lines of a definition shown when peeking at it.
+const PEEK_MAX_LINES: usize = 12;
+/// Space around the text of a peeked definition.
+const PEEK_PADDING: f64 = 6.0;
+
+/// A multiline text box editing [`AppState::code`], extended with a line
+/// number gutter and editor-specific keyboard commands.
+///
+/// The editor grows to the height of its text, so it is meant to be placed in
+/// a vertical [`Scroll`](druid::widget::Scroll) that scrolls the gutter along
+/// with the text.
+pub struct Editor {
+    textbox: WidgetPod<CodeText, TextBox<CodeText>>,
+    gutter_width: f64,
+    /// The resolved font of the text and the line numbers.
+    font: FontFamily,
+    /// Whether the caret line is kept vertically centered.
+    typewriter: bool,
+    /// The number of lines kept visible above and below the caret.
+    scroll_margin: usize,
+    /// The snippet whose tab stops are being filled in, if any.
+    snippet: Option<Session>,
+    /// The identifier being renamed, if any.
+    rename: Option<Rename>,
+    /// The lines of the definition shown below the caret line, if any.
+    peek: Option<String>,
+    /// The overlay to go to a definition by name, if it is open.
+    finder: Option<SymbolFinder>,
+    /// The rectangular selection made by dragging with Alt held, if any.
+    block: Option<BlockSelection>,
+    /// The modes of the snippet, rename, peek, finder and block selection
+    /// above, in the order Escape leaves them.
+    modes: Modes,
+}
+
+/// An identifier being renamed. Its other occurrences are replaced once the
+/// new name is confirmed.
+struct Rename {
+    /// The name before the rename.
+    name: String,
+    /// The range of the identifier being edited.
+    range: Range<usize>,
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        let textbox = TextBox::multiline()
+            .with_font(FontDescriptor::new(FontFamily::MONOSPACE).with_size(FONT_SIZE));
+        Editor {
+            textbox: WidgetPod::new(textbox),
+            gutter_width: 0.0,
+            font: FontFamily::MONOSPACE,
+            typewriter: false,
+            scroll_margin: 0,
+            snippet: None,
+            rename: None,
+            peek: None,
+            finder: None,
+            block: None,
+            modes: Modes::default(),
+        }
+    }
+
+    /// The current selection of the text box.
+    fn selection(&self) -> Selection {
+        self.textbox.widget().text().borrow().selection()
+    }
+
+    /// Use the font family named `name` for the text and the line numbers.
+    fn set_font(&mut self, text: &mut PietText, name: &str) {
+        self.font = font_family(text, name);
+        let font = FontDescriptor::new(self.font.clone()).with_size(FONT_SIZE);
+        self.textbox.widget_mut().set_font(font);
+    }
+
+    /// Move the selection of the text box, scrolling it into view.
+    fn set_selection(&mut self, ctx: &mut EventCtx, selection: Selection) {
+        if self.select(ctx, selection) {
+            self.scroll_to_caret(ctx);
+        }
+    }
+
+    /// Move the selection of the text box without scrolling, returning
+    /// `false` if the text box is not ready to be edited.
+    fn select(&mut self, ctx: &mut EventCtx, selection: Selection) -> bool {
+        let text = self.textbox.widget_mut().text_mut();
+        if !text.can_write() {
+            return false;
+        }
+        let _ = text.borrow_mut().set_selection(selection);
+        ctx.invalidate_text_input(ImeInvalidation::SelectionChanged);
+        ctx.request_layout();
+        true
+    }
+
+    /// The rectangle of the caret, relative to the editor.
+    fn caret_rect(&self) -> Rect {
+        let session = self.textbox.widget().text().borrow();
+        let line = session
+            .layout
+            .cursor_line_for_text_position(session.selection().active);
+        let origin = self.textbox.layout_rect().origin().to_vec2();
+        Rect::from_points(line.p0, line.p1) + origin
+    }
+
+    /// Scroll the enclosing scroll container to make the caret visible, or to
+    /// center it with typewriter scrolling.
+    fn scroll_to_caret(&self, ctx: &mut EventCtx) {
+        let caret = self.caret_rect();
+        if self.typewriter {
+            ctx.submit_notification(CENTER_CARET.with(caret + ctx.window_origin().to_vec2()));
+        } else {
+            let margin = FONT_SIZE + self.scroll_margin as f64 * caret.height();
+            ctx.scroll_area_to_view(caret.inflate(FONT_SIZE, margin));
+        }
+    }
+
+    /// Handle a click on the line numbers, acting on the clicked line as set
+    /// by `gutter_click`, or toggling its bookmark if Ctrl is held.
+    fn click_gutter(
+        &mut self,
+        ctx: &mut EventCtx,
+        mouse: &MouseEvent,
+        data: &mut AppState,
+        env: &Env,
+    ) {
+        let buffer = &data.code.buffer;
+        let line = match self.gutter_line(&data.code, mouse.pos.y, env) {
+            Some(line) => line,
+            None => return,
+        };
+        let action = if mouse.mods.ctrl() || mouse.mods.meta() {
+            GutterClick::ToggleBookmark
+        } else {
+            data.settings.gutter_click
+        };
+        match action {
+            GutterClick::SelectLine => {
+                let start_of = |line| data.code.line_range(line).map(|range| range.start);
+                let start = start_of(line).unwrap_or(buffer.len());
+                let end = start_of(line + 1).unwrap_or(buffer.len());
+                self.set_selection(ctx, Selection::new(start, end));
+                ctx.request_focus();
+            }
+            GutterClick::ToggleBookmark => {
+                data.code.bookmarks.toggle(line);
+                ctx.request_paint();
+            }
+            GutterClick::Nothing => {}
+        }
+    }
+
+    /// The zero-based line next to height `y` in the gutter, or `None` if the
+    /// text is not laid out yet.
+    fn gutter_line(&self, code: &CodeText, y: f64, env: &Env) -> Option<usize> {
+        let session = self.textbox.widget().text().borrow();
+        let layout = session.layout.layout()?;
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let tops: Vec<f64> = (0..code.line_count())
+            .map_while(|line| {
+                let position = layout.hit_test_text_position(code.line_range(line)?.start);
+                Some(insets.y0 + layout.line_metric(position.line)?.y_offset)
+            })
+            .collect();
+        Some(gutter::line_at(&tops, y))
+    }
+
+    /// Returns `true` if the gutter has a column for the commit of each line.
+    fn shows_blame(data: &AppState) -> bool {
+        data.settings.blame == BlameMode::Column && !data.blame.is_empty()
+    }
+
+    /// Paint the line numbers of all visible lines next to the text.
+    fn paint_gutter(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let visible = ctx.region().bounding_box();
+        let code = &data.code;
+        let caret_line = code.line_of(self.selection().active);
+        let mixed_indentation = code.mixed_indentation_lines();
+        let shows_blame = Self::shows_blame(data);
+        // Start at the line at the top of the visible region rather than
+        // hit testing every line above it.
+        let top = layout.hit_test_point(Point::new(0.0, visible.y0 - insets.y0));
+        for line in code.line_of(top.idx)..code.line_count() {
+            let start = match code.line_range(line) {
+                Some(range) => range.start,
+                None => break,
+            };
+            let position = layout.hit_test_text_position(start);
+            let metric = match layout.line_metric(position.line) {
+                Some(metric) => metric,
+                None => break,
+            };
+            let y = insets.y0 + metric.y_offset;
+            if y + metric.height < visible.y0 {
+                continue;
+            } else if y > visible.y1 {
+                break;
+            }
+            let color = if mixed_indentation.binary_search(&line).is_ok() {
+                data.theme.error.clone()
+            } else if line == caret_line {
+                data.theme.foreground.clone()
+            } else {
+                data.theme.line_number.clone()
+            };
+            if code.bookmarks.contains(line) {
+                let y = y + (metric.height - BOOKMARK_SIZE) / 2.0;
+                let marker = Rect::from_origin_size((1.0, y), (BOOKMARK_SIZE, BOOKMARK_SIZE));
+                ctx.fill(
+                    marker.to_rounded_rect(BOOKMARK_SIZE / 2.0),
+                    &data.theme.bookmark,
+                );
+            }
+            let label = gutter::label(line, caret_line, data.settings.line_numbers);
+            let label = label_layout(ctx.text(), &self.font, label, color);
+            let x = self.gutter_width - GUTTER_PADDING - label.size().width;
+            ctx.draw_text(&label, (x, y));
+            if let (true, Some(Some(commit))) = (shows_blame, data.blame.get(line)) {
+                let color = data.theme.line_number.clone();
+                let label = label_layout(ctx.text(), &self.font, commit.label(), color);
+                ctx.draw_text(&label, (GUTTER_PADDING, y));
+            }
+        }
+    }
+
+    /// Paint the background colors the theme gives to highlight captures.
+    fn paint_capture_backgrounds(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let backgrounds = data
+            .code
+            .capture_backgrounds(&data.theme.capture_backgrounds);
+        if backgrounds.is_empty() {
+            return;
+        }
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        for (range, color) in backgrounds {
+            for rect in layout.rects_for_range(range) {
+                ctx.fill(rect + origin.to_vec2(), color);
+            }
+        }
+    }
+
+    /// Underline the ranges of the language server's diagnostics with a wavy
+    /// line.
+    fn paint_diagnostics(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        if data.diagnostics.is_empty() {
+            return;
+        }
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        let buffer = &data.code.buffer;
+        for diagnostic in data.diagnostics.iter() {
+            // Empty ranges, e.g. of a missing token, mark the character at
+            // their start.
+            let start = diagnostic.range.start.min(buffer.len());
+            let mut end = diagnostic.range.end.clamp(start, buffer.len());
+            if end == start {
+                end = buffer[start..]
+                    .chars()
+                    .next()
+                    .map_or(start, |c| start + c.len_utf8());
+            }
+            let color = data.theme.diagnostic(diagnostic.severity);
+            for rect in layout.rects_for_range(start..end) {
+                let rect = rect + origin.to_vec2();
+                let mut wave = BezPath::new();
+                wave.move_to((rect.x0, rect.y1 - 1.0));
+                let mut x = rect.x0;
+                let mut up = true;
+                while x < rect.x1 {
+                    x += 2.0;
+                    wave.line_to((x, if up { rect.y1 - 3.0 } else { rect.y1 - 1.0 }));
+                    up = !up;
+                }
+                ctx.stroke(wave, color, 1.0);
+            }
+        }
+    }
+
+    /// Highlight the merge conflicts: their marker lines, our lines and their
+    /// lines each in a different color.
+    fn paint_conflicts(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let conflicts = data.code.conflict_regions();
+        if conflicts.is_empty() {
+            return;
+        }
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        let marker = data.theme.error.clone().with_alpha(0.25);
+        let ours = data.theme.bookmark.clone().with_alpha(0.15);
+        let theirs = data.theme.warning.clone().with_alpha(0.15);
+        for conflict in conflicts {
+            let mut ranges: Vec<_> = conflict
+                .markers()
+                .into_iter()
+                .map(|range| (range, &marker))
+                .collect();
+            ranges.push((conflict.ours.clone(), &ours));
+            ranges.push((conflict.theirs.clone(), &theirs));
+            for (range, color) in ranges {
+                for rect in layout.rects_for_range(range) {
+                    ctx.fill(rect + origin.to_vec2(), color);
+                }
+            }
+        }
+    }
+
+    /// Paint the column ruler and highlight the parts of lines extending past
+    /// it, as enabled in the settings.
+    fn paint_ruler(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let settings = &data.settings;
+        if !settings.show_ruler && !settings.highlight_long_lines {
+            return;
+        }
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        if settings.highlight_long_lines {
+            let color = data.theme.error.clone().with_alpha(0.2);
+            for range in text::overlong_line_ranges(&data.code.buffer, settings.ruler_column) {
+                for rect in layout.rects_for_range(range) {
+                    ctx.fill(rect + origin.to_vec2(), &color);
+                }
+            }
+        }
+        if settings.show_ruler {
+            // The font is monospaced, so the column's position is the width of
+            // that many characters.
+            let columns = "0".repeat(settings.ruler_column);
+            let columns = label_layout(ctx.text(), &self.font, columns, Color::WHITE);
+            let x = (origin.x + columns.size().width).round() + 0.5;
+            let height = ctx.size().height;
+            ctx.stroke(Line::new((x, 0.0), (x, height)), &data.theme.ruler, 1.0);
+        }
+    }
+
+    /// Paint the peeked definition in a box below the caret line.
+    fn paint_peek(&self, ctx: &mut PaintCtx, data: &AppState, peek: &str) {
+        let layout = label_layout(
+            ctx.text(),
+            &self.font,
+            peek.to_owned(),
+            data.theme.foreground.clone(),
+        );
+        let caret = self.caret_rect();
+        let origin = Point::new(caret.x0, caret.y1 + PEEK_PADDING);
+        let size = layout.size() + Size::new(2.0 * PEEK_PADDING, 2.0 * PEEK_PADDING);
+        let frame = Rect::from_origin_size(origin, size).to_rounded_rect(4.0);
+        ctx.fill(frame, &data.theme.selection_background);
+        ctx.stroke(frame, &data.theme.line_number, 1.0);
+        ctx.draw_text(&layout, origin + (PEEK_PADDING, PEEK_PADDING));
+    }
+
+    /// Paint the symbol finder at the top of the visible part of the editor:
+    /// the query followed by the matching definitions and their line numbers.
+    fn paint_finder(&self, ctx: &mut PaintCtx, data: &AppState, finder: &SymbolFinder) {
+        let symbols = data.code.symbols();
+        let matches = finder.matches(&symbols);
+        let mut listing = format!("Go to: {}", finder.query);
+        for (i, symbol) in matches.iter().enumerate() {
+            let marker = if i == finder.selected { "▸" } else { " " };
+            let line = data.code.line_of(symbol.range.start) + 1;
+            listing.push_str(&format!("\n{} {}  :{}", marker, symbol.name, line));
+        }
+        if matches.is_empty() {
+            listing.push_str("\n  No matching definitions");
+        }
+        let layout = label_layout(
+            ctx.text(),
+            &self.font,
+            listing,
+            data.theme.foreground.clone(),
+        );
+        let visible = ctx.region().bounding_box();
+        let origin = Point::new(self.gutter_width + PEEK_PADDING, visible.y0 + PEEK_PADDING);
+        let size = layout.size() + Size::new(2.0 * PEEK_PADDING, 2.0 * PEEK_PADDING);
+        let frame = Rect::from_origin_size(origin, size).to_rounded_rect(4.0);
+        ctx.fill(frame, &data.theme.selection_background);
+        ctx.stroke(frame, &data.theme.line_number, 1.0);
+        ctx.draw_text(&layout, origin + (PEEK_PADDING, PEEK_PADDING));
+    }
+
+    /// The line and column under `pos`, a point in the editor. The column is
+    /// taken from the horizontal position, so it may lie past the end of the
+    /// line.
+    fn position_at(
+        &self,
+        ctx: &mut EventCtx,
+        pos: Point,
+        data: &AppState,
+        env: &Env,
+    ) -> (usize, usize) {
+        let char_width = label_layout(ctx.text(), &self.font, "0".to_owned(), Color::WHITE)
+            .size()
+            .width;
+        let session = self.textbox.widget().text().borrow();
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        let pos = pos - origin.to_vec2();
+        let offset = match session.layout.layout() {
+            Some(layout) => layout.hit_test_point(pos).idx,
+            None => 0,
+        };
+        let line = data.code.line_of(offset);
+        let column = (pos.x.max(0.0) / char_width).round() as usize;
+        (line, column)
+    }
+
+    /// Start or extend a block selection with the mouse, returning `true` if
+    /// the event was consumed.
+    fn block_mouse(
+        &mut self,
+        ctx: &mut EventCtx,
+        event: &Event,
+        data: &mut AppState,
+        env: &Env,
+    ) -> bool {
+        match event {
+            Event::MouseDown(MouseEvent { pos, mods, .. }) if mods.alt() => {
+                let position = self.position_at(ctx, *pos, data, env);
+                self.block = Some(BlockSelection::new(position, position));
+                self.modes.enter(Mode::Block);
+                ctx.set_active(true);
+                ctx.request_focus();
+            }
+            Event::MouseMove(MouseEvent { pos, .. }) if ctx.is_active() && self.block.is_some() => {
+                let position = self.position_at(ctx, *pos, data, env);
+                if let Some(ref mut block) = self.block {
+                    block.active = position;
+                }
+            }
+            Event::MouseUp(_) if ctx.is_active() && self.block.is_some() => ctx.set_active(false),
+            Event::MouseDown(_) if self.block.is_some() => {
+                self.leave(ctx, data, Mode::Block);
+                return false;
+            }
+            _ => return false,
+        }
+        ctx.request_paint();
+        true
+    }
+
+    /// Handle a key while there is a block selection: typing replaces its
+    /// columns on every line and Backspace deletes them, or the character
+    /// before them. Other keys leave the block selection.
+    fn block_key(&mut self, ctx: &mut EventCtx, key: &KeyEvent, data: &mut AppState) -> bool {
+        let block = match self.block {
+            Some(block) => block,
+            None => return false,
+        };
+        let buffer = &data.code.buffer;
+        let (edits, block) = match key.key {
+            KbKey::Character(ref typed) if !key.mods.ctrl() && !key.mods.meta() => {
+                block.insert(buffer, typed)
+            }
+            KbKey::Backspace => block.delete_backward(buffer),
+            _ => {
+                self.leave(ctx, data, Mode::Block);
+                return false;
+            }
+        };
+        data.code.edit_batch(edits);
+        if let Some(range) = block.ranges(&data.code.buffer).last() {
+            self.set_selection(ctx, Selection::caret(range.end));
+        }
+        self.block = Some(block);
+        ctx.request_paint();
+        true
+    }
+
+    /// Paint the columns of the block selection, or a caret on every line if
+    /// none are selected.
+    fn paint_block(&self, ctx: &mut PaintCtx, data: &AppState, env: &Env, block: &BlockSelection) {
+        let session = self.textbox.widget().text().borrow();
+        let layout = match session.layout.layout() {
+            Some(layout) => layout,
+            None => return,
+        };
+        let insets = env.get(druid::theme::TEXTBOX_INSETS);
+        let origin = self.textbox.layout_rect().origin() + (insets.x0, insets.y0);
+        let selected = data.theme.selection_foreground.clone().with_alpha(0.25);
+        for range in block.ranges(&data.code.buffer) {
+            if range.is_empty() {
+                let caret = session.layout.cursor_line_for_text_position(range.start);
+                ctx.stroke(caret + origin.to_vec2(), &data.theme.caret, 1.0);
+            } else {
+                for rect in layout.rects_for_range(range) {
+                    ctx.fill(rect + origin.to_vec2(), &selected);
+                }
+            }
+        }
+    }
+
+    /// Handle a key while the symbol finder is open. Typing filters the
+    /// definitions, the arrow keys choose one and Enter goes to it. Other
+    /// keys are consumed so they do not edit the document.
+    fn finder_key(&mut self, ctx: &mut EventCtx, key: &KeyEvent, data: &mut AppState) -> bool {
+        let finder = match self.finder {
+            Some(ref mut finder) => finder,
+            None => return false,
+        };
+        let symbols = data.code.symbols();
+        match key.key {
+            KbKey::Enter => {
+                let target = finder
+                    .matches(&symbols)
+                    .get(finder.selected)
+                    .map(|symbol| symbol.range.start);
+                self.leave(ctx, data, Mode::Symbols);
+                if let Some(offset) = target {
+                    self.set_selection(ctx, Selection::caret(offset));
+                }
+            }
+            KbKey::ArrowDown => {
+                if finder.selected + 1 < finder.matches(&symbols).len() {
+                    finder.selected += 1;
+                }
+            }
+            KbKey::ArrowUp => finder.selected = finder.selected.saturating_sub(1),
+            KbKey::Backspace => {
+                finder.query.pop();
+                finder.selected = 0;
+            }
+            KbKey::Character(ref typed) if !key.mods.ctrl() && !key.mods.meta() => {
+                finder.query.push_str(typed);
+                finder.selected = 0;
+            }
+            _ => {}
+        }
+        ctx.request_paint();
+        true
+    }
+
+    /// Show the definition named by the identifier at the caret below the
+    /// caret line, without moving the caret.
+    fn peek_definition(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let definition = data.code.definition_of(self.selection().active);
+        self.peek = definition.map(|symbol| {
+            text::lines_excerpt(&data.code.buffer, symbol.range, PEEK_MAX_LINES).to_owned()
+        });
+        match self.peek {
+            Some(_) => self.modes.enter(Mode::Peek),
+            None => data.status = "No definition found".to_owned(),
+        }
+        ctx.request_paint();
+    }
+
+    /// Handle editor key bindings, returning `true` if the key was consumed.
+    fn handle_key(&mut self, ctx: &mut EventCtx, key: &KeyEvent, data: &mut AppState) -> bool {
+        if HotKey::new(None, KbKey::Escape).matches(key) {
+            return self.escape(ctx, data);
+        } else if self.finder.is_some() {
+            return self.finder_key(ctx, key, data);
+        } else if self.block.is_some() {
+            return self.block_key(ctx, key, data);
+        } else if HotKey::new(SysMods::CmdShift, "O").matches(key) {
+            self.finder = Some(SymbolFinder::default());
+            self.modes.enter(Mode::Symbols);
+            ctx.request_paint();
+            return true;
+        } else if HotKey::new(RawMods::Alt, KbKey::F12).matches(key) {
+            self.peek_definition(ctx, data);
+            return true;
+        } else if self.rename.is_some() && HotKey::new(None, KbKey::Enter).matches(key) {
+            self.finish_rename(ctx, data);
+            return true;
+        } else if data.settings.trim_whitespace_on_enter
+            && HotKey::new(None, KbKey::Enter).matches(key)
+        {
+            let range = self.selection().range();
+            let (range, line_break) = text::trimmed_line_break(&data.code.buffer, range);
+            data.code.edit(range.clone(), &line_break);
+            self.set_selection(ctx, Selection::caret(range.start + line_break.len()));
+            return true;
+        } else if HotKey::new(None, KbKey::Backspace).matches(key) {
+            return self.unindent(ctx, data);
+        } else if HotKey::new(None, KbKey::F2).matches(key) {
+            self.start_rename(ctx, data);
+            return true;
+        } else if HotKey::new(None, KbKey::Tab).matches(key) {
+            return self.next_tab_stop(ctx, data);
+        } else if HotKey::new(SysMods::Shift, KbKey::Tab).matches(key) {
+            return self.prev_tab_stop(ctx);
+        } else if HotKey::new(SysMods::Cmd, "d").matches(key) {
+            self.select_next_occurrence(ctx, data);
+            return true;
+        } else if HotKey::new(SysMods::Cmd, "/").matches(key) {
+            self.toggle_comment(ctx, data);
+            return true;
+        } else if HotKey::new(SysMods::CmdShift, "\"").matches(key) {
+            // Ctrl+Shift+' selects the string or comment around the caret.
+            let caret = self.selection().active;
+            if let Some(range) = data.code.enclosing_string_or_comment(caret) {
+                self.set_selection(ctx, Selection::new(range.start, range.end));
+            }
+            return true;
+        } else if HotKey::new(SysMods::AltCmd, "b").matches(key) {
+            let caret = self.selection().active;
+            if let Some(range) = data.code.block_range_at(caret) {
+                self.set_selection(ctx, Selection::new(range.start, range.end));
+            }
+            return true;
+        } else if HotKey::new(SysMods::Cmd, KbKey::F2).matches(key) {
+            let line = data.code.line_of(self.selection().active);
+            data.code.bookmarks.toggle(line);
+            ctx.request_paint();
+            return true;
+        } else if HotKey::new(SysMods::AltCmd, "d").matches(key) {
+            self.duplicate_incremented(ctx, data);
+            return true;
+        } else if HotKey::new(SysMods::AltCmd, "f").matches(key) {
+            self.select_enclosing(ctx, data, FUNCTION_KINDS);
+            return true;
+        } else if HotKey::new(SysMods::AltCmd, "c").matches(key) {
+            self.select_enclosing(ctx, data, CLASS_KINDS);
+            return true;
+        } else if HotKey::new(SysMods::AltCmd, "s").matches(key) {
+            let caret = self.selection().active;
+            if let Some(range) = data.code.enclosing_statement(caret) {
+                self.set_selection(ctx, Selection::new(range.start, range.end));
+            }
+            return true;
+        } else if HotKey::new(SysMods::CmdShift, "B").matches(key) {
+            let range = data.code.current_block_range(self.selection().active);
+            self.set_selection(ctx, Selection::new(range.start, range.end));
+            return true;
+        } else if let KbKey::Character(ref typed) = key.key {
+            if !key.mods.ctrl()
+                && !key.mods.meta()
+                && self.rename.is_none()
+                && self.snippet.is_none()
+            {
+                return self.type_pair(ctx, data, typed);
+            }
+        }
+        let caret = self.selection().active;
+        let target = if HotKey::new(SysMods::Cmd, KbKey::ArrowDown).matches(key) {
+            data.code.next_definition(caret)
+        } else if HotKey::new(SysMods::Cmd, KbKey::ArrowUp).matches(key) {
+            data.code.prev_definition(caret)
+        } else if HotKey::new(RawMods::Alt, KbKey::ArrowDown).matches(key) {
+            data.code.sibling_statement(caret, true)
+        } else if HotKey::new(RawMods::Alt, KbKey::ArrowUp).matches(key) {
+            data.code.sibling_statement(caret, false)
+        } else if HotKey::new(None, KbKey::F8).matches(key)
+            || HotKey::new(SysMods::Shift, KbKey::F8).matches(key)
+        {
+            let line = data.code.line_of(caret);
+            let bookmark = if key.mods.shift() {
+                data.code.bookmarks.prev(line)
+            } else {
+                data.code.bookmarks.next(line)
+            };
+            let range = bookmark.and_then(|line| data.code.line_range(line));
+            range.map(|range| range.start)
+        } else if HotKey::new(None, KbKey::F7).matches(key)
+            || HotKey::new(SysMods::Shift, KbKey::F7).matches(key)
+        {
+            // F7 and Shift+F7 go to the next and previous merge conflict,
+            // wrapping around like bookmarks.
+            let starts: Vec<usize> = data
+                .code
+                .conflict_regions()
+                .iter()
+                .map(|conflict| conflict.range.start)
+                .collect();
+            let line_start = text::line_start(&data.code.buffer, caret);
+            if key.mods.shift() {
+                let mut before = starts.iter().rev().filter(|&&start| start < line_start);
+                before.next().or_else(|| starts.last()).copied()
+            } else {
+                let mut after = starts.iter().filter(|&&start| start > line_start);
+                after.next().or_else(|| starts.first()).copied()
+            }
+        } else if HotKey::new(SysMods::Cmd, "]").matches(key) {
+            Some(text::next_blank_line_offset(&data.code.buffer, caret))
+        } else if HotKey::new(SysMods::Cmd, "[").matches(key) {
+            Some(text::prev_blank_line_offset(&data.code.buffer, caret))
+        } else if HotKey::new(SysMods::CmdShift, "|").matches(key) {
+            // Ctrl+Shift+\ jumps to the matching bracket, accepting the caret
+            // both before and after a bracket.
+            data.code
+                .matching_bracket(caret)
+                .or_else(|| data.code.matching_bracket(caret.checked_sub(1)?))
+        } else {
+            return false;
+        };
+        if let Some(offset) = target {
+            self.set_selection(ctx, Selection::caret(offset));
+        }
+        true
+    }
+
+    /// The selected range an operation applies to, without surrounding
+    /// whitespace if selections are trimmed.
+    fn operand_range(&self, data: &AppState) -> Range<usize> {
+        let range = self.selection().range();
+        if data.settings.trim_selections {
+            text::trim_selection(&data.code.buffer, range)
+        } else {
+            range
+        }
+    }
+
+    /// Select the word at the caret, or if there already is a selection, the
+    /// next occurrence of the selected text.
+    fn select_next_occurrence(&mut self, ctx: &mut EventCtx, data: &AppState) {
+        let selection = self.selection();
+        let range = if selection.is_caret() {
+            Some(data.code.word_range(selection.active))
+        } else {
+            data.code.next_occurrence(self.operand_range(data))
+        };
+        if let Some(range) = range {
+            self.set_selection(ctx, Selection::new(range.start, range.end));
+        }
+    }
+
+    /// Comment or uncomment the selected lines.
+    fn toggle_comment(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let selection = self.selection();
+        let prefix = data.code.language().line_comment();
+        let (range, lines) =
+            text::toggle_line_comment(&data.code.buffer, self.operand_range(data), prefix);
+        let delta = lines.len() as isize - range.len() as isize;
+        let end = range.start + lines.len();
+        data.code.edit(range.clone(), lines);
+        let selection = if selection.is_caret() {
+            let caret = (selection.active as isize + delta) as usize;
+            Selection::caret(caret.max(range.start).min(end))
+        } else {
+            Selection::new(range.start, end)
+        };
+        self.set_selection(ctx, selection);
+    }
+
+    /// Replace the selection with `pasted`, with the line endings of the
+    /// document and re-indented to the indentation of the caret line, or to
+    /// the caret column if only whitespace precedes it, as enabled in the
+    /// settings.
+    fn paste(&mut self, ctx: &mut EventCtx, data: &mut AppState, pasted: &str) {
+        let range = self.selection().range();
+        let buffer = &data.code.buffer;
+        let mut pasted = pasted.to_owned();
+        if data.settings.normalize_pasted_line_endings {
+            pasted = text::normalize_eol(&pasted, text::detect_eol(buffer));
+        }
+        if data.settings.reindent_on_paste {
+            let before = &buffer[text::line_start(buffer, range.start)..range.start];
+            let indent = if before.trim().is_empty() {
+                before
+            } else {
+                text::indentation(before)
+            };
+            pasted = text::reindent_block(&pasted, text::base_indent(&pasted), indent);
+        }
+        let caret = range.start + pasted.len();
+        data.code.edit(range, pasted);
+        self.set_selection(ctx, Selection::caret(caret));
+    }
+
+    /// Align a Python `else`, `elif`, `except`, `finally` or `case` clause
+    /// whose colon was just typed with the statement it continues.
+    fn dedent_clause(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let caret = self.selection();
+        let buffer = &data.code.buffer;
+        if data.code.language() != Language::Python
+            || !caret.is_caret()
+            || !buffer[..caret.active].ends_with(':')
+        {
+            return;
+        }
+        let start = text::line_start(buffer, caret.active);
+        let indent = text::indentation(&buffer[start..caret.active]);
+        let keyword = buffer[start + indent.len()..caret.active]
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or("");
+        if !text::PYTHON_CLAUSE_KEYWORDS.contains(&keyword) {
+            return;
+        }
+        if let Some(target) = text::clause_indent(buffer, start, keyword) {
+            let removed = indent.len() - target.len();
+            let (range, target) = (start..start + indent.len(), target.to_owned());
+            data.code.edit(range, target);
+            self.set_selection(ctx, Selection::caret(caret.active - removed));
+        }
+    }
+
+    /// Close a bracket or quote typed at the caret, or type over the closing
+    /// one after it, as decided by [`pairs::action`]. Returns `false` if the
+    /// character is to be inserted as usual.
+    fn type_pair(&mut self, ctx: &mut EventCtx, data: &mut AppState, typed: &str) -> bool {
+        let caret = self.selection();
+        let mut chars = typed.chars();
+        let c = match (chars.next(), chars.next()) {
+            (Some(c), None) if caret.is_caret() => c,
+            _ => return false,
+        };
+        match pairs::action(&data.code.buffer, caret.active, c, &data.settings) {
+            PairAction::Insert => return false,
+            PairAction::Close(close) => {
+                let (range, pair) = (caret.active..caret.active, format!("{}{}", c, close));
+                data.code.edit(range, pair);
+                self.set_selection(ctx, Selection::caret(caret.active + c.len_utf8()));
+            }
+            PairAction::TypeOver(offset) => self.set_selection(ctx, Selection::caret(offset)),
+        }
+        true
+    }
+
+    /// Insert a copy of the selection, or of the caret line if nothing is
+    /// selected, after it with its last number incremented, and select the
+    /// copy so that repeating this continues the sequence.
+    fn duplicate_incremented(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let selection = self.selection();
+        let buffer = &data.code.buffer;
+        let (range, separator) = if selection.is_caret() {
+            let start = text::line_start(buffer, selection.active);
+            let end = text::line_end(buffer, selection.active);
+            let end = buffer[start..end]
+                .strip_suffix('\r')
+                .map_or(end, |line| start + line.len());
+            (start..end, text::detect_eol(buffer))
+        } else {
+            (selection.range(), "")
+        };
+        let copy = match text::increment_last_number(&buffer[range.clone()]) {
+            Some(copy) => copy,
+            None => {
+                data.status = "No number to increment".to_owned();
+                return;
+            }
+        };
+        let start = range.end + separator.len();
+        let copied = Selection::new(start, start + copy.len());
+        data.code
+            .edit(range.end..range.end, format!("{}{}", separator, copy));
+        let selection = if selection.is_caret() {
+            let column = selection.active - range.start;
+            Selection::caret((start + column).min(copied.max()))
+        } else {
+            copied
+        };
+        self.set_selection(ctx, selection);
+    }
+
+    /// Select the innermost node around the caret of any of the `kinds`.
+    fn select_enclosing(&mut self, ctx: &mut EventCtx, data: &mut AppState, kinds: &[&str]) {
+        let caret = self.selection().active;
+        let innermost = kinds
+            .iter()
+            .filter_map(|kind| data.code.enclosing_of_kind(caret, kind))
+            .min_by_key(|range| range.len());
+        match innermost {
+            Some(range) => self.set_selection(ctx, Selection::new(range.start, range.end)),
+            None => data.status = "Nothing to select around the caret".to_owned(),
+        }
+    }
+
+    /// Remove the spaces back to the previous indentation level if only
+    /// spaces precede the caret, returning `false` if Backspace should remove
+    /// a single character instead.
+    fn unindent(&mut self, ctx: &mut EventCtx, data: &mut AppState) -> bool {
+        let selection = self.selection();
+        if !selection.is_caret() {
+            return false;
+        }
+        let buffer = &data.code.buffer;
+        let range = match text::unindent_range(buffer, selection.active, data.settings.tab_width) {
+            Some(range) => range,
+            None => return false,
+        };
+        data.code.edit(range.clone(), "");
+        self.set_selection(ctx, Selection::caret(range.start));
+        true
+    }
+
+    /// Leave the most recently entered mode, or if there is none, collapse the
+    /// selection to the caret. Returns `false` if there was nothing to do.
+    fn escape(&mut self, ctx: &mut EventCtx, data: &mut AppState) -> bool {
+        match self.modes.pop() {
+            Some(mode) => self.leave(ctx, data, mode),
+            None => {
+                let selection = self.selection();
+                if selection.is_caret() {
+                    return false;
+                }
+                self.set_selection(ctx, Selection::caret(selection.active));
+            }
+        }
+        true
+    }
+
+    /// Leave `mode`, discarding its state.
+    fn leave(&mut self, ctx: &mut EventCtx, data: &mut AppState, mode: Mode) {
+        self.modes.leave(mode);
+        match mode {
+            Mode::Snippet => self.snippet = None,
+            Mode::Rename => {
+                self.rename = None;
+                data.status.clear();
+            }
+            Mode::Peek => {
+                self.peek = None;
+                ctx.request_paint();
+            }
+            Mode::Symbols => {
+                self.finder = None;
+                ctx.request_paint();
+            }
+            Mode::Block => {
+                self.block = None;
+                ctx.request_paint();
+            }
+        }
+    }
+
+    /// Select the identifier at the caret to be renamed.
+    fn start_rename(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let range = match data.code.identifier_at(self.selection().active) {
+            Some(range) => range,
+            None => return,
+        };
+        let name = data.code.buffer[range.clone()].to_owned();
+        data.status = format!("Renaming `{}`, press Enter to apply", name);
+        self.set_selection(ctx, Selection::new(range.start, range.end));
+        self.rename = Some(Rename { name, range });
+        self.modes.enter(Mode::Rename);
+    }
+
+    /// Replace all other occurrences of the renamed identifier with its new
+    /// name.
+    fn finish_rename(&mut self, ctx: &mut EventCtx, data: &mut AppState) {
+        let rename = match self.rename.take() {
+            Some(rename) => rename,
+            None => return,
+        };
+        self.modes.leave(Mode::Rename);
+        let new_name = data.code.buffer[rename.range.clone()].to_owned();
+        if new_name.is_empty() || new_name == rename.name {
+            data.status.clear();
+            return;
+        }
+        let occurrences = data.code.identifier_occurrences(&rename.name);
+        let before = occurrences
+            .iter()
+            .filter(|range| range.start < rename.range.start)
+            .count();
+        let shift = before as isize * (new_name.len() as isize - rename.name.len() as isize);
+        data.status = format!(
+            "Renamed {} occurrences of `{}` to `{}`",
+            occurrences.len() + 1,
+            rename.name,
+            new_name
+        );
+        let edits = occurrences
+            .into_iter()
+            .map(|range| (range, new_name.clone()))
+            .collect();
+        data.code.edit_batch(edits);
+        let caret = (rename.range.end as isize + shift) as usize;
+        self.set_selection(ctx, Selection::caret(caret));
+    }
+
+    /// Move to the next tab stop of the current snippet, or expand the snippet
+    /// triggered by the word before the caret.
+    fn next_tab_stop(&mut self, ctx: &mut EventCtx, data: &mut AppState) -> bool {
+        if let Some(ref mut session) = self.snippet {
+            match session.next_stop() {
+                Some(stop) => self.set_selection(ctx, Selection::new(stop.start, stop.end)),
+                None => self.leave(ctx, data, Mode::Snippet),
+            }
+            return true;
+        }
+        let selection = self.selection();
+        if !selection.is_caret() {
+            return false;
+        }
+        let caret = selection.active;
+        let buffer = &data.code.buffer;
+        let language = data.code.language();
+        let start = buffer[..caret]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| language.is_word_char(c))
+            .last()
+            .map_or(caret, |(i, _)| i);
+        let trigger = &buffer[start..caret];
+        let snippet = data
+            .settings
+            .snippets
+            .iter()
+            .find(|snippet| snippet.language == language && snippet.trigger == trigger);
+        let snippet = match snippet {
+            Some(snippet) if !trigger.is_empty() => snippet,
+            _ => return false,
+        };
+        let indent: String = buffer[text::line_start(buffer, start)..]
+            .chars()
+            .take_while(|&c| c == ' ' || c == '\t')
+            .collect();
+        let expansion = snippet::expand(&snippet.body, &indent);
+        data.code.edit(start..caret, expansion.text.clone());
+        self.snippet = Session::new(start, &expansion);
+        if self.snippet.is_some() {
+            self.modes.enter(Mode::Snippet);
+        }
+        let selection = match self.snippet {
+            Some(ref session) => Selection::new(session.current().start, session.current().end),
+            None => Selection::caret(start + expansion.text.len()),
+        };
+        self.set_selection(ctx, selection);
+        true
+    }
+
+    /// Move to the previous tab stop of the current snippet.
+    fn prev_tab_stop(&mut self, ctx: &mut EventCtx) -> bool {
+        match self.snippet {
+            Some(ref mut session) => {
+                let stop = session.prev_stop();
+                self.set_selection(ctx, Selection::new(stop.start, stop.end));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Look up the font family named `name`, falling back to the system monospace
+/// font if the name is empty or the font is not installed.
+fn font_family(text: &mut PietText, name: &str) -> FontFamily {
+    if name.is_empty() {
+        return FontFamily::MONOSPACE;
+    }
+    text.font_family(name).unwrap_or_else(|| {
+        eprintln!("Font `{}` is not installed, using the default font", name);
+        FontFamily::MONOSPACE
+    })
+}
+
+/// `selection` with both ends clamped to `buffer` and moved back to the
+/// start of the character they are in.
+pub fn clamp_selection(buffer: &str, selection: Selection) -> Selection {
+    let clamp = |offset| text::clamp_offset(buffer, offset);
+    Selection::new(clamp(selection.anchor), clamp(selection.active))
+}
+
+/// Build the text layout of a line number label.
+fn label_layout(
+    text: &mut PietText,
+    font: &FontFamily,
+    label: String,
+    color: Color,
+) -> PietTextLayout {
+    text.new_text_layout(label)
+        .font(font.clone(), FONT_SIZE)
+        .text_color(color)
+        .build()
+        .unwrap()
+}
+
+impl Widget<AppState> for Editor {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        let selection = self.selection();
+        let revision = data.code.revision();
+        match event {
+            Event::KeyDown(key) if self.handle_key(ctx, key, data) => ctx.set_handled(),
+            Event::MouseDown(mouse) if mouse.pos.x < self.gutter_width => {
+                self.click_gutter(ctx, mouse, data, env);
+                ctx.set_handled();
+            }
+            Event::MouseMove(mouse)
+                if mouse.pos.x < self.gutter_width && data.settings.blame == BlameMode::Hover =>
+            {
+                // Hovering a line number shows the commit that last changed
+                // the line.
+                let line = self.gutter_line(&data.code, mouse.pos.y, env);
+                if let Some(commit) = line.and_then(|line| data.blame.get(line)?.as_ref()) {
+                    data.status = commit.label();
+                }
+                ctx.set_handled();
+            }
+            Event::MouseDown(_) | Event::MouseMove(_) | Event::MouseUp(_)
+                if self.block_mouse(ctx, event, data, env) =>
+            {
+                ctx.set_handled()
+            }
+            Event::Paste(clipboard)
+                if data.settings.reindent_on_paste
+                    || data.settings.normalize_pasted_line_endings =>
+            {
+                if let Some(pasted) = clipboard.get_string() {
+                    self.paste(ctx, data, &pasted);
+                    ctx.set_handled();
+                }
+            }
+            Event::Command(cmd) if cmd.is(SET_CARET) => {
+                let offset = text::clamp_offset(&data.code.buffer, *cmd.get_unchecked(SET_CARET));
+                // The text may have just been replaced, so it is laid out
+                // before scrolling.
+                if self.select(ctx, Selection::caret(offset)) {
+                    ctx.submit_command(SCROLL_TO_CARET.to(ctx.widget_id()));
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(SET_SELECTION) => {
+                let selection = *cmd.get_unchecked(SET_SELECTION);
+                let selection = clamp_selection(&data.code.buffer, selection);
+                if self.select(ctx, selection) {
+                    ctx.submit_command(SCROLL_TO_CARET.to(ctx.widget_id()));
+                }
+                ctx.set_handled();
+            }
+            Event::Command(cmd) if cmd.is(SCROLL_TO_CARET) => {
+                self.scroll_to_caret(ctx);
+                ctx.set_handled();
+            }
+            _ => {
+                let len = data.code.len();
+                self.textbox.event(ctx, event, &mut data.code, env);
+                if self.selection() != selection || data.code.len() != len {
+                    self.scroll_to_caret(ctx);
+                    if self.peek.is_some() {
+                        self.leave(ctx, data, Mode::Peek);
+                    }
+                }
+                // Keep the tab stops of a snippet and the identifier being
+                // renamed in place while the user types.
+                let delta = data.code.len() as isize - len as isize;
+                if delta != 0 {
+                    let caret = self.selection().active;
+                    if let Some(ref mut session) = self.snippet {
+                        if !session.edited(caret, delta) {
+                            self.leave(ctx, data, Mode::Snippet);
+                        }
+                    }
+                    if let Some(ref mut rename) = self.rename {
+                        let end = (rename.range.end as isize + delta) as usize;
+                        if caret < rename.range.start || caret > end {
+                            self.leave(ctx, data, Mode::Rename);
+                        } else {
+                            rename.range.end = end;
+                        }
+                    }
+                }
+                if delta == 1
+                    && data.settings.dedent_clauses
+                    && self.snippet.is_none()
+                    && self.rename.is_none()
+                {
+                    self.dedent_clause(ctx, data);
+                }
+            }
+        }
+        // Edits made through the editor can restore the selection on undo.
+        if data.code.revision() != revision {
+            data.code.record_selections(selection, self.selection());
+        }
+        // With a split view, only the editor being used reports its
+        // selection.
+        if ctx.has_focus() || self.selection() != selection {
+            data.selection = self.selection();
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.set_font(ctx.text(), &data.settings.font_family);
+            self.typewriter = data.settings.typewriter_scrolling;
+            self.scroll_margin = data.settings.scroll_margin;
+        }
+        self.textbox.lifecycle(ctx, event, &data.code, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        self.textbox.update(ctx, &data.code, env);
+        self.typewriter = data.settings.typewriter_scrolling;
+        self.scroll_margin = data.settings.scroll_margin;
+        if old_data.settings.font_family != data.settings.font_family {
+            self.set_font(ctx.text(), &data.settings.font_family);
+            ctx.request_layout();
+        }
+        if !old_data.code.same(&data.code)
+            || !old_data.blame.same(&data.blame)
+            || old_data.settings.blame != data.settings.blame
+        {
+            // The gutter may have to grow to fit more line numbers or the
+            // commits of the lines.
+            ctx.request_layout();
+        }
+        if !old_data.selection.same(&data.selection)
+            || old_data.settings.show_ruler != data.settings.show_ruler
+            || old_data.settings.ruler_column != data.settings.ruler_column
+            || old_data.settings.highlight_long_lines != data.settings.highlight_long_lines
+            || !old_data
+                .settings
+                .line_numbers
+                .same(&data.settings.line_numbers)
+            || !old_data.theme.same(&data.theme)
+        {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        env: &Env,
+    ) -> Size {
+        let line_count = data.code.line_count();
+        let widest_label = "0".repeat(line_count.to_string().len());
+        let widest_label = label_layout(ctx.text(), &self.font, widest_label, Color::WHITE);
+        self.gutter_width = widest_label.size().width + 2.0 * GUTTER_PADDING;
+        if Self::shows_blame(data) {
+            let chars = data.blame.iter().flatten();
+            let chars = chars.map(|commit| commit.label().chars().count()).max();
+            let widest_commit = "0".repeat(chars.unwrap_or(0));
+            let widest_commit = label_layout(ctx.text(), &self.font, widest_commit, Color::WHITE);
+            self.gutter_width += widest_commit.size().width + GUTTER_PADDING;
+        }
+
+        let text_bc = bc.shrink((self.gutter_width, 0.0));
+        let size = self.textbox.layout(ctx, &text_bc, &data.code, env);
+        let origin = Point::new(self.gutter_width, 0.0);
+        self.textbox.set_origin(ctx, &data.code, env, origin);
+        Size::new(size.width + self.gutter_width, size.height)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        self.paint_gutter(ctx, data, env);
+        self.textbox.paint(ctx, &data.code, env);
+        self.paint_capture_backgrounds(ctx, data, env);
+        self.paint_conflicts(ctx, data, env);
+        self.paint_diagnostics(ctx, data, env);
+        self.paint_ruler(ctx, data, env);
+        if let Some(ref peek) = self.peek {
+            self.paint_peek(ctx, data, peek);
+        }
+        if let Some(ref block) = self.block {
+            self.paint_block(ctx, data, env, block);
+        }
+        if let Some(ref finder) = self.finder {
+            self.paint_finder(ctx, data, finder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clamp_selection_to_buffer() {
+        let selection = clamp_selection("aé", Selection::new(10, 2));
+        assert_eq!(selection, Selection::new(3, 1));
+    }
+}
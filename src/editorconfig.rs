@@ -0,0 +1,301 @@
+//! Reading of [EditorConfig](https://editorconfig.org) files, which set the
+//! indentation and whitespace conventions of a project.
+//!
+//! Only the properties the editor supports are read. Glob patterns support
+//! `*`, `**`, `?`, `[...]`, `{a,b}` and numeric ranges like `{1..3}`.
+
+use std::fs;
+use std::path::Path;
+
+use crate::settings::Settings;
+
+/// The supported properties that apply to a file. `None` if no section
+/// matching the file sets them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    pub indent_with_tabs: Option<bool>,
+    pub indent_size: Option<usize>,
+    pub insert_final_newline: Option<bool>,
+    pub trim_trailing_whitespace: Option<bool>,
+    /// The lowercase charset, e.g. `utf-8` or `utf-8-bom`.
+    pub charset: Option<String>,
+}
+
+impl Properties {
+    /// The properties that apply to the file at `path`, from the
+    /// `.editorconfig` files in its directory and the directories above, up
+    /// to the first one declaring `root = true`. Closer files take
+    /// precedence.
+    pub fn resolve(path: &Path) -> Self {
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            if let Ok(source) = fs::read_to_string(dir.join(".editorconfig")) {
+                let root = is_root(&source);
+                files.push((dir.to_owned(), source));
+                if root {
+                    break;
+                }
+            }
+        }
+        let mut properties = Properties::default();
+        for (dir, source) in files.iter().rev() {
+            let relative = match path.strip_prefix(dir) {
+                Ok(relative) => relative.to_string_lossy().replace('\\', "/"),
+                Err(_) => continue,
+            };
+            properties.read(source, &relative);
+        }
+        properties
+    }
+
+    /// Read the properties of the sections of the EditorConfig file `source`
+    /// matching `path`, relative to the file's directory with `/` separators.
+    pub fn read(&mut self, source: &str, path: &str) {
+        let mut matches = false;
+        for line in source.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                matches = section_matches(section, path);
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if matches => (key.trim().to_lowercase(), value.trim()),
+                _ => continue,
+            };
+            let value = value.to_lowercase();
+            match key.as_str() {
+                "indent_style" => self.indent_with_tabs = Some(value == "tab"),
+                "indent_size" | "tab_width" => {
+                    if let Ok(size) = value.parse() {
+                        self.indent_size = Some(size);
+                    }
+                }
+                "insert_final_newline" => self.insert_final_newline = parse_bool(&value),
+                "trim_trailing_whitespace" => self.trim_trailing_whitespace = parse_bool(&value),
+                "charset" => self.charset = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    /// Override the settings the properties set.
+    pub fn apply(&self, settings: &mut Settings) {
+        if let Some(indent_with_tabs) = self.indent_with_tabs {
+            settings.indent_with_tabs = indent_with_tabs;
+        }
+        if let Some(indent_size) = self.indent_size {
+            settings.tab_width = indent_size;
+        }
+        if let Some(insert_final_newline) = self.insert_final_newline {
+            settings.ensure_final_newline = insert_final_newline;
+        }
+        if let Some(trim_trailing_whitespace) = self.trim_trailing_whitespace {
+            settings.trim_trailing_whitespace = trim_trailing_whitespace;
+        }
+    }
+}
+
+/// Returns `true` if the preamble of `source`, before the first section,
+/// declares `root = true`.
+fn is_root(source: &str) -> bool {
+    source
+        .lines()
+        .map(str::trim)
+        .take_while(|line| !line.starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .any(|(key, value)| {
+            key.trim().eq_ignore_ascii_case("root") && value.trim().eq_ignore_ascii_case("true")
+        })
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
+
+/// Returns `true` if the section glob `section` matches `path`. Globs without
+/// a `/` match the file name in any directory.
+fn section_matches(section: &str, path: &str) -> bool {
+    let pattern = match section.strip_prefix('/') {
+        Some(anchored) => anchored.to_owned(),
+        None if section.contains('/') => section.to_owned(),
+        None => format!("**/{}", section),
+    };
+    expand_braces(&pattern)
+        .iter()
+        .any(|pattern| glob_match(pattern.as_bytes(), path.as_bytes()))
+}
+
+/// The patterns `pattern` stands for with each `{a,b}` group and each
+/// numeric range `{1..3}` expanded.
+fn expand_braces(pattern: &str) -> Vec<String> {
+    let open = match pattern.find('{') {
+        Some(open) => open,
+        None => return vec![pattern.to_owned()],
+    };
+    let close = match pattern[open..].find('}') {
+        Some(close) => open + close,
+        None => return vec![pattern.to_owned()],
+    };
+    let (head, tail) = (&pattern[..open], &pattern[close + 1..]);
+    let group = &pattern[open + 1..close];
+    let alternatives: Vec<String> = match numeric_range(group) {
+        Some((from, to)) => (from.min(to)..=from.max(to))
+            .map(|n| n.to_string())
+            .collect(),
+        None => group.split(',').map(str::to_owned).collect(),
+    };
+    alternatives
+        .iter()
+        .flat_map(|alternative| expand_braces(&format!("{}{}{}", head, alternative, tail)))
+        .collect()
+}
+
+/// The bounds of the numeric range `group`, e.g. `1..3`, in either order.
+fn numeric_range(group: &str) -> Option<(i64, i64)> {
+    let (from, to) = group.split_once("..")?;
+    Some((from.parse().ok()?, to.parse().ok()?))
+}
+
+/// Match `path` against a glob `pattern` without braces.
+fn glob_match(pattern: &[u8], path: &[u8]) -> bool {
+    match pattern {
+        [] => path.is_empty(),
+        [b'*', b'*', b'/', rest @ ..] => {
+            // `**/` also matches no directory at all.
+            glob_match(rest, path)
+                || (0..path.len()).any(|i| path[i] == b'/' && glob_match(rest, &path[i + 1..]))
+        }
+        [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| glob_match(rest, &path[i..])),
+        [b'*', rest @ ..] => (0..=path.len())
+            .take_while(|&i| i == 0 || path[i - 1] != b'/')
+            .any(|i| glob_match(rest, &path[i..])),
+        [b'?', rest @ ..] => match path {
+            [c, path @ ..] if *c != b'/' => glob_match(rest, path),
+            _ => false,
+        },
+        [b'[', class @ ..] => match (class.iter().position(|&b| b == b']'), path) {
+            (Some(end), [c, path @ ..]) => {
+                let (negated, set) = match &class[..end] {
+                    [b'!', set @ ..] => (true, set),
+                    set => (false, set),
+                };
+                class_contains(set, *c) != negated && glob_match(&class[end + 1..], path)
+            }
+            (None, [b'[', path @ ..]) => glob_match(class, path),
+            _ => false,
+        },
+        [c, rest @ ..] => match path {
+            [p, path @ ..] if p == c => glob_match(rest, path),
+            _ => false,
+        },
+    }
+}
+
+/// Returns `true` if the character class `set`, e.g. `a-z_`, contains `c`.
+fn class_contains(set: &[u8], c: u8) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == b'-' {
+            if (set[i]..=set[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs() {
+        assert!(section_matches("*.py", "src/main.py"));
+        assert!(!section_matches("/*.py", "src/main.py"));
+        assert!(section_matches("src/*.py", "src/main.py"));
+        assert!(!section_matches("src/*.py", "src/lib/util.py"));
+        assert!(section_matches("src/**/util.py", "src/util.py"));
+        assert!(section_matches("src/**/util.py", "src/lib/util.py"));
+        assert!(section_matches("file?.txt", "file1.txt"));
+        assert!(!section_matches("/?.txt", "a/b.txt"));
+        assert!(section_matches("[a-c_].js", "b.js"));
+        assert!(section_matches("[!a-c].js", "d.js"));
+        assert!(!section_matches("[!a-c].js", "a.js"));
+        assert!(section_matches("*.{js,py}", "app.py"));
+        assert!(!section_matches("*.{js,py}", "app.rs"));
+        assert!(section_matches("part{1..3}.txt", "part2.txt"));
+        assert!(!section_matches("part{1..3}.txt", "part4.txt"));
+    }
+
+    #[test]
+    fn braces() {
+        assert_eq!(expand_braces("{a,b}.{c,d}"), ["a.c", "a.d", "b.c", "b.d"]);
+        assert_eq!(expand_braces("v{3..1}"), ["v1", "v2", "v3"]);
+        assert_eq!(expand_braces("{-1..1}"), ["-1", "0", "1"]);
+        assert_eq!(expand_braces("{a"), ["{a"]);
+    }
+
+    #[test]
+    fn classes() {
+        assert!(class_contains(b"a-z_", b'q'));
+        assert!(class_contains(b"a-z_", b'_'));
+        assert!(!class_contains(b"a-z_", b'-'));
+        assert!(class_contains(b"-a", b'-'));
+    }
+
+    #[test]
+    fn later_sections_override() {
+        let source = "[*]\nindent_style = space\nindent_size = 4\n\n[*.py]\nindent_size = 2\n";
+        let mut properties = Properties::default();
+        properties.read(source, "lib/app.py");
+        assert_eq!(properties.indent_size, Some(2));
+        assert_eq!(properties.indent_with_tabs, Some(false));
+        let mut properties = Properties::default();
+        properties.read(source, "lib/app.js");
+        assert_eq!(properties.indent_size, Some(4));
+    }
+
+    #[test]
+    fn root() {
+        assert!(is_root("# top-most\nroot = true\n\n[*]\nindent_size = 2\n"));
+        assert!(is_root("ROOT = True"));
+        assert!(!is_root("[*]\nroot = true\n"));
+        assert!(!is_root("root = false\n"));
+    }
+
+    #[test]
+    fn resolve_stops_at_root() {
+        let outer =
+            std::env::temp_dir().join(format!("lyranos-editorconfig-{}", std::process::id()));
+        let inner = outer.join("project");
+        fs::create_dir_all(&inner).unwrap();
+        fs::write(
+            outer.join(".editorconfig"),
+            "[*]\ncharset = latin1\nindent_size = 8\n",
+        )
+        .unwrap();
+        fs::write(
+            inner.join(".editorconfig"),
+            "root = true\n[*.py]\nindent_size = 2\n",
+        )
+        .unwrap();
+        let properties = Properties::resolve(&inner.join("app.py"));
+        fs::remove_dir_all(&outer).unwrap();
+        assert_eq!(properties.indent_size, Some(2));
+        assert_eq!(properties.charset, None);
+    }
+}
@@ -0,0 +1,95 @@
+//! Finding the definitions of a document by fuzzy matching their names.
+
+use crate::codetext::Symbol;
+
+/// The maximum number of definitions listed by the finder.
+pub const MAX_RESULTS: usize = 10;
+
+/// How well `candidate` matches `pattern`, higher being better, or `None` if
+/// the characters of `pattern` do not all occur in `candidate` in order,
+/// ignoring case.
+///
+/// Consecutive characters, characters starting a word (after `_` or at a
+/// lowercase to uppercase change) and matching case score higher, gaps
+/// between the characters and unmatched characters score lower.
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<i64> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let same = |a: char, b: char| a == b || a.to_lowercase().eq(b.to_lowercase());
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for p in pattern.chars() {
+        let i = next + chars[next..].iter().position(|&c| same(p, c))?;
+        score += 1;
+        if p == chars[i] {
+            score += 1;
+        }
+        if i == 0 {
+            score += 10;
+        } else if chars[i - 1] == '_' || (chars[i - 1].is_lowercase() && chars[i].is_uppercase()) {
+            score += 8;
+        }
+        match previous {
+            Some(previous) if previous + 1 == i => score += 5,
+            Some(previous) => score -= (i - previous - 1) as i64,
+            None => score -= i as i64,
+        }
+        previous = Some(i);
+        next = i + 1;
+    }
+    Some(score - (chars.len() - pattern.chars().count()) as i64 / 4)
+}
+
+/// The indices of the `candidates` matching `pattern`, best match first and
+/// in their original order among equally good matches.
+pub fn rank(pattern: &str, candidates: &[&str]) -> Vec<usize> {
+    let mut scored: Vec<(i64, usize)> = candidates
+        .iter()
+        .enumerate()
+        .filter_map(|(i, candidate)| Some((fuzzy_score(pattern, candidate)?, i)))
+        .collect();
+    scored.sort_by_key(|&(score, i)| (-score, i));
+    scored.into_iter().map(|(_, i)| i).collect()
+}
+
+/// The state of the overlay to go to a definition by name.
+#[derive(Debug, Default)]
+pub struct SymbolFinder {
+    /// The text typed to filter the definitions.
+    pub query: String,
+    /// The index of the highlighted definition among the matches.
+    pub selected: usize,
+}
+
+impl SymbolFinder {
+    /// The definitions among `symbols` matching the query, best first, at
+    /// most [`MAX_RESULTS`].
+    pub fn matches<'a>(&self, symbols: &'a [Symbol]) -> Vec<&'a Symbol> {
+        let names: Vec<&str> = symbols.iter().map(|symbol| symbol.name.as_str()).collect();
+        rank(&self.query, &names)
+            .into_iter()
+            .take(MAX_RESULTS)
+            .map(|i| &symbols[i])
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rank_sample_symbols() {
+        // The definitions of the sample document.
+        let names = ["scope_test", "do_local", "do_nonlocal", "do_global"];
+        for (query, best) in [
+            ("dl", "do_local"),
+            ("dn", "do_nonlocal"),
+            ("st", "scope_test"),
+        ] {
+            let ranked = rank(query, &names);
+            assert_eq!(ranked.first().map(|&i| names[i]), Some(best), "{}", query);
+        }
+        assert!(rank("xyz", &names).is_empty());
+    }
+}
@@ -0,0 +1,43 @@
+//! Running external code formatters.
+//!
+//! A formatter is a command reading a document from standard input and
+//! writing it formatted to standard output, e.g. `black -q -` for Python.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Format `source` with the formatter `command`, a program followed by its
+/// arguments, separated by whitespace.
+///
+/// Returns an error describing why formatting failed, with the formatter's
+/// error output if it exited unsuccessfully.
+pub fn format(command: &str, source: &str) -> Result<String, String> {
+    let mut words = command.split_whitespace();
+    let program = words.next().ok_or("the formatter command is empty")?;
+    let mut child = Command::new(program)
+        .args(words)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to run `{}`: {}", program, err))?;
+    // Writing from another thread keeps a formatter that starts writing
+    // before it has read everything from blocking on a full pipe.
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let input = source.to_owned();
+    let writer = thread::spawn(move || -> io::Result<()> { stdin.write_all(input.as_bytes()) });
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("failed to run `{}`: {}", program, err))?;
+    let written = writer.join().unwrap_or(Ok(()));
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(match stderr.trim() {
+            "" => format!("`{}` failed with {}", program, output.status),
+            message => format!("`{}` failed: {}", program, message),
+        });
+    }
+    written.map_err(|err| format!("failed to write to `{}`: {}", program, err))?;
+    String::from_utf8(output.stdout).map_err(|_| format!("`{}` wrote invalid UTF-8", program))
+}
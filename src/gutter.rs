@@ -0,0 +1,100 @@
+//! The line number gutter of the editor.
+
+use std::str::FromStr;
+
+use druid::Data;
+
+/// How line numbers are displayed in the gutter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum LineNumbers {
+    /// The number of each line, counting from one.
+    Absolute,
+    /// The distance of each line from the caret line.
+    Relative,
+    /// Like `Relative`, but the caret line shows its absolute number.
+    Hybrid,
+}
+
+impl FromStr for LineNumbers {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(LineNumbers::Absolute),
+            "relative" => Ok(LineNumbers::Relative),
+            "hybrid" => Ok(LineNumbers::Hybrid),
+            _ => Err(()),
+        }
+    }
+}
+
+/// What clicking a line number does. Clicking with Ctrl held always toggles
+/// a bookmark.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum GutterClick {
+    /// Select the whole line, including its line break.
+    SelectLine,
+    /// Add a bookmark to the line or remove the one it has.
+    ToggleBookmark,
+    /// Nothing.
+    Nothing,
+}
+
+impl FromStr for GutterClick {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "select_line" => Ok(GutterClick::SelectLine),
+            "toggle_bookmark" => Ok(GutterClick::ToggleBookmark),
+            "nothing" => Ok(GutterClick::Nothing),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The zero-based line at height `y`, given the top of every line in order.
+/// Each line extends to the top of the next, so wrapped lines include all
+/// their rows. Heights above the first line belong to it and heights below
+/// the last line to the last.
+pub fn line_at(tops: &[f64], y: f64) -> usize {
+    tops.partition_point(|&top| top <= y).saturating_sub(1)
+}
+
+/// The label of the zero-based `line` when the caret is on `caret_line`.
+pub fn label(line: usize, caret_line: usize, mode: LineNumbers) -> String {
+    let distance = if line > caret_line {
+        line - caret_line
+    } else {
+        caret_line - line
+    };
+    match mode {
+        LineNumbers::Relative => distance.to_string(),
+        LineNumbers::Hybrid if distance > 0 => distance.to_string(),
+        _ => (line + 1).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_at_height() {
+        // Line tops as laid out with the second line wrapped onto two rows.
+        let tops = [5.0, 20.0, 50.0];
+        for (y, line) in [(0.0, 0), (19.0, 0), (20.0, 1), (49.0, 1), (200.0, 2)] {
+            assert_eq!(line_at(&tops, y), line, "at {}", y);
+        }
+        assert_eq!(line_at(&[], 10.0), 0);
+    }
+
+    #[test]
+    fn labels() {
+        assert_eq!(label(4, 2, LineNumbers::Absolute), "5");
+        assert_eq!(label(4, 2, LineNumbers::Relative), "2");
+        assert_eq!(label(2, 2, LineNumbers::Relative), "0");
+        assert_eq!(label(2, 2, LineNumbers::Hybrid), "3");
+        assert_eq!(label(0, 2, LineNumbers::Hybrid), "2");
+    }
+}
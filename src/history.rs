@@ -0,0 +1,143 @@
+//! Undo and redo history of a document.
+
+use druid::text::Selection;
+
+/// The number of undo steps kept when no other limit is configured.
+pub const DEFAULT_LIMIT: usize = 1000;
+
+/// The replacement of `old` at `start` by `new`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub start: usize,
+    pub old: String,
+    pub new: String,
+}
+
+impl Edit {
+    /// The edit reverting this one.
+    pub fn inverse(&self) -> Edit {
+        Edit {
+            start: self.start,
+            old: self.new.clone(),
+            new: self.old.clone(),
+        }
+    }
+}
+
+/// Edits undone in one step, with the selections before and after them if
+/// they are known.
+#[derive(Debug, Clone)]
+struct Group {
+    edits: Vec<Edit>,
+    before: Option<Selection>,
+    after: Option<Selection>,
+}
+
+/// Undo and redo stacks of edit groups, each undone in one step.
+///
+/// Consecutive single character insertions are coalesced into one group up to
+/// the end of a word, so that undoing after typing removes a word rather than
+/// a character.
+#[derive(Debug)]
+pub struct History {
+    undo: Vec<Group>,
+    redo: Vec<Group>,
+    limit: usize,
+    /// Whether the last undo group is typed text that may be extended.
+    typing: bool,
+    /// The number of edits recorded so far, to tell if there were any.
+    revision: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> Self {
+        History {
+            undo: Vec::new(),
+            redo: Vec::new(),
+            limit,
+            typing: false,
+            revision: 0,
+        }
+    }
+
+    /// The number of edits recorded so far.
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    /// Record an edit made by the user.
+    pub fn record(&mut self, edit: Edit) {
+        self.revision += 1;
+        let typed = edit.old.is_empty() && edit.new.chars().count() == 1;
+        if typed && self.typing {
+            if let Some(last) = self
+                .undo
+                .last_mut()
+                .and_then(|group| group.edits.last_mut())
+            {
+                let follows = last.start + last.new.len() == edit.start;
+                // Start a new group with the first character of a new word.
+                let new_word = last.new.ends_with(char::is_whitespace)
+                    && !edit.new.starts_with(char::is_whitespace);
+                if follows && !new_word {
+                    last.new.push_str(&edit.new);
+                    self.redo.clear();
+                    return;
+                }
+            }
+        }
+        self.push(vec![edit]);
+        self.typing = typed;
+    }
+
+    /// Record edits that are undone together, in the order they were applied.
+    pub fn record_group(&mut self, edits: Vec<Edit>) {
+        if !edits.is_empty() {
+            self.revision += 1;
+            self.push(edits);
+            self.typing = false;
+        }
+    }
+
+    /// Record the selections before and after the edits of the last group
+    /// made since they were last recorded. A group extended by typing keeps
+    /// the selection from before its first edit.
+    pub fn record_selections(&mut self, before: Selection, after: Selection) {
+        if let Some(group) = self.undo.last_mut() {
+            group.before.get_or_insert(before);
+            group.after = Some(after);
+        }
+    }
+
+    fn push(&mut self, edits: Vec<Edit>) {
+        self.undo.push(Group {
+            edits,
+            before: None,
+            after: None,
+        });
+        if self.undo.len() > self.limit {
+            self.undo.remove(0);
+        }
+        self.redo.clear();
+    }
+
+    /// The edits to apply, in order, to undo the last group, and the
+    /// selection before the group if it is known.
+    pub fn undo(&mut self) -> Option<(Vec<Edit>, Option<Selection>)> {
+        let group = self.undo.pop()?;
+        let inverse = group.edits.iter().rev().map(Edit::inverse).collect();
+        let before = group.before;
+        self.redo.push(group);
+        self.typing = false;
+        Some((inverse, before))
+    }
+
+    /// The edits to apply, in order, to redo the last undone group, and the
+    /// selection after the group if it is known.
+    pub fn redo(&mut self) -> Option<(Vec<Edit>, Option<Selection>)> {
+        let group = self.redo.pop()?;
+        self.undo.push(group.clone());
+        self.typing = false;
+        Some((group.edits, group.after))
+    }
+}
@@ -0,0 +1,332 @@
+//! Sorting of Python import statements, similar to isort.
+//!
+//! Imports are grouped into `__future__` imports, the standard library,
+//! third-party packages and relative imports of the current package, in that
+//! order and separated by blank lines. Whether a module is part of the
+//! standard library is looked up in a list of its top-level modules, so
+//! first-party absolute imports are sorted with the third-party packages.
+
+/// The top-level modules of the Python 3 standard library.
+const STDLIB: &[&str] = &[
+    "abc",
+    "antigravity",
+    "argparse",
+    "array",
+    "ast",
+    "asyncio",
+    "atexit",
+    "base64",
+    "binascii",
+    "bisect",
+    "builtins",
+    "bz2",
+    "calendar",
+    "cmath",
+    "codecs",
+    "collections",
+    "colorsys",
+    "concurrent",
+    "configparser",
+    "contextlib",
+    "contextvars",
+    "copy",
+    "copyreg",
+    "cProfile",
+    "csv",
+    "ctypes",
+    "curses",
+    "dataclasses",
+    "datetime",
+    "dbm",
+    "decimal",
+    "difflib",
+    "dis",
+    "doctest",
+    "email",
+    "encodings",
+    "enum",
+    "errno",
+    "faulthandler",
+    "fcntl",
+    "filecmp",
+    "fileinput",
+    "fnmatch",
+    "fractions",
+    "ftplib",
+    "functools",
+    "gc",
+    "getopt",
+    "getpass",
+    "gettext",
+    "glob",
+    "graphlib",
+    "grp",
+    "gzip",
+    "hashlib",
+    "heapq",
+    "hmac",
+    "html",
+    "http",
+    "imaplib",
+    "importlib",
+    "inspect",
+    "io",
+    "ipaddress",
+    "itertools",
+    "json",
+    "keyword",
+    "linecache",
+    "locale",
+    "logging",
+    "lzma",
+    "mailbox",
+    "marshal",
+    "math",
+    "mimetypes",
+    "mmap",
+    "multiprocessing",
+    "netrc",
+    "numbers",
+    "operator",
+    "optparse",
+    "os",
+    "pathlib",
+    "pdb",
+    "pickle",
+    "pkgutil",
+    "platform",
+    "plistlib",
+    "poplib",
+    "posix",
+    "pprint",
+    "profile",
+    "pstats",
+    "pty",
+    "pwd",
+    "queue",
+    "random",
+    "re",
+    "readline",
+    "reprlib",
+    "resource",
+    "runpy",
+    "sched",
+    "secrets",
+    "select",
+    "selectors",
+    "shelve",
+    "shlex",
+    "shutil",
+    "signal",
+    "site",
+    "smtplib",
+    "socket",
+    "socketserver",
+    "sqlite3",
+    "ssl",
+    "stat",
+    "statistics",
+    "string",
+    "stringprep",
+    "struct",
+    "subprocess",
+    "symtable",
+    "sys",
+    "sysconfig",
+    "syslog",
+    "tarfile",
+    "tempfile",
+    "termios",
+    "textwrap",
+    "this",
+    "threading",
+    "time",
+    "timeit",
+    "tkinter",
+    "token",
+    "tokenize",
+    "tomllib",
+    "trace",
+    "traceback",
+    "tracemalloc",
+    "tty",
+    "turtle",
+    "types",
+    "typing",
+    "unicodedata",
+    "unittest",
+    "urllib",
+    "uuid",
+    "venv",
+    "warnings",
+    "wave",
+    "weakref",
+    "webbrowser",
+    "winreg",
+    "wsgiref",
+    "xml",
+    "xmlrpc",
+    "zipapp",
+    "zipfile",
+    "zipimport",
+    "zlib",
+    "zoneinfo",
+];
+
+/// The groups imports are sorted into, in the order they are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Group {
+    Future,
+    Stdlib,
+    ThirdParty,
+    Local,
+}
+
+/// An import statement with the comment lines directly above it.
+struct Import<'a> {
+    /// The comment lines and the lines of the statement.
+    lines: Vec<&'a str>,
+    /// Whether the statement is of the form `from module import name`.
+    is_from: bool,
+    module: &'a str,
+}
+
+impl<'a> Import<'a> {
+    fn group(&self) -> Group {
+        let top_level = self.module.split('.').next().unwrap_or("");
+        if self.module == "__future__" {
+            Group::Future
+        } else if self.module.starts_with('.') {
+            Group::Local
+        } else if STDLIB.contains(&top_level) {
+            Group::Stdlib
+        } else {
+            Group::ThirdParty
+        }
+    }
+}
+
+/// Sort the lines of `block`, consisting of Python import statements,
+/// comments and blank lines, into groups as described in the
+/// [module documentation](self), alphabetically by module within each group
+/// with `import` statements before `from` imports.
+///
+/// Comment lines stay attached to the statement below them, comments at the
+/// end of the block stay at the end, and statements continued over several
+/// lines with parentheses or backslashes are moved as a whole. The names
+/// within a statement are not sorted.
+pub fn sort_imports(block: &str) -> String {
+    let eol = if block.contains("\r\n") { "\r\n" } else { "\n" };
+    let mut imports = Vec::new();
+    let mut comments = Vec::new();
+    let mut lines = block.lines();
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed.starts_with('#') {
+            comments.push(line);
+            continue;
+        }
+        let mut statement = std::mem::take(&mut comments);
+        statement.push(line);
+        let mut depth = paren_depth(line);
+        let mut last = line;
+        while depth > 0 || last.trim_end().ends_with('\\') {
+            match lines.next() {
+                Some(next) => {
+                    depth += paren_depth(next);
+                    statement.push(next);
+                    last = next;
+                }
+                None => break,
+            }
+        }
+        let (is_from, module) = match trimmed.strip_prefix("from ") {
+            Some(rest) => (true, rest.split_whitespace().next().unwrap_or("")),
+            None => {
+                let rest = trimmed.strip_prefix("import").unwrap_or(trimmed);
+                (
+                    false,
+                    rest.split(&[',', '(', ' '][..])
+                        .find(|s| !s.is_empty())
+                        .unwrap_or(""),
+                )
+            }
+        };
+        imports.push(Import {
+            lines: statement,
+            is_from,
+            module,
+        });
+    }
+    imports.sort_by_cached_key(|import| {
+        (
+            import.group(),
+            import.is_from,
+            import.module.to_lowercase(),
+            import.lines.join("\n"),
+        )
+    });
+    let mut sorted = Vec::new();
+    for (i, import) in imports.iter().enumerate() {
+        if i > 0 && imports[i - 1].group() != import.group() {
+            sorted.push("");
+        }
+        sorted.extend(&import.lines);
+    }
+    sorted.extend(comments);
+    sorted.join(eol)
+}
+
+/// The number of parentheses opened and not closed on `line`, ignoring a
+/// trailing comment.
+fn paren_depth(line: &str) -> isize {
+    let code = line.split('#').next().unwrap_or(line);
+    code.matches('(').count() as isize - code.matches(')').count() as isize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups() {
+        let block = "import requests\nfrom . import sibling\nimport sys\n\
+                     from __future__ import annotations\nimport os.path\nfrom os import sep";
+        assert_eq!(
+            sort_imports(block),
+            "from __future__ import annotations\n\nimport os.path\nimport sys\n\
+             from os import sep\n\nimport requests\n\nfrom . import sibling"
+        );
+    }
+
+    #[test]
+    fn relative_imports() {
+        let block = "from .b import x\nfrom ..a import y\nfrom . import z";
+        assert_eq!(
+            sort_imports(block),
+            "from . import z\nfrom ..a import y\nfrom .b import x"
+        );
+    }
+
+    #[test]
+    fn comments_stay_attached() {
+        let block = "import sys\n# Needed for paths\nimport os  # os.path\n# The end";
+        assert_eq!(
+            sort_imports(block),
+            "# Needed for paths\nimport os  # os.path\nimport sys\n# The end"
+        );
+    }
+
+    #[test]
+    fn continuations() {
+        let block = "from typing import (\n    List,  # (\n    Dict,\n)\n\
+                     from abc import \\\n    ABC\nimport json";
+        assert_eq!(
+            sort_imports(block),
+            "import json\nfrom abc import \\\n    ABC\nfrom typing import (\n    List,  # (\n    Dict,\n)"
+        );
+    }
+}
@@ -0,0 +1,215 @@
+//! Languages supported by the editor.
+
+use std::path::Path;
+
+use druid::Data;
+
+/// The number of lines at the start and at the end of a document that are
+/// searched for a modeline.
+const MODELINE_LINES: usize = 5;
+
+/// The regions of Python code that can be folded, as tree-sitter-python does
+/// not ship a folds query.
+const PYTHON_FOLD_QUERY: &str = "[
+  (function_definition)
+  (class_definition)
+  (decorated_definition)
+  (if_statement)
+  (for_statement)
+  (while_statement)
+  (with_statement)
+  (try_statement)
+  (import_from_statement)
+  (parameters)
+  (argument_list)
+  (list)
+  (tuple)
+  (set)
+  (dictionary)
+  (list_comprehension)
+  (set_comprehension)
+  (dictionary_comprehension)
+  (generator_expression)
+  (string)
+] @fold
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum Language {
+    Python,
+    JavaScript,
+}
+
+impl Language {
+    /// Look up a language by its lowercase name, as used in configuration and
+    /// modelines.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "python" | "py" => Some(Language::Python),
+            "javascript" | "js" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+
+    /// The lowercase name of the language, as used in configuration.
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Python => "python",
+            Language::JavaScript => "javascript",
+        }
+    }
+
+    /// Look up a language by the extension of `path`.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "py" | "pyi" | "pyw" => Some(Language::Python),
+            "js" | "mjs" | "cjs" | "jsx" => Some(Language::JavaScript),
+            _ => None,
+        }
+    }
+
+    /// The language of the document at `path` with the contents `text`. A
+    /// modeline in the document overrides the language implied by the file
+    /// extension. Defaults to Python.
+    pub fn detect(path: &Path, text: &str) -> Self {
+        detect_modeline_language(text)
+            .or_else(|| Language::from_path(path))
+            .unwrap_or(Language::Python)
+    }
+
+    /// The tree-sitter grammar of the language.
+    pub fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Language::Python => tree_sitter_python::language(),
+            Language::JavaScript => tree_sitter_javascript::language(),
+        }
+    }
+
+    /// The tree-sitter query used for syntax highlighting.
+    pub fn highlight_query(self) -> &'static str {
+        match self {
+            Language::Python => tree_sitter_python::HIGHLIGHT_QUERY,
+            Language::JavaScript => tree_sitter_javascript::HIGHLIGHT_QUERY,
+        }
+    }
+
+    /// The tree-sitter query capturing foldable regions as `@fold`, if the
+    /// language has one.
+    pub fn fold_query(self) -> Option<&'static str> {
+        match self {
+            Language::Python => Some(PYTHON_FOLD_QUERY),
+            Language::JavaScript => None,
+        }
+    }
+
+    /// The prefix inserted to comment out a line.
+    pub fn line_comment(self) -> &'static str {
+        match self {
+            Language::Python => "# ",
+            Language::JavaScript => "// ",
+        }
+    }
+
+    /// Returns `true` if `c` is part of a word when moving by words, e.g. `_`
+    /// in Python identifiers.
+    pub fn is_word_char(self, c: char) -> bool {
+        match self {
+            Language::Python => c.is_alphanumeric() || c == '_',
+            Language::JavaScript => c.is_alphanumeric() || c == '_' || c == '$',
+        }
+    }
+}
+
+/// The language declared by an Emacs (`-*- mode: python -*-`) or Vim
+/// (`vim: ft=python`) modeline in the first or last lines of `text`.
+pub fn detect_modeline_language(text: &str) -> Option<Language> {
+    let lines: Vec<&str> = text.lines().collect();
+    let head = lines.iter().take(MODELINE_LINES);
+    let tail = lines.iter().skip(MODELINE_LINES).rev().take(MODELINE_LINES);
+    head.chain(tail)
+        .find_map(|line| emacs_modeline(line).or_else(|| vim_modeline(line)))
+}
+
+/// Parse `-*- python -*-` or `-*- mode: python; ... -*-`.
+fn emacs_modeline(line: &str) -> Option<Language> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let vars = line[start..end].trim();
+    if !vars.contains(':') {
+        return Language::from_name(&vars.to_lowercase());
+    }
+    vars.split(';').find_map(|var| {
+        let (name, value) = var.split_once(':')?;
+        if name.trim().eq_ignore_ascii_case("mode") {
+            Language::from_name(&value.trim().to_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+/// Parse `vim: ft=python` or `vim: set filetype=python :`, including the `vi:`
+/// and `ex:` variants.
+fn vim_modeline(line: &str) -> Option<Language> {
+    let start = ["vim:", "vi:", "ex:"].iter().find_map(|marker| {
+        let i = line.find(marker)?;
+        let standalone = i == 0 || line[..i].ends_with(char::is_whitespace);
+        standalone.then(|| i + marker.len())
+    })?;
+    line[start..]
+        .split(|c: char| c.is_whitespace() || c == ':')
+        .find_map(|option| match option.split_once('=')? {
+            ("ft" | "filetype" | "syn" | "syntax", value) => Language::from_name(value),
+            _ => None,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emacs_modelines() {
+        assert_eq!(
+            detect_modeline_language("# -*- mode: javascript; coding: utf-8 -*-\n"),
+            Some(Language::JavaScript)
+        );
+        assert_eq!(
+            detect_modeline_language("#!/usr/bin/env node\n// -*- JS -*-\n"),
+            Some(Language::JavaScript)
+        );
+        assert_eq!(detect_modeline_language("# -*- coding: utf-8 -*-\n"), None);
+    }
+
+    #[test]
+    fn vim_modelines() {
+        for text in [
+            "// vim: ft=javascript",
+            "// vim: set filetype=javascript :",
+            "// vi: syntax=js",
+            "// ex: ft=js",
+        ] {
+            assert_eq!(
+                detect_modeline_language(text),
+                Some(Language::JavaScript),
+                "{:?}",
+                text
+            );
+        }
+        // The marker has to stand on its own.
+        assert_eq!(detect_modeline_language("// navi: ft=js"), None);
+    }
+
+    #[test]
+    fn modeline_at_end() {
+        let mut lines = vec!["x = 1"; 20];
+        lines.push("# vim: ft=javascript");
+        assert_eq!(
+            detect_modeline_language(&lines.join("\n")),
+            Some(Language::JavaScript)
+        );
+        // Modelines in the middle of a document are ignored.
+        lines[10] = lines.pop().unwrap();
+        assert_eq!(detect_modeline_language(&lines.join("\n")), None);
+    }
+}
@@ -0,0 +1,20 @@
+//! The document model of the editor: the text with its syntax tree,
+//! highlighting, undo history and line index, and the settings it depends
+//! on. It is a library of its own so that it can be benchmarked without the
+//! user interface.
+
+pub mod blame;
+pub mod bookmarks;
+pub mod codetext;
+pub mod config;
+pub mod conflicts;
+pub mod gutter;
+pub mod history;
+pub mod language;
+pub mod lines;
+pub mod pairs;
+pub mod scopes;
+pub mod settings;
+pub mod snippet;
+pub mod text;
+pub mod timestamp;
@@ -0,0 +1,139 @@
+//! An index of the line starts of a document, kept up to date as it is
+//! edited.
+
+use std::ops::Range;
+
+use crate::text;
+
+/// The offsets of the starts of all lines of a text, as returned by
+/// [`text::line_starts`]. A text ending with a line break ends with an empty
+/// line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        LineIndex {
+            starts: text::line_starts(text).collect(),
+        }
+    }
+
+    /// The number of lines, at least one.
+    pub fn line_count(&self) -> usize {
+        self.starts.len()
+    }
+
+    /// The offset of the start of the zero-based `line`.
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        self.starts.get(line).copied()
+    }
+
+    /// The zero-based line containing `offset`.
+    pub fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    /// The range of the zero-based `line` of `text`, the text the index was
+    /// built for, without its line break, be it `\n` or `\r\n`.
+    pub fn line_range(&self, text: &str, line: usize) -> Option<Range<usize>> {
+        let start = self.line_start(line)?;
+        let end = match self.line_start(line + 1) {
+            Some(next) if text[..next - 1].ends_with('\r') => next - 2,
+            Some(next) => next - 1,
+            None => text.len(),
+        };
+        Some(start..end)
+    }
+
+    /// The zero-based line of `offset` in `text` and its column in UTF-16
+    /// code units, which is how the language server protocol gives
+    /// positions.
+    pub fn utf16_position(&self, text: &str, offset: usize) -> (usize, usize) {
+        let line = self.line_of(offset);
+        let column = text[self.starts[line]..offset].encode_utf16().count();
+        (line, column)
+    }
+
+    /// The offset in `text` of a zero-based line and a column in UTF-16 code
+    /// units, clamped to the end of the line and of the text.
+    pub fn utf16_offset(&self, text: &str, (line, column): (usize, usize)) -> usize {
+        let range = match self.line_range(text, line) {
+            Some(range) => range,
+            None => return text.len(),
+        };
+        let mut units = 0;
+        for (i, c) in text[range.clone()].char_indices() {
+            if units >= column {
+                return range.start + i;
+            }
+            units += c.len_utf16();
+        }
+        range.end
+    }
+
+    /// Update the index for an edit replacing `range` of the text with `new`.
+    pub fn edited(&mut self, range: Range<usize>, new: &str) {
+        // The lines starting after a line break in the replaced range are
+        // gone, those after it move.
+        let first = self.starts.partition_point(|&start| start <= range.start);
+        let last = self.starts.partition_point(|&start| start <= range.end);
+        let inserted: Vec<usize> = new
+            .match_indices('\n')
+            .map(|(i, _)| range.start + i + 1)
+            .collect();
+        let moved = first + inserted.len();
+        self.starts.splice(first..last, inserted);
+        for start in &mut self.starts[moved..] {
+            *start = *start - range.end + range.start + new.len();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crlf_and_no_final_newline() {
+        for (text, ranges) in [
+            ("a\r\nb\nc", vec![0..1, 3..4, 5..6]),
+            ("a\n\r\n", vec![0..1, 2..2, 4..4]),
+        ] {
+            let mut index = LineIndex::new(text);
+            index.edited(0..0, "\n");
+            index.edited(0..1, "");
+            assert_eq!(index, LineIndex::new(text));
+            let found: Vec<_> = (0..index.line_count())
+                .filter_map(|line| index.line_range(text, line))
+                .collect();
+            assert_eq!(found, ranges, "{:?}", text);
+            assert_eq!(index.line_of(text.len()), ranges.len() - 1);
+        }
+    }
+
+    #[test]
+    fn edits() {
+        let mut text = String::from("one\ntwo\nthree");
+        let mut index = LineIndex::new(&text);
+        for (range, new) in [(4..4, "2\n"), (0..8, "1"), (1..1, "\r\n\n")] {
+            index.edited(range.clone(), new);
+            text.replace_range(range, new);
+            assert_eq!(index, LineIndex::new(&text), "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn utf16_positions() {
+        let text = "a😀b\r\nxé";
+        let index = LineIndex::new(text);
+        for (offset, position) in [(0, (0, 0)), (5, (0, 3)), (6, (0, 4)), (11, (1, 2))] {
+            assert_eq!(index.utf16_position(text, offset), position);
+            assert_eq!(index.utf16_offset(text, position), offset);
+        }
+        // Columns past the end of a line are clamped to it.
+        assert_eq!(index.utf16_offset(text, (0, 10)), 6);
+        assert_eq!(index.utf16_offset(text, (5, 0)), text.len());
+    }
+}
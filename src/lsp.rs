@@ -0,0 +1,418 @@
+//! A minimal language server client, built with the `lsp` feature.
+//!
+//! The server configured for the document's language, e.g.
+//! `language_server.python = pyright-langserver --stdio`, is started when a
+//! file is opened. Every edit is sent to it as an incremental change, and the
+//! diagnostics it publishes for the document are shown in the editor.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use druid::widget::prelude::*;
+use druid::TimerToken;
+use serde_json::{json, Value};
+
+use crate::codetext::CodeText;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::text;
+use crate::AppState;
+
+/// How often published diagnostics are picked up.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// The id of the `initialize` request, the only request sent.
+const INITIALIZE_ID: u64 = 1;
+
+/// A diagnostic as published, with the line and UTF-16 column of its start
+/// and end.
+struct Published {
+    start: (usize, usize),
+    end: (usize, usize),
+    severity: Severity,
+    message: String,
+}
+
+/// The connection to a server's standard input. Messages are held back until
+/// the server has answered the `initialize` request.
+struct Connection {
+    stdin: ChildStdin,
+    initialized: bool,
+    pending: Vec<Value>,
+}
+
+impl Connection {
+    fn send(&mut self, message: Value) -> io::Result<()> {
+        if self.initialized {
+            write_message(&mut self.stdin, &message)
+        } else {
+            self.pending.push(message);
+            Ok(())
+        }
+    }
+
+    /// Acknowledge the answer to `initialize` and send the held back
+    /// messages.
+    fn initialized(&mut self) -> io::Result<()> {
+        self.initialized = true;
+        self.send(notification("initialized", json!({})))?;
+        for message in mem::take(&mut self.pending) {
+            write_message(&mut self.stdin, &message)?;
+        }
+        Ok(())
+    }
+}
+
+/// A running language server with the document open.
+struct LanguageServer {
+    child: Child,
+    connection: Arc<Mutex<Connection>>,
+    /// The diagnostics last published for the document and not picked up
+    /// yet.
+    published: Arc<Mutex<Option<Vec<Published>>>>,
+    uri: String,
+    version: u64,
+}
+
+impl LanguageServer {
+    /// Start the server `command`, a program followed by its arguments
+    /// separated by whitespace, and open the document at `path` in it.
+    fn start(command: &str, path: &Path, code: &CodeText) -> io::Result<Self> {
+        let mut words = command.split_whitespace();
+        let program = words
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(words)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdin = child.stdin.take().expect("stdin is piped");
+        let stdout = child.stdout.take().expect("stdout is piped");
+        let uri = file_uri(path);
+        let root = path.parent().map(file_uri);
+        write_message(
+            &mut stdin,
+            &json!({
+                "jsonrpc": "2.0",
+                "id": INITIALIZE_ID,
+                "method": "initialize",
+                "params": {
+                    "processId": std::process::id(),
+                    "rootUri": root,
+                    "capabilities": {
+                        "textDocument": { "publishDiagnostics": {} },
+                    },
+                },
+            }),
+        )?;
+        let connection = Arc::new(Mutex::new(Connection {
+            stdin,
+            initialized: false,
+            pending: Vec::new(),
+        }));
+        connection.lock().unwrap().send(notification(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": code.language().name(),
+                    "version": 0,
+                    "text": code.buffer,
+                },
+            }),
+        ))?;
+        let published = Arc::new(Mutex::new(None));
+        let reader = Reader {
+            connection: connection.clone(),
+            published: published.clone(),
+            uri: uri.clone(),
+        };
+        thread::spawn(move || reader.run(BufReader::new(stdout)));
+        Ok(LanguageServer {
+            child,
+            connection,
+            published,
+            uri,
+            version: 0,
+        })
+    }
+
+    /// Send the edit that turned `old` into `new` to the server, as the
+    /// range of `old` that changed and the text replacing it.
+    fn did_change(&mut self, old: &CodeText, new: &CodeText) -> io::Result<()> {
+        let (prefix, suffix) = text::common_affixes(&old.buffer, &new.buffer);
+        let (start, end) = (prefix, old.buffer.len() - suffix);
+        let position = |(line, character)| json!({ "line": line, "character": character });
+        self.version += 1;
+        self.connection.lock().unwrap().send(notification(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": self.uri, "version": self.version },
+                "contentChanges": [{
+                    "range": {
+                        "start": position(old.utf16_position(start)),
+                        "end": position(old.utf16_position(end)),
+                    },
+                    "text": &new.buffer[prefix..new.buffer.len() - suffix],
+                }],
+            }),
+        ))
+    }
+
+    /// The diagnostics published since they were last taken, resolved
+    /// against the document.
+    fn take_diagnostics(&self, code: &CodeText) -> Option<Vec<Diagnostic>> {
+        let published = self.published.lock().unwrap().take()?;
+        let diagnostics = published
+            .into_iter()
+            .map(|published| Diagnostic {
+                range: code.utf16_offset(published.start)..code.utf16_offset(published.end),
+                severity: published.severity,
+                message: published.message,
+            })
+            .collect();
+        Some(diagnostics)
+    }
+}
+
+impl Drop for LanguageServer {
+    fn drop(&mut self) {
+        // Servers exit when asked to, killing makes sure of it.
+        let exit = json!({ "jsonrpc": "2.0", "method": "exit" });
+        if let Ok(mut connection) = self.connection.lock() {
+            let _ = write_message(&mut connection.stdin, &exit);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Reads the messages of a server on a thread of its own.
+struct Reader {
+    connection: Arc<Mutex<Connection>>,
+    published: Arc<Mutex<Option<Vec<Published>>>>,
+    uri: String,
+}
+
+impl Reader {
+    fn run(self, mut stdout: impl BufRead) {
+        while let Ok(Some(message)) = read_message(&mut stdout) {
+            let method = message["method"].as_str();
+            let result = match (method, message.get("id")) {
+                (None, Some(id)) if id == INITIALIZE_ID => {
+                    self.connection.lock().unwrap().initialized()
+                }
+                (Some("textDocument/publishDiagnostics"), None) => {
+                    let params = &message["params"];
+                    if params["uri"] == self.uri.as_str() {
+                        let diagnostics = params["diagnostics"].as_array().map(|diagnostics| {
+                            diagnostics.iter().filter_map(parse_diagnostic).collect()
+                        });
+                        *self.published.lock().unwrap() = diagnostics;
+                    }
+                    Ok(())
+                }
+                // Requests from the server, e.g. for its configuration, are
+                // answered with the defaults.
+                (Some(method), Some(id)) => {
+                    let result = match method {
+                        "workspace/configuration" => {
+                            let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                            Value::Array(vec![Value::Null; items])
+                        }
+                        _ => Value::Null,
+                    };
+                    let response = json!({ "jsonrpc": "2.0", "id": id, "result": result });
+                    self.connection.lock().unwrap().send(response)
+                }
+                _ => Ok(()),
+            };
+            if result.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+/// A diagnostic of a `textDocument/publishDiagnostics` notification.
+fn parse_diagnostic(diagnostic: &Value) -> Option<Published> {
+    let position = |position: &Value| {
+        let line = position["line"].as_u64()? as usize;
+        let character = position["character"].as_u64()? as usize;
+        Some((line, character))
+    };
+    let range = &diagnostic["range"];
+    Some(Published {
+        start: position(&range["start"])?,
+        end: position(&range["end"])?,
+        severity: match diagnostic["severity"].as_u64() {
+            Some(2) => Severity::Warning,
+            Some(3) => Severity::Information,
+            Some(4) => Severity::Hint,
+            _ => Severity::Error,
+        },
+        message: diagnostic["message"].as_str()?.to_owned(),
+    })
+}
+
+fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
+
+/// Write a message with the header framing it.
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// Read the next message, `None` at the end of the output.
+fn read_message(reader: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let length: usize = length
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "missing Content-Length"))?;
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// The `file` URI of `path`, with the characters not allowed in a path
+/// percent-encoded.
+fn file_uri(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    let mut uri = if path.starts_with('/') {
+        "file://".to_owned()
+    } else {
+        "file:///".to_owned()
+    };
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
+
+/// An invisible widget running the language server of the open document,
+/// keeping it in sync with the document and storing the diagnostics it
+/// publishes in [`AppState::diagnostics`].
+pub struct LanguageServers {
+    server: Option<LanguageServer>,
+    /// Why the server could not be started, reported with the next poll.
+    failure: Option<String>,
+    timer: TimerToken,
+}
+
+impl LanguageServers {
+    pub fn new() -> Self {
+        LanguageServers {
+            server: None,
+            failure: None,
+            timer: TimerToken::INVALID,
+        }
+    }
+
+    /// Start the server configured for the document's language, if any,
+    /// replacing the running one.
+    fn restart(&mut self, data: &AppState) {
+        self.server = None;
+        let path = match data.path {
+            Some(ref path) => path,
+            None => return,
+        };
+        let language = data.code.language();
+        let servers = &data.settings.language_servers;
+        if let Some((_, command)) = servers.iter().find(|(other, _)| *other == language) {
+            match LanguageServer::start(command, path, &data.code) {
+                Ok(server) => self.server = Some(server),
+                Err(err) => self.failure = Some(format!("Failed to start `{}`: {}", command, err)),
+            }
+        }
+    }
+}
+
+impl Widget<AppState> for LanguageServers {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
+        if let Event::Timer(token) = event {
+            if *token == self.timer {
+                if let Some(failure) = self.failure.take() {
+                    data.notifications.error(failure);
+                }
+                if let Some(ref server) = self.server {
+                    if let Some(diagnostics) = server.take_diagnostics(&data.code) {
+                        data.diagnostics = Arc::new(diagnostics);
+                    }
+                }
+                self.timer = ctx.request_timer(POLL_INTERVAL);
+            }
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        data: &AppState,
+        _env: &Env,
+    ) {
+        if let LifeCycle::WidgetAdded = event {
+            self.restart(data);
+            self.timer = ctx.request_timer(POLL_INTERVAL);
+        }
+    }
+
+    fn update(&mut self, _ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        let opened = !old_data.path.same(&data.path)
+            || old_data.code.language() != data.code.language()
+            || !old_data
+                .settings
+                .language_servers
+                .same(&data.settings.language_servers);
+        if opened {
+            self.restart(data);
+        } else if !old_data.code.same(&data.code) {
+            if let Some(ref mut server) = self.server {
+                if let Err(err) = server.did_change(&old_data.code, &data.code) {
+                    self.failure = Some(format!("The language server stopped: {}", err));
+                    self.server = None;
+                }
+            }
+        }
+    }
+
+    fn layout(
+        &mut self,
+        _ctx: &mut LayoutCtx,
+        _bc: &BoxConstraints,
+        _data: &AppState,
+        _env: &Env,
+    ) -> Size {
+        Size::ZERO
+    }
+
+    fn paint(&mut self, _ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {}
+}
@@ -1,72 +1,915 @@
-// On Windows platform, don't show a console when opening the app.
-#![windows_subsystem = "windows"]
+// On Windows platform, don't show a console when opening the app, unless it
+// is built with the `debug-console` feature to show diagnostics.
+#![cfg_attr(not(feature = "debug-console"), windows_subsystem = "windows")]
 
-mod codetext;
+mod annotations;
+mod block;
+mod breadcrumbs;
+mod diagnostics;
+mod editor;
+#[cfg(not(target_arch = "wasm32"))]
+mod editorconfig;
+mod finder;
+#[cfg(not(target_arch = "wasm32"))]
+mod formatter;
+mod imports;
+#[cfg(all(feature = "lsp", not(target_arch = "wasm32")))]
+mod lsp;
+mod modes;
+mod notifications;
+mod preview;
+mod recent;
+#[cfg(not(target_arch = "wasm32"))]
+mod related;
+mod theme;
+mod typewriter;
 
+use std::env;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use annotations::ScrollAnnotations;
+use blame::{BlameMode, LineCommit};
+use breadcrumbs::Breadcrumbs;
 use codetext::CodeText;
+use conflicts::Resolution;
+use diagnostics::Diagnostic;
+use editor::Editor;
+use language::Language;
+use notifications::{Notifications, Toasts};
+use preview::ThemePreview;
+use recent::RecentFiles;
+use settings::Settings;
+use theme::Theme;
+use typewriter::Typewriter;
+
+use lyranos::{
+    blame, codetext, config, conflicts, gutter, language, pairs, settings, snippet, text, timestamp,
+};
 
+use druid::text::{EditableText, Selection};
 use druid::widget::prelude::*;
-use druid::widget::TextBox;
-use druid::FontDescriptor;
-use druid::FontFamily;
+use druid::widget::{Either, Flex, Label, Scroll, SizedBox, Split};
 use druid::{
-    AppDelegate, AppLauncher, Color, Command, Data, DelegateCtx, Handled, Lens, LocalizedString,
-    Menu, Selector, Target, Widget, WidgetExt, WindowDesc, WindowId,
+    commands, AppDelegate, AppLauncher, Application, Command, Data, DelegateCtx, ExtEventSink,
+    FileDialogOptions, Handled, Lens, LocalizedString, Menu, MenuItem, Selector, Target, Widget,
+    WidgetExt, WindowDesc, WindowId,
 };
 
 const WINDOW_TITLE: LocalizedString<AppState> = LocalizedString::new("Code Editor");
 
-const TEXT: &str = "import antigravity
+/// The sample document shown when started with `--demo`.
+const TEXT: &str = include_str!("sample.py");
 
-a = 42.5
-x = f\"Hello {a + 1}\"
+const OPEN_LINK: Selector<String> = Selector::new("druid-example.open-link");
+const COPY_PATH: Selector = Selector::new("lyranos.copy-path");
+const REVEAL_IN_FILE_MANAGER: Selector = Selector::new("lyranos.reveal-in-file-manager");
+const TOGGLE_INVISIBLES: Selector = Selector::new("lyranos.toggle-invisibles");
+const TOGGLE_SPLIT_VIEW: Selector = Selector::new("lyranos.toggle-split-view");
+const TOGGLE_CAPTURES_PANEL: Selector = Selector::new("lyranos.toggle-captures-panel");
+const TOGGLE_THEME_PREVIEW: Selector = Selector::new("lyranos.toggle-theme-preview");
+const REVERT_TO_SAVED: Selector = Selector::new("lyranos.revert-to-saved");
+const FIX_MIXED_INDENTATION: Selector = Selector::new("lyranos.fix-mixed-indentation");
+const INSERT_TIMESTAMP: Selector = Selector::new("lyranos.insert-timestamp");
+const TOGGLE_QUOTES: Selector = Selector::new("lyranos.toggle-quotes");
+const SPLIT_STRING: Selector = Selector::new("lyranos.split-string");
+const JOIN_STRINGS: Selector = Selector::new("lyranos.join-strings");
+const REMOVE_UNUSED_IMPORTS: Selector = Selector::new("lyranos.remove-unused-imports");
+const SORT_IMPORTS: Selector = Selector::new("lyranos.sort-imports");
+const INSERT_DEBUG_PRINT: Selector = Selector::new("lyranos.insert-debug-print");
+const GO_TO_RELATED_FILE: Selector = Selector::new("lyranos.go-to-related-file");
+const REPARSE: Selector = Selector::new("lyranos.reparse");
+/// Convert all line endings of the document to the given one.
+const CONVERT_EOL: Selector<&'static str> = Selector::new("lyranos.convert-eol");
+const RESOLVE_CONFLICT: Selector<Resolution> = Selector::new("lyranos.resolve-conflict");
+const REFLOW_COMMENT: Selector = Selector::new("lyranos.reflow-comment");
+const TOGGLE_DIGIT_GROUPS: Selector = Selector::new("lyranos.toggle-digit-groups");
 
-def scope_test():
-    def do_local():
-        spam = 'local spam'
+#[derive(Clone, Data, Lens)]
+struct AppState {
+    code: CodeText,
+    /// The file the document was loaded from, `None` if it is unsaved.
+    path: Option<Arc<PathBuf>>,
+    /// The settings in effect for the document, the user's settings with
+    /// those of its `.editorconfig` files applied.
+    settings: Settings,
+    /// The settings from the user's settings file.
+    user_settings: Settings,
+    theme: Theme,
+    /// The selection of the editor being used, read with `selection` and
+    /// moved with `set_selection`.
+    selection: Selection,
+    /// The selection set by the command being handled, passed on to the
+    /// editor once it is done.
+    pending_selection: Option<Selection>,
+    /// A message shown in the status bar.
+    status: String,
+    /// Whether the document is shown in two panes.
+    split_view: bool,
+    /// Whether the panel listing the highlight captures without a color is
+    /// shown.
+    captures_panel: bool,
+    /// Whether a sample document is shown with the colors of the theme.
+    theme_preview: bool,
+    /// Whether the file started with a byte order mark, which is kept out of
+    /// the buffer.
+    bom: bool,
+    /// The document as it was loaded or last saved, to detect unsaved
+    /// changes.
+    saved: Arc<String>,
+    /// Whether Revert to Saved was chosen once and awaits confirmation.
+    confirm_revert: bool,
+    notifications: Notifications,
+    /// The problems the language server reported in the document.
+    diagnostics: Arc<Vec<Diagnostic>>,
+    /// The commit that last changed each line of the saved file, empty if
+    /// unknown or if it is not in a git repository.
+    blame: Arc<Vec<Option<LineCommit>>>,
+}
 
-    def do_nonlocal():
-        nonlocal spam
-        spam = 'nonlocal spam'
+impl AppState {
+    /// The state of a new window showing `text`, which is not saved to any
+    /// file.
+    fn new(text: &str, settings: Settings, theme: Theme) -> Self {
+        let code = CodeText::with_language(text.to_owned(), Language::Python, &settings);
+        let mut notifications = Notifications::default();
+        for err in code.query_errors() {
+            notifications.error(err.as_str());
+        }
+        AppState {
+            code,
+            path: None,
+            settings: settings.clone(),
+            user_settings: settings,
+            theme,
+            selection: Selection::caret(0),
+            pending_selection: None,
+            status: String::new(),
+            split_view: false,
+            captures_panel: false,
+            theme_preview: false,
+            bom: false,
+            saved: Arc::new(text.to_owned()),
+            confirm_revert: false,
+            notifications,
+            diagnostics: Arc::new(Vec::new()),
+            blame: Arc::new(Vec::new()),
+        }
+    }
 
-    def do_global():
-        global spam
-        spam = 'global spam'
+    /// Statistics about the selected text for the status bar, empty if
+    /// nothing is selected.
+    fn selection_summary(&self) -> String {
+        let selected = match self.code.buffer.get(self.selection.range()) {
+            Some(selected) if !selected.is_empty() => selected,
+            _ => return String::new(),
+        };
+        format!(
+            "Sel: {} chars, {} lines, {} words",
+            text::count_chars(selected),
+            text::count_lines(selected),
+            text::count_words(selected)
+        )
+    }
 
-    spam = 'test spam'
-    do_local()
-    print('After local assignment:', spam)
-    do_nonlocal()
-    print('After nonlocal assignment:', spam)
-    do_global()
-    print('After global assignment:', spam)
+    /// The first line of the message of the diagnostic at the caret, for the
+    /// status bar.
+    fn diagnostic_message(&self) -> String {
+        let caret = self.selection.active;
+        self.diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.range.start <= caret && caret <= diagnostic.range.end)
+            .and_then(|diagnostic| diagnostic.message.lines().next())
+            .unwrap_or_default()
+            .to_owned()
+    }
 
-scope_test()
-print('In global scope:', spam)";
+    /// Whether the document ends with a line break, for the status bar.
+    /// Empty for an empty document or if the indicator is turned off.
+    fn final_newline_indicator(&self) -> &'static str {
+        if !self.settings.show_final_newline || self.code.is_empty() {
+            ""
+        } else if self.code.buffer.ends_with('\n') {
+            "Final newline"
+        } else {
+            "No final newline"
+        }
+    }
 
-const OPEN_LINK: Selector<String> = Selector::new("druid-example.open-link");
+    /// The line ending of the document for the status bar, `Mixed` if it
+    /// has both `\n` and `\r\n` line endings. Empty for a single line.
+    fn eol_indicator(&self) -> &'static str {
+        let buffer = &self.code.buffer;
+        let lf = self.code.line_count() - 1;
+        let crlf = (0..lf)
+            .filter_map(|line| self.code.line_range(line))
+            .filter(|range| buffer[range.end..].starts_with('\r'))
+            .count();
+        match lf {
+            0 => "",
+            lf if lf == crlf => "CRLF",
+            _ if crlf == 0 => "LF",
+            _ => "Mixed",
+        }
+    }
 
-#[derive(Clone, Data, Lens)]
-struct AppState {
-    code: CodeText,
+    /// The selection of the editor being used.
+    fn selection(&self) -> Selection {
+        self.selection
+    }
+
+    /// Select `range` in the editor being used, with the caret at its end.
+    /// The editor is moved once the command being handled is done, clamping
+    /// the range to the document.
+    fn set_selection(&mut self, range: Range<usize>) {
+        self.selection = Selection::new(range.start, range.end);
+        self.pending_selection = Some(self.selection);
+    }
+
+    /// Remember the caret position in the current file for when it is
+    /// reopened.
+    fn remember_caret(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(path) = self.path.clone() {
+            let mut recent = self.recent_files();
+            recent.record(&path, self.selection.active);
+            if let Err(err) = recent.save_user() {
+                self.notifications
+                    .warn(format!("Failed to save recent files: {}", err));
+            }
+        }
+    }
+
+    /// The user's recent files, none if they cannot be loaded.
+    fn recent_files(&mut self) -> RecentFiles {
+        RecentFiles::load_user().unwrap_or_else(|err| {
+            self.notifications.warn(err);
+            RecentFiles::default()
+        })
+    }
+
+    /// Open the file at `path`, returning the caret position it was last left
+    /// at, if known.
+    fn open(&mut self, path: &Path) -> Option<usize> {
+        match fs::read_to_string(path) {
+            Ok(text) => {
+                self.remember_caret();
+                self.load(path, &text);
+                // Carets are not remembered on the web.
+                if cfg!(target_arch = "wasm32") {
+                    None
+                } else {
+                    self.recent_files().caret(path)
+                }
+            }
+            Err(err) => {
+                self.notifications
+                    .error(format!("Failed to open {}: {}", path.display(), err));
+                None
+            }
+        }
+    }
+
+    /// Open the first existing file related to the document by the naming
+    /// conventions in the settings, e.g. its tests, returning the caret
+    /// position it was last left at. If none exists, an empty document is
+    /// started in place of the most likely one, to be created by saving.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn open_related_file(&mut self) -> Option<usize> {
+        let path = self.path.clone()?;
+        let patterns: Vec<&str> = self
+            .settings
+            .related_files
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        let candidates = related::counterparts(&path, &patterns);
+        if let Some(existing) = candidates.iter().find(|candidate| candidate.is_file()) {
+            return self.open(existing);
+        }
+        let new = match candidates.first() {
+            Some(new) => new,
+            None => {
+                self.status = "No related file naming conventions apply".to_owned();
+                return None;
+            }
+        };
+        self.remember_caret();
+        self.load(new, "");
+        self.status = format!("New file {}, save to create it", new.display());
+        None
+    }
+
+    /// Open the most recently used file again, returning the caret position
+    /// it was left at. A file that no longer exists is forgotten.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn reopen_last_file(&mut self) -> Option<usize> {
+        let mut recent = self.recent_files();
+        let (path, caret) = recent.most_recent()?;
+        let path = path.to_owned();
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                self.load(&path, &text);
+                Some(caret)
+            }
+            Err(err) => {
+                self.notifications
+                    .warn(format!("Failed to reopen {}: {}", path.display(), err));
+                if err.kind() == std::io::ErrorKind::NotFound {
+                    recent.forget(&path);
+                    if let Err(err) = recent.save_user() {
+                        self.notifications
+                            .warn(format!("Failed to save recent files: {}", err));
+                    }
+                }
+                None
+            }
+        }
+    }
+
+    /// Convert the indentation of the lines mixing tabs and spaces to the
+    /// configured indentation style.
+    fn fix_mixed_indentation(&mut self) {
+        let code = &self.code;
+        let edits: Vec<_> = code
+            .mixed_indentation_lines()
+            .iter()
+            .filter_map(|&line| {
+                let range = code.line_range(line)?;
+                let indent = text::indentation(&code.buffer[range.clone()]);
+                let fixed = text::normalize_indentation(
+                    indent,
+                    self.settings.tab_width,
+                    self.settings.indent_with_tabs,
+                );
+                Some((range.start..range.start + indent.len(), fixed))
+            })
+            .collect();
+        let fixed = edits.len();
+        self.code.edit_batch(edits);
+        self.status = format!("Fixed the indentation of {} lines", fixed);
+    }
+
+    /// Remove the import statements whose names are never used, as far as
+    /// [`CodeText::unused_imports`] can tell.
+    fn remove_unused_imports(&mut self) {
+        let buffer = &self.code.buffer;
+        let edits: Vec<_> = self
+            .code
+            .unused_imports()
+            .into_iter()
+            .map(|range| (text::removal_range(buffer, range), String::new()))
+            .collect();
+        let count = edits.len();
+        self.code.edit_batch(edits);
+        self.status = format!("Removed {} unused imports", count);
+    }
+
+    /// Sort the import statements at the top of the document, as described in
+    /// [`imports`].
+    fn sort_imports(&mut self) {
+        let range = match self.code.import_block() {
+            Some(range) => range,
+            None => {
+                self.status = "No imports at the top of the file".to_owned();
+                return;
+            }
+        };
+        let sorted = imports::sort_imports(&self.code.buffer[range.clone()]);
+        if sorted == self.code.buffer[range.clone()] {
+            self.status = "The imports are already sorted".to_owned();
+            return;
+        }
+        self.code.edit_batch(vec![(range, sorted)]);
+        self.status = "Sorted the imports".to_owned();
+    }
+
+    /// Switch the string literal at the caret between single and double
+    /// quotes.
+    fn toggle_quotes(&mut self) {
+        let caret = self.selection.active;
+        let toggled = self
+            .code
+            .enclosing_string_or_comment(caret)
+            .and_then(|range| {
+                let literal = text::toggle_quotes(&self.code.buffer[range.clone()])?;
+                Some((range, literal))
+            });
+        match toggled {
+            Some((range, literal)) => self.code.edit(range, literal),
+            None => self.status = "No string at the caret whose quotes can be toggled".to_owned(),
+        }
+    }
+
+    /// Split the string literal at the caret into two adjacent literals,
+    /// returning the caret position at the start of the second one.
+    fn split_string(&mut self) -> Option<usize> {
+        let caret = self.selection.active;
+        let range = self.code.string_at(caret)?;
+        let literal = &self.code.buffer[range.clone()];
+        let split = match text::split_string(literal, caret - range.start) {
+            Some(split) => split,
+            None => {
+                self.status = "The string cannot be split here".to_owned();
+                return None;
+            }
+        };
+        // The text after the caret is unchanged.
+        let caret = range.start + split.len() - (range.end - caret);
+        self.code.edit(range, split);
+        Some(caret)
+    }
+
+    /// Join the adjacent string literals around the caret into one,
+    /// returning the start of the joined literal.
+    fn join_strings(&mut self) -> Option<usize> {
+        let ranges = match self.code.concatenated_strings_at(self.selection.active) {
+            Some(ranges) => ranges,
+            None => {
+                self.status = "No adjacent strings at the caret".to_owned();
+                return None;
+            }
+        };
+        let buffer = &self.code.buffer;
+        let literals: Vec<&str> = ranges.iter().map(|range| &buffer[range.clone()]).collect();
+        let joined = match text::join_strings(&literals) {
+            Some(joined) => joined,
+            None => {
+                self.status =
+                    "Only strings with the same prefix and quotes can be joined".to_owned();
+                return None;
+            }
+        };
+        let range = ranges.first()?.start..ranges.last()?.end;
+        let start = range.start;
+        self.code.edit(range, joined);
+        Some(start)
+    }
+
+    /// Insert a statement printing the selected expression, or the identifier
+    /// at the caret, on a new line after the selection, returning the end of
+    /// the inserted line.
+    fn insert_debug_print(&mut self) -> Option<usize> {
+        let range = self.selection.range();
+        let range = if range.is_empty() {
+            self.code.identifier_at(range.start)?
+        } else {
+            range
+        };
+        let buffer = &self.code.buffer;
+        let start = text::line_start(buffer, range.start);
+        let indent = text::indentation(&buffer[start..text::line_end(buffer, start)]);
+        let line = text::debug_print_line(
+            &self.settings.debug_print_template,
+            &buffer[range.clone()],
+            indent,
+            text::detect_eol(buffer),
+        );
+        let end = text::line_end(buffer, range.end);
+        let end = buffer[start..end]
+            .strip_suffix('\r')
+            .map_or(end, |line| start + line.len());
+        let caret = end + line.len();
+        self.code.edit(end..end, line);
+        Some(caret)
+    }
+
+    /// Resolve the merge conflict around the caret, returning the start of
+    /// the lines kept.
+    fn resolve_conflict(&mut self, resolution: Resolution) -> Option<usize> {
+        let caret = self.selection.active;
+        let conflict = self
+            .code
+            .conflict_regions()
+            .iter()
+            .find(|conflict| conflict.range.start <= caret && caret < conflict.range.end)?
+            .clone();
+        let resolved = conflict.resolved(&self.code.buffer, resolution);
+        self.code.edit(conflict.range.clone(), resolved);
+        Some(conflict.range.start)
+    }
+
+    /// Rewrap the block of line comments around the caret to the ruler
+    /// column, returning the end of the reflowed block.
+    fn reflow_comment(&mut self) -> Option<usize> {
+        let range = self.code.comment_block(self.selection.active)?;
+        let prefix = self.code.language().line_comment().trim_end();
+        let reflowed = text::reflow_comment(
+            &self.code.buffer[range.clone()],
+            prefix,
+            self.settings.ruler_column,
+        );
+        let caret = range.start + reflowed.len();
+        self.code.edit_batch(vec![(range, reflowed)]);
+        Some(caret)
+    }
+
+    /// Group the digits of the number literals in the selection, or of the
+    /// one at the caret, by thousands with underscores, or remove the
+    /// underscores if any of them has some. Returns `false` if there is no
+    /// number literal.
+    fn toggle_digit_groups(&mut self) -> bool {
+        let range = self.selection().range();
+        let literals = self.code.number_literals(range.clone());
+        let last = match literals.last() {
+            Some(last) => last.clone(),
+            None => return false,
+        };
+        let buffer = &self.code.buffer;
+        let grouped = literals
+            .iter()
+            .any(|literal| buffer[literal.clone()].contains('_'));
+        let edits: Vec<_> = literals
+            .into_iter()
+            .map(|literal| {
+                let old = &buffer[literal.clone()];
+                let new = if grouped {
+                    text::ungroup_digits(old)
+                } else {
+                    text::group_digits(old)
+                };
+                (literal, new)
+            })
+            .filter(|(literal, new)| buffer[literal.clone()] != *new)
+            .collect();
+        let grown: usize = edits.iter().map(|(_, new)| new.len()).sum();
+        let shrunk: usize = edits.iter().map(|(literal, _)| literal.len()).sum();
+        self.code.edit_batch(edits);
+        // Keep the literals selected, or put the caret after the one it was
+        // in.
+        let (start, end) = if range.is_empty() {
+            let end = last.end + grown - shrunk;
+            (end, end)
+        } else {
+            (range.start, range.end + grown - shrunk)
+        };
+        self.set_selection(start..end);
+        true
+    }
+
+    /// The contents of the panel listing the highlight captures applied to the
+    /// document that have no color mapping.
+    fn unmapped_captures_report(&self) -> String {
+        if !self.code.is_highlighted() {
+            return "The document is not highlighted".to_owned();
+        }
+        let unmapped = self.code.applied_unmapped_captures();
+        if unmapped.is_empty() {
+            return "Every capture in the document has a color".to_owned();
+        }
+        let mut report = "Captures without a color:".to_owned();
+        for (name, count) in unmapped {
+            report.push_str(&format!("\n{} ({} times)", name, count));
+        }
+        report
+    }
+
+    /// Returns `true` if the document differs from the file it was loaded
+    /// from or last saved to.
+    fn is_dirty(&self) -> bool {
+        self.code.buffer != *self.saved
+    }
+
+    /// Replace the document with `text`, the contents of the file at `path`.
+    fn load(&mut self, path: &Path, text: &str) {
+        let (text, bom) = text::strip_bom(text);
+        self.settings = self.user_settings.clone();
+        self.bom = bom;
+        #[cfg(not(target_arch = "wasm32"))]
+        self.apply_editorconfig(path);
+        let language = Language::detect(path, text);
+        self.code = CodeText::with_language(text.to_owned(), language, &self.settings);
+        self.saved = Arc::new(text.to_owned());
+        self.path = Some(Arc::new(path.to_owned()));
+        self.status.clear();
+        self.diagnostics = Arc::new(Vec::new());
+        self.blame = Arc::new(Vec::new());
+        if !self.code.is_highlighted() {
+            self.notifications
+                .info("File too large, syntax highlighting is off");
+        }
+        for err in self.code.query_errors() {
+            self.notifications.error(err.as_str());
+        }
+        let servers = &self.settings.language_servers;
+        if cfg!(not(feature = "lsp")) && servers.iter().any(|(other, _)| *other == language) {
+            self.notifications
+                .warn("Language servers need a build with the `lsp` feature");
+        }
+        if cfg!(not(feature = "blame")) && self.settings.blame != BlameMode::Off {
+            self.notifications
+                .warn("Showing commits needs a build with the `blame` feature");
+        }
+    }
+
+    /// Find the commit that last changed each line of the saved file off the
+    /// UI thread, unless blame is off.
+    #[cfg_attr(
+        not(all(feature = "blame", not(target_arch = "wasm32"))),
+        allow(unused_variables)
+    )]
+    fn request_blame(&self, sink: ExtEventSink) {
+        #[cfg(all(feature = "blame", not(target_arch = "wasm32")))]
+        if let (Some(path), true) = (&self.path, self.settings.blame != BlameMode::Off) {
+            blame::spawn(path.to_path_buf(), sink);
+        }
+    }
+
+    /// Apply the `.editorconfig` files that apply to the file at `path` to
+    /// the settings and to whether the document is saved with a byte order
+    /// mark.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn apply_editorconfig(&mut self, path: &Path) {
+        let properties = editorconfig::Properties::resolve(path);
+        properties.apply(&mut self.settings);
+        match properties.charset.as_deref() {
+            Some("utf-8-bom") => self.bom = true,
+            Some("utf-8") => self.bom = false,
+            Some(charset) => self.notifications.warn(format!(
+                "Unsupported charset `{}` in .editorconfig, keeping UTF-8",
+                charset
+            )),
+            None => {}
+        }
+    }
+
+    /// Format the document with the formatter configured for its language if
+    /// `format_on_save` is on, returning the offset at the line and column of
+    /// the caret if the document changed.
+    ///
+    /// Formatting is part of saving rather than a reaction to it, so the
+    /// formatted document is saved as is and cannot cause another save.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn format_for_save(&mut self) -> Result<Option<usize>, String> {
+        if !self.settings.format_on_save {
+            return Ok(None);
+        }
+        let language = self.code.language();
+        let command = match self
+            .settings
+            .formatters
+            .iter()
+            .find(|(l, _)| *l == language)
+        {
+            Some((_, command)) => command,
+            None => return Ok(None),
+        };
+        let formatted = formatter::format(command, &self.code.buffer)?;
+        if formatted == self.code.buffer {
+            return Ok(None);
+        }
+        Ok(Some(
+            self.code.replace_all(formatted, self.selection.active),
+        ))
+    }
+
+    /// Write the document to `path`, reporting the outcome in the status bar.
+    /// Returns the caret offset to restore if the document was formatted or
+    /// cleaned up.
+    ///
+    /// The document is not saved if formatting it fails.
+    fn save(&mut self, path: &Path) -> Option<usize> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let caret = match self.format_for_save() {
+            Ok(caret) => caret,
+            Err(err) => {
+                self.notifications
+                    .error(format!("Not saved, formatting failed: {}", err));
+                return None;
+            }
+        };
+        #[cfg(target_arch = "wasm32")]
+        let caret = None;
+        let mut cleaned = self.code.buffer.clone();
+        if self.settings.trim_trailing_whitespace {
+            cleaned = text::trim_trailing_whitespace(&cleaned);
+        }
+        if self.settings.ensure_final_newline {
+            cleaned = text::ensure_final_newline(&cleaned);
+        }
+        let caret = if cleaned != self.code.buffer {
+            let caret = caret.unwrap_or(self.selection.active);
+            Some(self.code.replace_all(cleaned, caret))
+        } else {
+            caret
+        };
+        let contents = if self.bom && self.settings.preserve_bom {
+            format!("{}{}", text::BOM, self.code.buffer)
+        } else {
+            self.code.buffer.clone()
+        };
+        if let Err(err) = fs::write(path, contents) {
+            self.notifications
+                .error(format!("Failed to save {}: {}", path.display(), err));
+            return caret;
+        }
+        self.path = Some(Arc::new(path.to_owned()));
+        self.saved = Arc::new(self.code.buffer.clone());
+        self.remember_caret();
+        self.status = format!("Saved {}", path.display());
+        // Saving is never blocked by syntax errors, but point them out.
+        let errors = self.code.syntax_errors().len();
+        if errors > 0 {
+            self.notifications
+                .warn(format!("Saved with {} syntax errors", errors));
+        }
+        caret
+    }
 }
 
 struct Delegate;
 
-impl<T: Data> AppDelegate<T> for Delegate {
-    fn command(
+impl Delegate {
+    /// Handle a command of the app, other than moving the selection.
+    fn handle_command(
         &mut self,
-        _ctx: &mut DelegateCtx,
-        _target: Target,
+        ctx: &mut DelegateCtx,
         cmd: &Command,
-        _data: &mut T,
-        _env: &Env,
+        data: &mut AppState,
     ) -> Handled {
         if let Some(url) = cmd.get(OPEN_LINK) {
             #[cfg(not(target_arch = "wasm32"))]
             open::that_in_background(url);
             #[cfg(target_arch = "wasm32")]
-            tracing::warn!("opening link({}) not supported on web yet.", url);
+            data.notifications.warn(format!(
+                "Opening links is not supported on the web: {}",
+                url
+            ));
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
+            if let Some(caret) = data.open(file_info.path()) {
+                ctx.submit_command(editor::SET_CARET.with(caret));
+            }
+            data.request_blame(ctx.get_external_handle());
+            Handled::Yes
+        } else if cmd.is(GO_TO_RELATED_FILE) {
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(caret) = data.open_related_file() {
+                ctx.submit_command(editor::SET_CARET.with(caret));
+            }
+            #[cfg(not(target_arch = "wasm32"))]
+            data.request_blame(ctx.get_external_handle());
+            #[cfg(target_arch = "wasm32")]
+            data.notifications
+                .warn("Related files cannot be opened on the web");
+            Handled::Yes
+        } else if cmd.is(REVERT_TO_SAVED) {
+            if let Some(path) = data.path.clone() {
+                if !data.confirm_revert {
+                    data.confirm_revert = true;
+                    data.status = "Revert again to discard all unsaved changes".to_owned();
+                } else {
+                    match fs::read_to_string(&*path) {
+                        Ok(text) => {
+                            let caret = data.selection().active;
+                            data.load(&path, &text);
+                            data.request_blame(ctx.get_external_handle());
+                            data.set_selection(caret..caret);
+                        }
+                        Err(err) => data.notifications.error(format!(
+                            "Failed to revert {}: {}",
+                            path.display(),
+                            err
+                        )),
+                    }
+                    data.confirm_revert = false;
+                }
+            }
+            Handled::Yes
+        } else if cmd.is(commands::SAVE_FILE) {
+            match data.path.clone() {
+                Some(path) => {
+                    if let Some(caret) = data.save(&path) {
+                        ctx.submit_command(editor::SET_CARET.with(caret));
+                    }
+                    data.request_blame(ctx.get_external_handle());
+                }
+                None => {
+                    ctx.submit_command(commands::SHOW_SAVE_PANEL.with(FileDialogOptions::new()))
+                }
+            }
+            Handled::Yes
+        } else if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            if let Some(caret) = data.save(file_info.path()) {
+                ctx.submit_command(editor::SET_CARET.with(caret));
+            }
+            data.request_blame(ctx.get_external_handle());
+            Handled::Yes
+        } else if cmd.is(TOGGLE_INVISIBLES) {
+            data.settings.show_invisibles = !data.settings.show_invisibles;
+            data.user_settings.show_invisibles = data.settings.show_invisibles;
+            Handled::Yes
+        } else if cmd.is(commands::UNDO) || cmd.is(commands::REDO) {
+            let selection = if cmd.is(commands::UNDO) {
+                data.code.undo()
+            } else {
+                data.code.redo()
+            };
+            if let Some(selection) = selection {
+                ctx.submit_command(editor::SET_SELECTION.with(selection));
+            }
+            Handled::Yes
+        } else if cmd.is(FIX_MIXED_INDENTATION) {
+            data.fix_mixed_indentation();
+            Handled::Yes
+        } else if cmd.is(REMOVE_UNUSED_IMPORTS) {
+            let caret = data.selection().active;
+            data.remove_unused_imports();
+            data.set_selection(caret..caret);
+            Handled::Yes
+        } else if cmd.is(SORT_IMPORTS) {
+            let caret = data.selection().active;
+            data.sort_imports();
+            data.set_selection(caret..caret);
+            Handled::Yes
+        } else if cmd.is(INSERT_TIMESTAMP) {
+            let stamp = timestamp::format(&data.settings.timestamp_format, timestamp::now());
+            let range = data.selection().range();
+            let caret = range.start + stamp.len();
+            data.code.edit(range, stamp);
+            data.set_selection(caret..caret);
+            Handled::Yes
+        } else if cmd.is(TOGGLE_QUOTES) {
+            let caret = data.selection().active;
+            data.toggle_quotes();
+            data.set_selection(caret..caret);
+            Handled::Yes
+        } else if cmd.is(INSERT_DEBUG_PRINT) {
+            match data.insert_debug_print() {
+                Some(caret) => ctx.submit_command(editor::SET_CARET.with(caret)),
+                None => data.status = "Select an expression to print".to_owned(),
+            }
+            Handled::Yes
+        } else if cmd.is(SPLIT_STRING) || cmd.is(JOIN_STRINGS) {
+            let caret = if cmd.is(SPLIT_STRING) {
+                data.split_string()
+            } else {
+                data.join_strings()
+            };
+            if let Some(caret) = caret {
+                ctx.submit_command(editor::SET_CARET.with(caret));
+            }
+            Handled::Yes
+        } else if cmd.is(TOGGLE_SPLIT_VIEW) {
+            data.split_view = !data.split_view;
+            Handled::Yes
+        } else if cmd.is(TOGGLE_CAPTURES_PANEL) {
+            data.captures_panel = !data.captures_panel;
+            Handled::Yes
+        } else if cmd.is(TOGGLE_THEME_PREVIEW) {
+            data.theme_preview = !data.theme_preview;
+            Handled::Yes
+        } else if let Some(&eol) = cmd.get(CONVERT_EOL) {
+            let converted = text::normalize_eol(&data.code.buffer, eol);
+            let caret = data.code.replace_all(converted, data.selection().active);
+            data.set_selection(caret..caret);
+            data.status = format!("Converted line endings to {}", data.eol_indicator());
+            Handled::Yes
+        } else if let Some((path, commits)) = cmd.get(blame::BLAME_READY) {
+            // The document may have been replaced while it was blamed.
+            if data.path.as_deref() == Some(path) {
+                data.blame = commits.clone();
+            }
+            Handled::Yes
+        } else if let Some(&resolution) = cmd.get(RESOLVE_CONFLICT) {
+            match data.resolve_conflict(resolution) {
+                Some(caret) => ctx.submit_command(editor::SET_CARET.with(caret)),
+                None => data.status = "No merge conflict at the caret".to_owned(),
+            }
+            Handled::Yes
+        } else if cmd.is(TOGGLE_DIGIT_GROUPS) {
+            if !data.toggle_digit_groups() {
+                data.status = "No number in the selection".to_owned();
+            }
+            Handled::Yes
+        } else if cmd.is(REFLOW_COMMENT) {
+            match data.reflow_comment() {
+                Some(caret) => ctx.submit_command(editor::SET_CARET.with(caret)),
+                None => data.status = "No comment at the caret".to_owned(),
+            }
+            Handled::Yes
+        } else if cmd.is(REPARSE) {
+            data.code.reparse_full();
+            Handled::Yes
+        } else if cmd.is(COPY_PATH) {
+            if let Some(ref path) = data.path {
+                let path = path.to_string_lossy();
+                Application::global().clipboard().put_string(path);
+            }
+            Handled::Yes
+        } else if cmd.is(REVEAL_IN_FILE_MANAGER) {
+            if let Some(dir) = data.path.as_ref().and_then(|path| path.parent()) {
+                #[cfg(not(target_arch = "wasm32"))]
+                open::that_in_background(dir);
+                #[cfg(target_arch = "wasm32")]
+                data.notifications.warn(format!(
+                    "Revealing files is not supported on the web: {}",
+                    dir.display()
+                ));
+            }
             Handled::Yes
         } else {
             Handled::No
@@ -74,6 +917,48 @@ impl<T: Data> AppDelegate<T> for Delegate {
     }
 }
 
+impl AppDelegate<AppState> for Delegate {
+    fn command(
+        &mut self,
+        ctx: &mut DelegateCtx,
+        _target: Target,
+        cmd: &Command,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Handled {
+        let handled = self.handle_command(ctx, cmd, data);
+        if let Some(selection) = data.pending_selection.take() {
+            ctx.submit_command(editor::SET_SELECTION.with(selection));
+        }
+        handled
+    }
+
+    fn event(
+        &mut self,
+        _ctx: &mut DelegateCtx,
+        _window_id: WindowId,
+        event: Event,
+        data: &mut AppState,
+        _env: &Env,
+    ) -> Option<Event> {
+        // Typing cancels a pending revert.
+        if let Event::KeyDown(_) = event {
+            data.confirm_revert = false;
+        }
+        Some(event)
+    }
+
+    fn window_removed(
+        &mut self,
+        _id: WindowId,
+        data: &mut AppState,
+        _env: &Env,
+        _ctx: &mut DelegateCtx,
+    ) {
+        data.remember_caret();
+    }
+}
+
 pub fn main() {
     // describe the main window
     let main_window = WindowDesc::new(build_root_widget())
@@ -81,18 +966,46 @@ pub fn main() {
         .menu(make_menu)
         .window_size((700.0, 600.0));
 
-    // create the initial app state
-    let initial_state = AppState {
-        code: CodeText::new(TEXT.to_owned()),
+    // start with an empty document unless the sample is asked for
+    let text = if env::args().any(|arg| arg == "--demo") {
+        TEXT
+    } else {
+        ""
     };
 
-    // start the application
-    AppLauncher::with_window(main_window)
-        .configure_env(|env, _app_state| {
-            env.set(
-                druid::theme::BACKGROUND_LIGHT,
-                Color::from_hex_str("#282c34").unwrap(),
+    // create the initial app state, reporting invalid configuration in it
+    let mut errors = Vec::new();
+    let settings = Settings::load_user().unwrap_or_else(|err| {
+        errors.push(err);
+        Settings::default()
+    });
+    let theme = Theme::load_user().unwrap_or_else(|err| {
+        errors.push(err);
+        Theme::default()
+    });
+    let mut initial_state = AppState::new(text, settings, theme);
+    for err in errors {
+        initial_state.notifications.error(err);
+    }
+
+    let launcher = AppLauncher::with_window(main_window);
+    #[cfg(not(target_arch = "wasm32"))]
+    if text.is_empty() && initial_state.settings.reopen_last_file {
+        if let Some(caret) = initial_state.reopen_last_file() {
+            let _ = launcher.get_external_handle().submit_command(
+                editor::SET_CARET,
+                caret,
+                Target::Auto,
             );
+        }
+        initial_state.request_blame(launcher.get_external_handle());
+    }
+
+    // start the application
+    launcher
+        .configure_env(|env, app_state| {
+            app_state.theme.apply(env);
+            app_state.settings.apply(env);
         })
         .log_to_console()
         .delegate(Delegate)
@@ -100,33 +1013,227 @@ pub fn main() {
         .expect("Failed to launch application");
 }
 
+/// An editor scrolling independently of any other editor of the document.
+fn editor_pane() -> impl Widget<AppState> {
+    ScrollAnnotations::new(
+        Typewriter::new(Scroll::new(Editor::new().expand_width().padding(5.0)).vertical()).expand(),
+    )
+}
+
 fn build_root_widget() -> impl Widget<AppState> {
-    let textbox = TextBox::multiline()
-        .with_font(FontDescriptor::new(FontFamily::MONOSPACE).with_size(16.0))
-        .lens(AppState::code)
-        .expand()
-        .padding(5.0);
-    textbox
+    let status_bar = Flex::row()
+        .with_flex_child(
+            Label::dynamic(|data: &AppState, _env| data.status.clone()).expand_width(),
+            1.0,
+        )
+        .with_child(Label::dynamic(|data: &AppState, _env| {
+            data.diagnostic_message()
+        }))
+        .with_spacer(10.0)
+        .with_child(Label::dynamic(|data: &AppState, _env| {
+            data.selection_summary()
+        }))
+        .with_spacer(10.0)
+        .with_child(Label::dynamic(|data: &AppState, _env| {
+            data.final_newline_indicator().to_owned()
+        }))
+        .with_spacer(10.0)
+        .with_child(Label::dynamic(|data: &AppState, _env| {
+            data.eol_indicator().to_owned()
+        }));
+    #[cfg(all(feature = "lsp", not(target_arch = "wasm32")))]
+    let status_bar = status_bar.with_child(lsp::LanguageServers::new());
+    let status_bar = status_bar.padding(5.0);
+    Flex::column()
+        .with_child(Breadcrumbs::new())
+        .with_flex_child(
+            Either::new(
+                |data: &AppState, _env| data.split_view,
+                Split::rows(editor_pane(), editor_pane()).draggable(true),
+                editor_pane(),
+            ),
+            1.0,
+        )
+        .with_child(Either::new(
+            |data: &AppState, _env| data.captures_panel,
+            Label::dynamic(|data: &AppState, _env| data.unmapped_captures_report())
+                .padding(5.0)
+                .expand_width(),
+            SizedBox::empty(),
+        ))
+        .with_child(Either::new(
+            |data: &AppState, _env| data.theme_preview,
+            ThemePreview::new().padding(5.0),
+            SizedBox::empty(),
+        ))
+        .with_child(Toasts::new())
+        .with_child(status_bar.expand_width())
+        // Re-apply the theme and settings whenever they change.
+        .env_scope(|env, data: &AppState| {
+            data.theme.apply(env);
+            data.settings.apply(env);
+        })
 }
 
 #[allow(unused_assignments, unused_mut)]
-fn make_menu<T: Data>(_window_id: Option<WindowId>, _app_state: &AppState, _env: &Env) -> Menu<T> {
+fn make_menu(_window_id: Option<WindowId>, _app_state: &AppState, _env: &Env) -> Menu<AppState> {
     let mut base = Menu::empty();
+    let mut file = Menu::new(LocalizedString::new("common-menu-file-menu"));
     #[cfg(target_os = "macos")]
     {
         base = base.entry(druid::platform_menus::mac::application::default())
     }
     #[cfg(any(target_os = "windows", target_os = "linux", target_os = "openbsd"))]
     {
-        base = base.entry(druid::platform_menus::win::file::default());
+        file = druid::platform_menus::win::file::default();
     }
     base.entry(
+        file.separator()
+            .entry(
+                MenuItem::new("Revert to Saved")
+                    .command(REVERT_TO_SAVED)
+                    .enabled_if(|data: &AppState, _env| data.path.is_some() && data.is_dirty()),
+            )
+            .entry(
+                MenuItem::new("Go to Related File")
+                    .command(GO_TO_RELATED_FILE)
+                    .enabled_if(|data: &AppState, _env| data.path.is_some()),
+            )
+            .entry(
+                MenuItem::new("Copy Path")
+                    .command(COPY_PATH)
+                    .enabled_if(|data: &AppState, _env| data.path.is_some()),
+            )
+            .entry(
+                MenuItem::new("Reveal in File Manager")
+                    .command(REVEAL_IN_FILE_MANAGER)
+                    .enabled_if(|data: &AppState, _env| data.path.is_some()),
+            ),
+    )
+    .entry(
         Menu::new(LocalizedString::new("common-menu-edit-menu"))
             .entry(druid::platform_menus::common::undo())
             .entry(druid::platform_menus::common::redo())
             .separator()
             .entry(druid::platform_menus::common::cut().enabled(false))
             .entry(druid::platform_menus::common::copy())
-            .entry(druid::platform_menus::common::paste()),
+            .entry(druid::platform_menus::common::paste())
+            .separator()
+            .entry(
+                MenuItem::new("Fix Mixed Indentation")
+                    .command(FIX_MIXED_INDENTATION)
+                    .enabled_if(|data: &AppState, _env| {
+                        !data.code.mixed_indentation_lines().is_empty()
+                    }),
+            )
+            .entry(
+                MenuItem::new("Remove Unused Imports")
+                    .command(REMOVE_UNUSED_IMPORTS)
+                    .enabled_if(|data: &AppState, _env| data.code.language() == Language::Python),
+            )
+            .entry(
+                MenuItem::new("Sort Imports")
+                    .command(SORT_IMPORTS)
+                    .enabled_if(|data: &AppState, _env| data.code.language() == Language::Python),
+            )
+            .entry(MenuItem::new("Insert Date and Time").command(INSERT_TIMESTAMP))
+            .entry(
+                MenuItem::new("Toggle Quotes")
+                    .command(TOGGLE_QUOTES)
+                    .enabled_if(|data: &AppState, _env| data.code.language() == Language::Python),
+            )
+            .entry(
+                MenuItem::new("Split String")
+                    .command(SPLIT_STRING)
+                    .enabled_if(|data: &AppState, _env| data.code.language() == Language::Python),
+            )
+            .entry(
+                MenuItem::new("Join Strings")
+                    .command(JOIN_STRINGS)
+                    .enabled_if(|data: &AppState, _env| data.code.language() == Language::Python),
+            )
+            .entry(MenuItem::new("Insert Debug Print").command(INSERT_DEBUG_PRINT))
+            .entry(MenuItem::new("Reflow Comment").command(REFLOW_COMMENT))
+            .entry(MenuItem::new("Toggle Digit Grouping").command(TOGGLE_DIGIT_GROUPS))
+            .separator()
+            .entry(MenuItem::new("Convert Line Endings to LF").command(CONVERT_EOL.with("\n")))
+            .entry(MenuItem::new("Convert Line Endings to CRLF").command(CONVERT_EOL.with("\r\n")))
+            .separator()
+            .entry(MenuItem::new("Accept Ours").command(RESOLVE_CONFLICT.with(Resolution::Ours)))
+            .entry(
+                MenuItem::new("Accept Theirs").command(RESOLVE_CONFLICT.with(Resolution::Theirs)),
+            )
+            .entry(MenuItem::new("Accept Both").command(RESOLVE_CONFLICT.with(Resolution::Both))),
     )
+    .entry(
+        Menu::new("View")
+            .entry(
+                MenuItem::new("Show Invisible Characters")
+                    .command(TOGGLE_INVISIBLES)
+                    .selected_if(|data: &AppState, _env| data.settings.show_invisibles),
+            )
+            .entry(
+                MenuItem::new("Split View")
+                    .command(TOGGLE_SPLIT_VIEW)
+                    .selected_if(|data: &AppState, _env| data.split_view),
+            )
+            .entry(
+                MenuItem::new("Show Unmapped Captures")
+                    .command(TOGGLE_CAPTURES_PANEL)
+                    .selected_if(|data: &AppState, _env| data.captures_panel),
+            )
+            .entry(
+                MenuItem::new("Theme Preview")
+                    .command(TOGGLE_THEME_PREVIEW)
+                    .selected_if(|data: &AppState, _env| data.theme_preview),
+            )
+            .entry(
+                MenuItem::new("Reparse Document")
+                    .command(REPARSE)
+                    .enabled_if(|data: &AppState, _env| data.code.is_highlighted()),
+            ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_reaches_editor() {
+        let mut data = AppState::new("aé\nb", Settings::default(), Theme::default());
+        data.set_selection(2..10);
+        assert_eq!(data.selection(), Selection::new(2, 10));
+        // The delegate sends the pending selection to the editor once the
+        // command is handled, which clamps it to the document.
+        let sent = data.pending_selection.take().unwrap();
+        assert_eq!(
+            editor::clamp_selection(&data.code.buffer, sent),
+            Selection::new(1, 5)
+        );
+    }
+
+    #[test]
+    fn sort_imports_keeps_code() {
+        let text = "import sys\n# Paths\nimport os\n\nx = 1  \nimport re\n";
+        let mut data = AppState::new(text, Settings::default(), Theme::default());
+        data.sort_imports();
+        assert_eq!(
+            data.code.buffer,
+            "# Paths\nimport os\nimport sys\n\nx = 1  \nimport re\n"
+        );
+    }
+
+    #[test]
+    fn eol_indicator() {
+        for (text, indicator) in [
+            ("a", ""),
+            ("a\nb\n", "LF"),
+            ("a\r\nb\r\n", "CRLF"),
+            ("a\r\nb\nc", "Mixed"),
+        ] {
+            let data = AppState::new(text, Settings::default(), Theme::default());
+            assert_eq!(data.eol_indicator(), indicator, "{:?}", text);
+        }
+    }
 }
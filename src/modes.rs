@@ -0,0 +1,60 @@
+//! Transient editor modes, which Escape leaves one at a time.
+
+/// A transient mode of an editor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// The tab stops of a snippet are being filled in.
+    Snippet,
+    /// An identifier is being renamed.
+    Rename,
+    /// A definition is shown below the caret line.
+    Peek,
+    /// The definitions are being searched by name to go to one.
+    Symbols,
+    /// The same columns of several lines are selected and edited together.
+    Block,
+}
+
+/// The active modes of an editor, most recently entered last. Escape leaves
+/// the most recently entered mode first.
+#[derive(Debug, Default)]
+pub struct Modes {
+    stack: Vec<Mode>,
+}
+
+impl Modes {
+    /// Enter `mode`, making it the most recent mode if it is active already.
+    pub fn enter(&mut self, mode: Mode) {
+        self.leave(mode);
+        self.stack.push(mode);
+    }
+
+    /// Leave `mode`, wherever it is in the stack.
+    pub fn leave(&mut self, mode: Mode) {
+        self.stack.retain(|&other| other != mode);
+    }
+
+    /// Leave the most recently entered mode and return it.
+    pub fn pop(&mut self) -> Option<Mode> {
+        self.stack.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reenter_and_leave() {
+        let mut modes = Modes::default();
+        modes.enter(Mode::Snippet);
+        modes.enter(Mode::Rename);
+        modes.enter(Mode::Peek);
+        // Entering an active mode again moves it to the top.
+        modes.enter(Mode::Snippet);
+        modes.leave(Mode::Rename);
+        assert_eq!(modes.pop(), Some(Mode::Snippet));
+        assert_eq!(modes.pop(), Some(Mode::Peek));
+        assert_eq!(modes.pop(), None);
+    }
+}
@@ -0,0 +1,274 @@
+//! Transient messages stacked above the status bar, which disappear on their
+//! own after a while or when clicked.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use druid::piet::{PietTextLayout, Text, TextLayout, TextLayoutBuilder};
+use druid::widget::prelude::*;
+use druid::{Color, Data, FontFamily, Rect, TimerToken};
+
+use crate::AppState;
+
+const FONT_SIZE: f64 = 13.0;
+const PADDING: f64 = 6.0;
+/// The space between two notifications.
+const SPACING: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Data)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    /// How long a notification of this severity is shown. More severe ones
+    /// stay longer so they are not missed.
+    fn timeout(self) -> Duration {
+        match self {
+            Severity::Info => Duration::from_secs(3),
+            Severity::Warning => Duration::from_secs(5),
+            Severity::Error => Duration::from_secs(8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Data)]
+pub struct Notification {
+    pub severity: Severity,
+    pub text: String,
+    /// When the notification disappears.
+    #[data(same_fn = "PartialEq::eq")]
+    pub expires: Instant,
+}
+
+/// The notifications being shown, oldest first.
+#[derive(Debug, Clone, Default, Data)]
+pub struct Notifications {
+    list: Arc<Vec<Notification>>,
+}
+
+impl Notifications {
+    /// Show `text` for the timeout of its `severity`, starting at `now`.
+    pub fn push(&mut self, severity: Severity, text: impl Into<String>, now: Instant) {
+        Arc::make_mut(&mut self.list).push(Notification {
+            severity,
+            text: text.into(),
+            expires: now + severity.timeout(),
+        });
+    }
+
+    /// Show an informational message from now on.
+    pub fn info(&mut self, text: impl Into<String>) {
+        self.push(Severity::Info, text, Instant::now());
+    }
+
+    /// Show an error message from now on.
+    pub fn error(&mut self, text: impl Into<String>) {
+        self.push(Severity::Error, text, Instant::now());
+    }
+
+    /// Show a warning from now on.
+    pub fn warn(&mut self, text: impl Into<String>) {
+        self.push(Severity::Warning, text, Instant::now());
+    }
+
+    /// Remove the notifications that expired by `now`.
+    pub fn expire(&mut self, now: Instant) {
+        if self
+            .list
+            .iter()
+            .any(|notification| notification.expires <= now)
+        {
+            Arc::make_mut(&mut self.list).retain(|notification| notification.expires > now);
+        }
+    }
+
+    /// Remove the notification at `index`.
+    pub fn dismiss(&mut self, index: usize) {
+        if index < self.list.len() {
+            Arc::make_mut(&mut self.list).remove(index);
+        }
+    }
+
+    /// How long until the next notification expires, `None` if there are
+    /// none.
+    pub fn next_delay(&self) -> Option<Duration> {
+        let expiry = self
+            .list
+            .iter()
+            .map(|notification| notification.expires)
+            .min()?;
+        Some(expiry.saturating_duration_since(Instant::now()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Notification> {
+        self.list.iter()
+    }
+}
+
+/// Shows [`AppState::notifications`] stacked on top of each other, taking no
+/// space when there are none.
+pub struct Toasts {
+    /// The laid out text of each notification with its color.
+    layouts: Vec<(PietTextLayout, Color)>,
+    timer: TimerToken,
+}
+
+impl Toasts {
+    pub fn new() -> Self {
+        Toasts {
+            layouts: Vec::new(),
+            timer: TimerToken::INVALID,
+        }
+    }
+
+    /// The height of a notification with the given text layout.
+    fn row_height(layout: &PietTextLayout) -> f64 {
+        layout.size().height + 2.0 * PADDING + SPACING
+    }
+}
+
+impl Widget<AppState> for Toasts {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, _env: &Env) {
+        match event {
+            Event::Timer(token) if *token == self.timer => {
+                data.notifications.expire(Instant::now());
+                if let Some(delay) = data.notifications.next_delay() {
+                    self.timer = ctx.request_timer(delay);
+                }
+            }
+            Event::MouseDown(mouse) => {
+                let mut y = 0.0;
+                for (i, (layout, _)) in self.layouts.iter().enumerate() {
+                    y += Self::row_height(layout);
+                    if mouse.pos.y < y {
+                        data.notifications.dismiss(i);
+                        ctx.set_handled();
+                        break;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn lifecycle(
+        &mut self,
+        _ctx: &mut LifeCycleCtx,
+        _event: &LifeCycle,
+        _data: &AppState,
+        _env: &Env,
+    ) {
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, _env: &Env) {
+        if !old_data.notifications.same(&data.notifications) {
+            if let Some(delay) = data.notifications.next_delay() {
+                self.timer = ctx.request_timer(delay);
+            }
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        env: &Env,
+    ) -> Size {
+        let text_color = env.get(druid::theme::TEXT_COLOR);
+        self.layouts = data
+            .notifications
+            .iter()
+            .map(|notification| {
+                let layout = ctx
+                    .text()
+                    .new_text_layout(notification.text.clone())
+                    .font(FontFamily::SYSTEM_UI, FONT_SIZE)
+                    .text_color(text_color.clone())
+                    .build()
+                    .unwrap();
+                let color = match notification.severity {
+                    Severity::Info => data.theme.selection_background.clone(),
+                    Severity::Warning => data.theme.warning.clone(),
+                    Severity::Error => data.theme.error.clone(),
+                };
+                (layout, color)
+            })
+            .collect();
+        let height = self
+            .layouts
+            .iter()
+            .map(|(layout, _)| Self::row_height(layout))
+            .sum();
+        bc.constrain(Size::new(bc.max().width, height))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &AppState, _env: &Env) {
+        let width = ctx.size().width;
+        let mut y = 0.0;
+        for (layout, color) in &self.layouts {
+            let height = layout.size().height + 2.0 * PADDING;
+            let rect = Rect::new(0.0, y, width, y + height);
+            ctx.fill(rect, &color.clone().with_alpha(0.35));
+            ctx.draw_text(layout, (PADDING, y + PADDING));
+            y += height + SPACING;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(notifications: &Notifications) -> Vec<&str> {
+        notifications
+            .iter()
+            .map(|notification| notification.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn expire_by_severity() {
+        let now = Instant::now();
+        let mut notifications = Notifications::default();
+        notifications.push(Severity::Error, "error", now);
+        notifications.push(Severity::Info, "info", now);
+        notifications.push(Severity::Warning, "warning", now);
+        notifications.expire(now + Duration::from_secs(4));
+        assert_eq!(texts(&notifications), ["error", "warning"]);
+        notifications.expire(now + Duration::from_secs(8));
+        assert!(texts(&notifications).is_empty());
+    }
+
+    #[test]
+    fn dismiss() {
+        let mut notifications = Notifications::default();
+        notifications.info("first");
+        notifications.warn("second");
+        notifications.error("third");
+        notifications.dismiss(1);
+        notifications.dismiss(2);
+        assert_eq!(texts(&notifications), ["first", "third"]);
+    }
+
+    #[test]
+    fn next_delay() {
+        let mut notifications = Notifications::default();
+        assert_eq!(notifications.next_delay(), None);
+        notifications.error("error");
+        notifications.info("info");
+        let delay = notifications.next_delay().unwrap();
+        assert!(delay <= Severity::Info.timeout() && delay > Duration::from_secs(2));
+        // Expired notifications are due right away.
+        notifications.push(
+            Severity::Info,
+            "late",
+            Instant::now() - Duration::from_secs(5),
+        );
+        assert_eq!(notifications.next_delay(), Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,142 @@
+//! Closing brackets and quotes automatically as they are opened, and typing
+//! over the closing ones.
+
+use std::str::FromStr;
+use std::sync::Arc;
+
+use druid::Data;
+
+use crate::settings::Settings;
+
+/// The pairs of characters closed automatically, written as each opening
+/// character followed by its closing one, e.g. `()[]{}""''`. A quote is its
+/// own closing character.
+#[derive(Debug, Clone, PartialEq, Eq, Data)]
+pub struct Pairs(Arc<Vec<(char, char)>>);
+
+impl Pairs {
+    /// The closing character of `open`, if it opens a pair.
+    pub fn closer(&self, open: char) -> Option<char> {
+        self.0
+            .iter()
+            .find(|&&(other, _)| other == open)
+            .map(|&(_, close)| close)
+    }
+
+    /// Returns `true` if `c` closes a pair.
+    pub fn is_closer(&self, c: char) -> bool {
+        self.0.iter().any(|&(_, close)| close == c)
+    }
+}
+
+impl Default for Pairs {
+    fn default() -> Self {
+        "()[]{}\"\"''".parse().unwrap()
+    }
+}
+
+impl FromStr for Pairs {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        if chars.len() % 2 != 0 {
+            return Err(());
+        }
+        let pairs = chars.chunks(2).map(|pair| (pair[0], pair[1])).collect();
+        Ok(Pairs(Arc::new(pairs)))
+    }
+}
+
+/// What typing a character at a caret does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PairAction {
+    /// Insert the character as usual.
+    Insert,
+    /// Insert the character followed by the given closing one, leaving the
+    /// caret between them.
+    Close(char),
+    /// Insert nothing and move the caret to the given offset, past the
+    /// closing character that was typed.
+    TypeOver(usize),
+}
+
+/// What typing `typed` at `caret` in `buffer` does with the pairs and
+/// options of `settings`.
+///
+/// Typing a closing character right before the same one types over it, also
+/// across spaces and tabs with `typeover_whitespace`. Typing an opening
+/// character inserts its closing one too, except for quotes following a word,
+/// like the apostrophe of `don't`, and, with `close_quotes_before_space_only`,
+/// quotes followed by anything but whitespace or the end of the line.
+pub fn action(buffer: &str, caret: usize, typed: char, settings: &Settings) -> PairAction {
+    let pairs = &settings.auto_close_pairs;
+    let after = &buffer[caret..];
+    if pairs.is_closer(typed) {
+        let skipped = if settings.typeover_whitespace {
+            after.len() - after.trim_start_matches(&[' ', '\t'][..]).len()
+        } else {
+            0
+        };
+        if after[skipped..].starts_with(typed) {
+            return PairAction::TypeOver(caret + skipped + typed.len_utf8());
+        }
+    }
+    let close = match pairs.closer(typed) {
+        Some(close) => close,
+        None => return PairAction::Insert,
+    };
+    if close == typed {
+        let before = buffer[..caret].chars().next_back();
+        if before.map_or(false, |c| c.is_alphanumeric() || c == '_') {
+            return PairAction::Insert;
+        }
+        let next = after.chars().next();
+        if settings.close_quotes_before_space_only && next.map_or(false, |c| !c.is_whitespace()) {
+            return PairAction::Insert;
+        }
+    }
+    PairAction::Close(close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let pairs: Pairs = "() <>".parse().unwrap();
+        assert_eq!(pairs.closer('<'), Some('>'));
+        assert_eq!(pairs.closer('['), None);
+        assert!(pairs.is_closer(')'));
+        assert!("()[".parse::<Pairs>().is_err());
+    }
+
+    #[test]
+    fn apostrophe() {
+        let settings = Settings::default();
+        assert_eq!(action("don", 3, '\'', &settings), PairAction::Insert);
+        assert_eq!(
+            action("s = \"don\"", 8, '\'', &settings),
+            PairAction::Insert
+        );
+        assert_eq!(action("s = ", 4, '\'', &settings), PairAction::Close('\''));
+        // Only before whitespace or the end of the line.
+        assert_eq!(action("s = x", 4, '\'', &settings), PairAction::Insert);
+    }
+
+    #[test]
+    fn typeover() {
+        let settings = Settings::default();
+        assert_eq!(action("f", 1, '(', &settings), PairAction::Close(')'));
+        assert_eq!(action("f(x)", 3, ')', &settings), PairAction::TypeOver(4));
+        assert_eq!(action("f(x )", 3, ')', &settings), PairAction::Insert);
+        assert_eq!(
+            action("s = ''", 5, '\'', &settings),
+            PairAction::TypeOver(6)
+        );
+        let mut spaced = Settings::default();
+        spaced.typeover_whitespace = true;
+        assert_eq!(action("f(x )", 3, ')', &spaced), PairAction::TypeOver(5));
+    }
+}
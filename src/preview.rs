@@ -0,0 +1,93 @@
+//! A preview of the theme on a fixed sample document.
+
+use druid::widget::prelude::*;
+use druid::widget::RawLabel;
+use druid::{FontDescriptor, FontFamily, Point, WidgetPod};
+
+use crate::codetext::CodeText;
+use crate::AppState;
+
+const FONT_SIZE: f64 = 13.0;
+const PADDING: f64 = 8.0;
+
+/// The previewed sample, using as many kinds of tokens as possible.
+const SAMPLE: &str = r#"# Comments, keywords, strings, numbers and calls
+import math
+from dataclasses import dataclass
+
+@dataclass
+class Circle:
+    """A circle around the origin."""
+    radius: float = 1.0
+
+    def area(self) -> float:
+        if self.radius < 0:
+            raise ValueError("negative radius\n")
+        return math.pi * self.radius ** 2
+
+print(f"{Circle().area():.2f}", len([1, 2.5, 0x3]), None, True)"#;
+
+/// The sample document highlighted and rendered with the colors of the
+/// current theme, updating as the theme changes. The sample cannot be
+/// edited.
+pub struct ThemePreview {
+    sample: CodeText,
+    label: WidgetPod<CodeText, RawLabel<CodeText>>,
+}
+
+impl ThemePreview {
+    pub fn new() -> Self {
+        let font = FontDescriptor::new(FontFamily::MONOSPACE).with_size(FONT_SIZE);
+        ThemePreview {
+            sample: CodeText::new(SAMPLE.to_owned()),
+            label: WidgetPod::new(RawLabel::new().with_font(font)),
+        }
+    }
+}
+
+impl Widget<AppState> for ThemePreview {
+    fn event(&mut self, _ctx: &mut EventCtx, _event: &Event, _data: &mut AppState, _env: &Env) {}
+
+    fn lifecycle(
+        &mut self,
+        ctx: &mut LifeCycleCtx,
+        event: &LifeCycle,
+        _data: &AppState,
+        env: &Env,
+    ) {
+        self.label.lifecycle(ctx, event, &self.sample, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        // The theme reaches the sample through the environment.
+        self.label.update(ctx, &self.sample, env);
+        if !old_data.theme.same(&data.theme) {
+            ctx.request_paint();
+        }
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        _data: &AppState,
+        env: &Env,
+    ) -> Size {
+        let label_bc = bc.shrink((2.0 * PADDING, 2.0 * PADDING)).loosen();
+        let size = self.label.layout(ctx, &label_bc, &self.sample, env);
+        self.label
+            .set_origin(ctx, &self.sample, env, Point::new(PADDING, PADDING));
+        bc.constrain(Size::new(bc.max().width, size.height + 2.0 * PADDING))
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        let frame = ctx
+            .size()
+            .to_rect()
+            .inset(-PADDING / 2.0)
+            .to_rounded_rect(4.0);
+        ctx.fill(frame, &data.theme.background);
+        ctx.stroke(frame, &data.theme.line_number, 1.0);
+        self.label.paint(ctx, &self.sample, env);
+    }
+}
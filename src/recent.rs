@@ -0,0 +1,86 @@
+//! Recently opened files and the caret positions they were left at.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::{self, ConfigError};
+
+/// The maximum number of files remembered.
+const MAX_ENTRIES: usize = 100;
+
+/// The caret positions of recently opened files, most recent first.
+///
+/// They are stored in the `recent` file in the config directory, in the
+/// format described in [`config`](crate::config) with entries of the form
+/// `caret = path`, since paths may contain `=` themselves.
+#[derive(Debug, Clone, Default)]
+pub struct RecentFiles {
+    entries: Vec<(PathBuf, usize)>,
+}
+
+impl RecentFiles {
+    /// Parse the contents of a recent files file.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let entries = config::parse_entries(source)?
+            .into_iter()
+            .map(|(line, caret, path)| Ok((PathBuf::from(path), config::parse_value(line, caret)?)))
+            .collect::<Result<_, ConfigError>>()?;
+        Ok(RecentFiles { entries })
+    }
+
+    /// Load the user's recent files from the config directory, or why loading
+    /// failed.
+    pub fn load_user() -> Result<Self, String> {
+        config::load_user("recent", RecentFiles::parse)
+    }
+
+    /// Write the recent files to the config directory.
+    pub fn save_user(&self) -> io::Result<()> {
+        let dir = config::config_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+        fs::create_dir_all(&dir)?;
+        let source: String = self
+            .entries
+            .iter()
+            .map(|(path, caret)| format!("{} = {}\n", caret, path.display()))
+            .collect();
+        fs::write(dir.join("recent"), source)
+    }
+
+    /// The caret position the file at `path` was left at.
+    pub fn caret(&self, path: &Path) -> Option<usize> {
+        let path = absolute(path);
+        self.entries
+            .iter()
+            .find(|(other, _)| *other == path)
+            .map(|&(_, caret)| caret)
+    }
+
+    /// The most recently used file and the caret position it was left at.
+    pub fn most_recent(&self) -> Option<(&Path, usize)> {
+        self.entries
+            .first()
+            .map(|(path, caret)| (path.as_path(), *caret))
+    }
+
+    /// Forget the file at `path`, e.g. because it no longer exists.
+    pub fn forget(&mut self, path: &Path) {
+        let path = absolute(path);
+        self.entries.retain(|(other, _)| *other != path);
+    }
+
+    /// Remember `caret` for the file at `path`, making it the most recent
+    /// file and forgetting the least recent ones beyond the limit.
+    pub fn record(&mut self, path: &Path, caret: usize) {
+        let path = absolute(path);
+        self.entries.retain(|(other, _)| *other != path);
+        self.entries.insert(0, (path, caret));
+        self.entries.truncate(MAX_ENTRIES);
+    }
+}
+
+/// `path` made absolute, so that a file is found no matter how it was opened.
+fn absolute(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_owned())
+}
@@ -0,0 +1,120 @@
+//! Finding the files related to a file by naming conventions, such as the
+//! tests of a module.
+//!
+//! A convention is a pattern for file stems in which `{}` stands for the stem
+//! of the source file, e.g. `test_{}` relates `foo.py` and `test_foo.py`.
+//! Related files have the same extension. They are looked for in the
+//! directory of the file, and tests also in `tests` or `test` directories
+//! next to their source.
+
+use std::path::{Path, PathBuf};
+
+/// The directories next to the source files that tests may be kept in.
+const TEST_DIRS: &[&str] = &["tests", "test"];
+
+/// The files the file at `path` may be related to by one of the `patterns`,
+/// most likely first, whether they exist or not.
+///
+/// If the stem of the file matches a pattern, it is the counterpart of a
+/// source file, e.g. `test_foo.py` of `foo.py`. Otherwise, the candidates
+/// are the counterparts of the file itself.
+pub fn counterparts(path: &Path, patterns: &[&str]) -> Vec<PathBuf> {
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => stem,
+        None => return Vec::new(),
+    };
+    let file_name = |stem: &str| match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => format!("{}.{}", stem, ext),
+        None => stem.to_owned(),
+    };
+    let in_test_dir = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| TEST_DIRS.contains(&name));
+    let mut candidates = Vec::new();
+    for pattern in patterns {
+        if let Some(source) = match_pattern(pattern, stem) {
+            let source = file_name(source);
+            candidates.push(dir.join(&source));
+            if let (true, Some(parent)) = (in_test_dir, dir.parent()) {
+                candidates.push(parent.join(&source));
+            }
+        }
+    }
+    if !candidates.is_empty() {
+        return candidates;
+    }
+    for pattern in patterns {
+        let related = file_name(&pattern.replacen("{}", stem, 1));
+        candidates.push(dir.join(&related));
+        candidates.extend(
+            TEST_DIRS
+                .iter()
+                .map(|test_dir| dir.join(test_dir).join(&related)),
+        );
+    }
+    candidates
+}
+
+/// The part of `stem` standing for `{}` in `pattern`, `None` if `stem` does
+/// not match the pattern.
+fn match_pattern<'a>(pattern: &str, stem: &'a str) -> Option<&'a str> {
+    let (prefix, suffix) = pattern.split_once("{}")?;
+    let source = stem.strip_prefix(prefix)?.strip_suffix(suffix)?;
+    (!source.is_empty()).then(|| source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: &[&str] = &["test_{}", "{}_test"];
+
+    fn paths(paths: &[&str]) -> Vec<PathBuf> {
+        paths.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn tests_of_source() {
+        assert_eq!(
+            counterparts(Path::new("src/foo.py"), PATTERNS),
+            paths(&[
+                "src/test_foo.py",
+                "src/tests/test_foo.py",
+                "src/test/test_foo.py",
+                "src/foo_test.py",
+                "src/tests/foo_test.py",
+                "src/test/foo_test.py",
+            ])
+        );
+    }
+
+    #[test]
+    fn source_of_tests() {
+        assert_eq!(
+            counterparts(Path::new("src/test_foo.py"), PATTERNS),
+            paths(&["src/foo.py"])
+        );
+        assert_eq!(
+            counterparts(Path::new("src/foo_test.py"), PATTERNS),
+            paths(&["src/foo.py"])
+        );
+        assert_eq!(
+            counterparts(Path::new("pkg/tests/test_foo.py"), PATTERNS),
+            paths(&["pkg/tests/foo.py", "pkg/foo.py"])
+        );
+    }
+
+    #[test]
+    fn without_extension() {
+        assert_eq!(
+            counterparts(Path::new("bin/tool"), &["test_{}"]),
+            paths(&["bin/test_tool", "bin/tests/test_tool", "bin/test/test_tool"])
+        );
+        assert_eq!(
+            counterparts(Path::new("bin/test_tool"), &["test_{}"]),
+            paths(&["bin/tool"])
+        );
+    }
+}
@@ -0,0 +1,193 @@
+//! Resolution of the scopes Python variables refer to.
+//!
+//! tree-sitter-python does not ship a locals query, so scopes are resolved
+//! from the tree following Python's rules: a name assigned in a function is
+//! local to it unless declared `global` or `nonlocal`, and other names are
+//! looked up in the enclosing functions and then in the module. Class bodies
+//! and comprehensions are not treated as scopes of their own.
+
+use std::collections::HashSet;
+use std::ops::Range;
+
+use tree_sitter::Node;
+
+/// The scope a variable reference resolves to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableScope {
+    /// Bound in the function the reference is in.
+    Local,
+    /// Bound in an enclosing function.
+    Enclosing,
+    /// Bound in the module, or a builtin.
+    Global,
+}
+
+/// The names bound in a function and the names it declares `global` or
+/// `nonlocal`.
+#[derive(Default)]
+struct Scope<'a> {
+    locals: HashSet<&'a str>,
+    globals: HashSet<&'a str>,
+    nonlocals: HashSet<&'a str>,
+}
+
+/// The byte ranges of all variable references under `root`, a Python module,
+/// with the scope they resolve to, in document order.
+pub fn resolve_python(root: Node, source: &[u8]) -> Vec<(Range<usize>, VariableScope)> {
+    let mut references = Vec::new();
+    visit(root, source, &mut Vec::new(), &mut references);
+    references
+}
+
+fn visit<'a>(
+    node: Node,
+    source: &'a [u8],
+    functions: &mut Vec<Scope<'a>>,
+    references: &mut Vec<(Range<usize>, VariableScope)>,
+) {
+    match node.kind() {
+        "identifier" => {
+            if let (true, Ok(name)) = (is_variable(node), node.utf8_text(source)) {
+                references.push((node.byte_range(), resolve(name, functions)));
+            }
+            return;
+        }
+        "function_definition" => {
+            if let Some(name) = node.child_by_field_name("name") {
+                visit(name, source, functions, references);
+            }
+            let mut scope = Scope::default();
+            if let Some(parameters) = node.child_by_field_name("parameters") {
+                collect_targets(parameters, source, &mut scope);
+            }
+            if let Some(body) = node.child_by_field_name("body") {
+                collect_bindings(body, source, &mut scope);
+                functions.push(scope);
+                if let Some(parameters) = node.child_by_field_name("parameters") {
+                    visit(parameters, source, functions, references);
+                }
+                visit(body, source, functions, references);
+                functions.pop();
+            }
+            return;
+        }
+        _ => {}
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        visit(child, source, functions, references);
+    }
+}
+
+/// Returns `false` for identifiers that name an attribute or a keyword
+/// argument rather than a variable.
+fn is_variable(node: Node) -> bool {
+    let parent = match node.parent() {
+        Some(parent) => parent,
+        None => return true,
+    };
+    match parent.kind() {
+        "attribute" => parent.child_by_field_name("attribute") != Some(node),
+        "keyword_argument" => parent.child_by_field_name("name") != Some(node),
+        _ => true,
+    }
+}
+
+/// The scope `name` resolves to when referenced in the innermost of
+/// `functions`.
+fn resolve(name: &str, functions: &[Scope]) -> VariableScope {
+    let (innermost, enclosing) = match functions.split_last() {
+        Some(split) => split,
+        None => return VariableScope::Global,
+    };
+    if innermost.globals.contains(name) {
+        VariableScope::Global
+    } else if innermost.nonlocals.contains(name) {
+        VariableScope::Enclosing
+    } else if innermost.locals.contains(name) {
+        VariableScope::Local
+    } else if enclosing.iter().any(|scope| {
+        !scope.globals.contains(name)
+            && (scope.locals.contains(name) || scope.nonlocals.contains(name))
+    }) {
+        VariableScope::Enclosing
+    } else {
+        VariableScope::Global
+    }
+}
+
+/// Collect the names bound and declared in the function body `node`, without
+/// descending into nested functions and classes.
+fn collect_bindings<'a>(node: Node, source: &'a [u8], scope: &mut Scope<'a>) {
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        match child.kind() {
+            "global_statement" | "nonlocal_statement" => {
+                let declared = if child.kind() == "global_statement" {
+                    &mut scope.globals
+                } else {
+                    &mut scope.nonlocals
+                };
+                let mut cursor = child.walk();
+                for name in child.named_children(&mut cursor) {
+                    if let Ok(name) = name.utf8_text(source) {
+                        declared.insert(name);
+                    }
+                }
+            }
+            "assignment" | "augmented_assignment" | "for_statement" => {
+                if let Some(left) = child.child_by_field_name("left") {
+                    collect_targets(left, source, scope);
+                }
+                collect_bindings(child, source, scope);
+            }
+            "function_definition" | "class_definition" => {
+                if let Some(name) = child.child_by_field_name("name") {
+                    collect_targets(name, source, scope);
+                }
+            }
+            "named_expression" => {
+                if let Some(name) = child.child_by_field_name("name") {
+                    collect_targets(name, source, scope);
+                }
+                collect_bindings(child, source, scope);
+            }
+            "aliased_import" => {
+                if let Some(alias) = child.child_by_field_name("alias") {
+                    collect_targets(alias, source, scope);
+                }
+            }
+            "lambda" => {}
+            _ => collect_bindings(child, source, scope),
+        }
+    }
+}
+
+/// Collect the names bound by an assignment target or a parameter list,
+/// skipping attributes, subscripts, types and default values.
+fn collect_targets<'a>(node: Node, source: &'a [u8], scope: &mut Scope<'a>) {
+    match node.kind() {
+        "identifier" => {
+            if let Ok(name) = node.utf8_text(source) {
+                scope.locals.insert(name);
+            }
+        }
+        "attribute" | "subscript" => {}
+        "typed_parameter" => {
+            if let Some(name) = node.named_child(0) {
+                collect_targets(name, source, scope);
+            }
+        }
+        "default_parameter" | "typed_default_parameter" => {
+            if let Some(name) = node.child_by_field_name("name") {
+                collect_targets(name, source, scope);
+            }
+        }
+        _ => {
+            let mut cursor = node.walk();
+            for child in node.named_children(&mut cursor) {
+                collect_targets(child, source, scope);
+            }
+        }
+    }
+}
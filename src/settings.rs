@@ -0,0 +1,310 @@
+//! User settings.
+
+use std::sync::Arc;
+
+use druid::{Data, Env, Lens};
+
+use crate::blame::BlameMode;
+use crate::codetext;
+use crate::config::{self, ConfigError};
+use crate::gutter::{GutterClick, LineNumbers};
+use crate::history;
+use crate::language::Language;
+use crate::pairs::Pairs;
+use crate::snippet::Snippet;
+use crate::text;
+use crate::timestamp;
+
+/// Editor behavior configurable by the user.
+///
+/// Settings are loaded from the `settings` file in the config directory, in
+/// the format described in [`config`](crate::config), using the field names
+/// of this struct as keys. Settings that are not specified keep their
+/// default value.
+///
+/// Snippets are added with keys of the form `snippet.<language>.<trigger>`,
+/// e.g. `snippet.python.main = if __name__ == "__main__":\n    ${1:main()}`.
+///
+/// Formatters used by `format_on_save` are set with keys of the form
+/// `formatter.<language>`, e.g. `formatter.python = black -q -`, as described
+/// in [`formatter`](crate::formatter).
+///
+/// Language servers, used when built with the `lsp` feature, are set with keys
+/// of the form `language_server.<language>`, e.g.
+/// `language_server.python = pyright-langserver --stdio`.
+#[derive(Clone, Data, Lens)]
+pub struct Settings {
+    /// Documents larger than this many bytes are shown without syntax
+    /// highlighting.
+    pub highlight_size_limit: usize,
+    /// Whether invisible and control characters such as zero-width spaces are
+    /// marked in the text.
+    pub show_invisibles: bool,
+    /// Whether the file open when the editor was last closed is opened again
+    /// on launch.
+    pub reopen_last_file: bool,
+    /// Whether saving makes the document end with exactly one line break.
+    pub ensure_final_newline: bool,
+    /// Whether documents are formatted with the formatter configured for
+    /// their language before they are saved.
+    pub format_on_save: bool,
+    /// Whether saving removes whitespace from the end of every line.
+    pub trim_trailing_whitespace: bool,
+    /// Whether the status bar shows if the document ends with a line break.
+    pub show_final_newline: bool,
+    /// Whether highlight captures without a color mapping are underlined.
+    pub debug_unmapped_captures: bool,
+    /// One of `absolute`, `relative` or `hybrid`.
+    pub line_numbers: LineNumbers,
+    /// What clicking a line number does, one of `select_line`,
+    /// `toggle_bookmark` or `nothing`. Ctrl+click always toggles a bookmark.
+    pub gutter_click: GutterClick,
+    /// How the commit that last changed each line is shown for files in a
+    /// git repository, one of `off`, `hover` or `column`. Needs a build with
+    /// the `blame` feature.
+    pub blame: BlameMode,
+    /// The name of the editor font, e.g. `JetBrains Mono`. The system
+    /// monospace font is used if empty or if the font is not installed.
+    pub font_family: String,
+    /// Whether the lines of syntax errors, including inconsistently indented
+    /// Python lines, are marked on the scrollbar.
+    pub annotate_syntax_errors: bool,
+    /// Whether typing the colon of a Python `else`, `elif`, `except`,
+    /// `finally` or `case` clause aligns it with the statement it continues.
+    pub dedent_clauses: bool,
+    /// The brackets and quotes closed automatically when typed, each opening
+    /// character followed by its closing one, e.g. `()[]{}""''`. Empty to
+    /// close none.
+    pub auto_close_pairs: Pairs,
+    /// Whether quotes are only closed automatically when followed by
+    /// whitespace or the end of the line.
+    pub close_quotes_before_space_only: bool,
+    /// Whether typing a closing character types over the same one after the
+    /// caret even with spaces or tabs in between.
+    pub typeover_whitespace: bool,
+    /// Whether pasted lines are re-indented to match the line they are
+    /// pasted into.
+    pub reindent_on_paste: bool,
+    /// Whether whitespace at the start and end of the selection is ignored
+    /// when toggling comments and selecting the next occurrence.
+    pub trim_selections: bool,
+    /// Whether pressing Enter removes the whitespace before the caret from
+    /// the line it leaves.
+    pub trim_whitespace_on_enter: bool,
+    /// Whether the line endings of pasted text are converted to those of the
+    /// document.
+    pub normalize_pasted_line_endings: bool,
+    /// Whether files opened with a byte order mark are saved with one.
+    pub preserve_bom: bool,
+    /// Whether the caret line is kept vertically centered while moving
+    /// through the document.
+    pub typewriter_scrolling: bool,
+    /// The number of lines kept visible above and below the caret when
+    /// scrolling it into view.
+    pub scroll_margin: usize,
+    /// Whether Python variables are colored by whether they are local, from
+    /// an enclosing function or global.
+    pub color_variable_scopes: bool,
+    /// Whether a vertical ruler is drawn at `ruler_column`.
+    pub show_ruler: bool,
+    /// The column lines should not extend past, 79 for PEP 8.
+    pub ruler_column: usize,
+    /// Whether the characters past `ruler_column` are highlighted.
+    pub highlight_long_lines: bool,
+    /// The format of the date and time inserted by Insert Date and Time, as
+    /// described in [`timestamp::format`].
+    pub timestamp_format: String,
+    /// The statement inserted by Insert Debug Print, in which `$expr` is
+    /// replaced by the selected expression.
+    pub debug_print_template: String,
+    /// The comma-separated naming conventions of related files, e.g. tests,
+    /// for Go to Related File, as described in [`related`](crate::related).
+    pub related_files: String,
+    /// The maximum number of undo steps.
+    pub undo_limit: usize,
+    /// The width of a tab character, in columns.
+    pub tab_width: usize,
+    /// Whether indentation is made of tabs rather than spaces.
+    pub indent_with_tabs: bool,
+    pub snippets: Arc<Vec<Snippet>>,
+    /// The formatter command of each language that has one.
+    pub formatters: Arc<Vec<(Language, String)>>,
+    /// The language server command of each language that has one.
+    pub language_servers: Arc<Vec<(Language, String)>>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            highlight_size_limit: 2 * 1024 * 1024,
+            show_invisibles: false,
+            reopen_last_file: false,
+            format_on_save: false,
+            ensure_final_newline: false,
+            trim_trailing_whitespace: false,
+            show_final_newline: true,
+            debug_unmapped_captures: false,
+            line_numbers: LineNumbers::Absolute,
+            gutter_click: GutterClick::SelectLine,
+            blame: BlameMode::Off,
+            font_family: String::new(),
+            annotate_syntax_errors: true,
+            dedent_clauses: true,
+            auto_close_pairs: Pairs::default(),
+            close_quotes_before_space_only: true,
+            typeover_whitespace: false,
+            reindent_on_paste: false,
+            trim_selections: false,
+            trim_whitespace_on_enter: false,
+            normalize_pasted_line_endings: true,
+            preserve_bom: true,
+            typewriter_scrolling: false,
+            scroll_margin: 0,
+            color_variable_scopes: false,
+            show_ruler: false,
+            ruler_column: 79,
+            highlight_long_lines: false,
+            timestamp_format: timestamp::DEFAULT_FORMAT.to_owned(),
+            debug_print_template: text::DEFAULT_DEBUG_PRINT.to_owned(),
+            related_files: "test_{}, {}_test".to_owned(),
+            undo_limit: history::DEFAULT_LIMIT,
+            tab_width: 4,
+            indent_with_tabs: false,
+            snippets: Arc::new(Snippet::defaults()),
+            formatters: Arc::new(Vec::new()),
+            language_servers: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl Settings {
+    /// Parse settings from the contents of a settings file.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let mut settings = Settings::default();
+        let mut snippets = Snippet::defaults();
+        let mut formatters = Vec::new();
+        let mut language_servers = Vec::new();
+        for (line, key, value) in config::parse_entries(source)? {
+            if let Some(name) = key.strip_prefix("snippet.") {
+                let snippet = parse_snippet(line, name, value)?;
+                snippets.retain(|other| {
+                    other.language != snippet.language || other.trigger != snippet.trigger
+                });
+                snippets.push(snippet);
+                continue;
+            }
+            if let Some(name) = key.strip_prefix("formatter.") {
+                let language = Language::from_name(name).ok_or_else(|| {
+                    ConfigError::new(line, format!("unknown language `{}`", name))
+                })?;
+                formatters.retain(|(other, _)| *other != language);
+                formatters.push((language, value.to_owned()));
+                continue;
+            }
+            if let Some(name) = key.strip_prefix("language_server.") {
+                let language = Language::from_name(name).ok_or_else(|| {
+                    ConfigError::new(line, format!("unknown language `{}`", name))
+                })?;
+                language_servers.retain(|(other, _)| *other != language);
+                language_servers.push((language, value.to_owned()));
+                continue;
+            }
+            match key {
+                "highlight_size_limit" => {
+                    settings.highlight_size_limit = config::parse_value(line, value)?
+                }
+                "show_invisibles" => settings.show_invisibles = config::parse_value(line, value)?,
+                "reopen_last_file" => settings.reopen_last_file = config::parse_value(line, value)?,
+                "format_on_save" => settings.format_on_save = config::parse_value(line, value)?,
+                "ensure_final_newline" => {
+                    settings.ensure_final_newline = config::parse_value(line, value)?
+                }
+                "trim_trailing_whitespace" => {
+                    settings.trim_trailing_whitespace = config::parse_value(line, value)?
+                }
+                "show_final_newline" => {
+                    settings.show_final_newline = config::parse_value(line, value)?
+                }
+                "debug_unmapped_captures" => {
+                    settings.debug_unmapped_captures = config::parse_value(line, value)?
+                }
+                "line_numbers" => settings.line_numbers = config::parse_value(line, value)?,
+                "gutter_click" => settings.gutter_click = config::parse_value(line, value)?,
+                "blame" => settings.blame = config::parse_value(line, value)?,
+                "font_family" => settings.font_family = value.to_owned(),
+                "annotate_syntax_errors" => {
+                    settings.annotate_syntax_errors = config::parse_value(line, value)?
+                }
+                "dedent_clauses" => settings.dedent_clauses = config::parse_value(line, value)?,
+                "auto_close_pairs" => settings.auto_close_pairs = config::parse_value(line, value)?,
+                "close_quotes_before_space_only" => {
+                    settings.close_quotes_before_space_only = config::parse_value(line, value)?
+                }
+                "typeover_whitespace" => {
+                    settings.typeover_whitespace = config::parse_value(line, value)?
+                }
+                "reindent_on_paste" => {
+                    settings.reindent_on_paste = config::parse_value(line, value)?
+                }
+                "trim_selections" => settings.trim_selections = config::parse_value(line, value)?,
+                "trim_whitespace_on_enter" => {
+                    settings.trim_whitespace_on_enter = config::parse_value(line, value)?
+                }
+                "normalize_pasted_line_endings" => {
+                    settings.normalize_pasted_line_endings = config::parse_value(line, value)?
+                }
+                "preserve_bom" => settings.preserve_bom = config::parse_value(line, value)?,
+                "typewriter_scrolling" => {
+                    settings.typewriter_scrolling = config::parse_value(line, value)?
+                }
+                "scroll_margin" => settings.scroll_margin = config::parse_value(line, value)?,
+                "color_variable_scopes" => {
+                    settings.color_variable_scopes = config::parse_value(line, value)?
+                }
+                "show_ruler" => settings.show_ruler = config::parse_value(line, value)?,
+                "ruler_column" => settings.ruler_column = config::parse_value(line, value)?,
+                "highlight_long_lines" => {
+                    settings.highlight_long_lines = config::parse_value(line, value)?
+                }
+                "timestamp_format" => settings.timestamp_format = value.to_owned(),
+                "debug_print_template" => settings.debug_print_template = value.to_owned(),
+                "related_files" => settings.related_files = value.to_owned(),
+                "undo_limit" => settings.undo_limit = config::parse_value(line, value)?,
+                "tab_width" => settings.tab_width = config::parse_value(line, value)?,
+                "indent_with_tabs" => settings.indent_with_tabs = config::parse_value(line, value)?,
+                _ => return Err(ConfigError::new(line, format!("unknown key `{}`", key))),
+            }
+        }
+        settings.snippets = Arc::new(snippets);
+        settings.formatters = Arc::new(formatters);
+        settings.language_servers = Arc::new(language_servers);
+        Ok(settings)
+    }
+
+    /// Load the user's settings from the config directory, falling back to the
+    /// defaults if there are none. Returns why loading failed if they are
+    /// invalid.
+    pub fn load_user() -> Result<Self, String> {
+        config::load_user("settings", Settings::parse)
+    }
+
+    /// Apply the settings affecting text rendering to the druid environment.
+    pub fn apply(&self, env: &mut Env) {
+        env.set(codetext::SHOW_INVISIBLES, self.show_invisibles);
+        env.set(
+            codetext::DEBUG_UNMAPPED_CAPTURES,
+            self.debug_unmapped_captures,
+        );
+        env.set(codetext::COLOR_VARIABLE_SCOPES, self.color_variable_scopes);
+    }
+}
+
+/// Parse a snippet from the part of its key following `snippet.`.
+fn parse_snippet(line: usize, name: &str, body: &str) -> Result<Snippet, ConfigError> {
+    let (language, trigger) = name
+        .split_once('.')
+        .ok_or_else(|| ConfigError::new(line, "expected `snippet.<language>.<trigger>`"))?;
+    let language = Language::from_name(language)
+        .ok_or_else(|| ConfigError::new(line, format!("unknown language `{}`", language)))?;
+    Ok(Snippet::new(language, trigger, &config::unescape(body)))
+}
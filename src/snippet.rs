@@ -0,0 +1,207 @@
+//! Snippets expanding a trigger word into boilerplate with tab stops.
+//!
+//! Snippet bodies use a subset of the TextMate syntax: `$1` marks an empty tab
+//! stop, `${1:name}` a tab stop with placeholder text and `$0` the final caret
+//! position. Nested placeholders are not supported.
+
+use std::ops::Range;
+
+use crate::language::Language;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Snippet {
+    pub language: Language,
+    pub trigger: String,
+    pub body: String,
+}
+
+impl Snippet {
+    pub fn new(language: Language, trigger: &str, body: &str) -> Self {
+        Snippet {
+            language,
+            trigger: trigger.to_owned(),
+            body: body.to_owned(),
+        }
+    }
+
+    /// The snippets available when no others are configured.
+    pub fn defaults() -> Vec<Snippet> {
+        vec![
+            Snippet::new(
+                Language::Python,
+                "def",
+                "def ${1:name}(${2:args}):\n    ${3:pass}",
+            ),
+            Snippet::new(Language::Python, "class", "class ${1:Name}:\n    ${2:pass}"),
+            Snippet::new(
+                Language::Python,
+                "for",
+                "for ${1:item} in ${2:items}:\n    ${3:pass}",
+            ),
+            Snippet::new(Language::Python, "if", "if ${1:condition}:\n    ${2:pass}"),
+        ]
+    }
+}
+
+/// The text a snippet expands to, along with its tab stops in the order they
+/// are visited.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expansion {
+    pub text: String,
+    /// Byte ranges of the placeholders, relative to the start of `text`.
+    pub stops: Vec<Range<usize>>,
+}
+
+/// Expand a snippet `body`, indenting all lines after the first by `indent`.
+pub fn expand(body: &str, indent: &str) -> Expansion {
+    let mut text = String::new();
+    let mut stops = Vec::new();
+    let mut rest = body;
+    while let Some(dollar) = rest.find('$') {
+        text.push_str(&rest[..dollar].replace('\n', &format!("\n{}", indent)));
+        rest = &rest[dollar + 1..];
+        if let Some((index, placeholder, remainder)) = parse_stop(rest) {
+            let start = text.len();
+            text.push_str(placeholder);
+            stops.push((index, start..text.len()));
+            rest = remainder;
+        } else {
+            text.push('$');
+        }
+    }
+    text.push_str(&rest.replace('\n', &format!("\n{}", indent)));
+    // `$0` is visited last.
+    stops.sort_by_key(|(index, _)| if *index == 0 { usize::MAX } else { *index });
+    Expansion {
+        text,
+        stops: stops.into_iter().map(|(_, range)| range).collect(),
+    }
+}
+
+/// Parse a tab stop following a `$`, returning its index, placeholder and the
+/// remaining body.
+fn parse_stop(rest: &str) -> Option<(usize, &str, &str)> {
+    if let Some(inner) = rest.strip_prefix('{') {
+        let end = inner.find('}')?;
+        let (index, placeholder) = inner[..end].split_once(':').unwrap_or((&inner[..end], ""));
+        Some((index.parse().ok()?, placeholder, &inner[end + 1..]))
+    } else {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        Some((rest[..digits].parse().ok()?, "", &rest[digits..]))
+    }
+}
+
+/// The tab stops of an expanded snippet in the document, kept up to date
+/// while the user fills in the placeholders.
+pub struct Session {
+    stops: Vec<Range<usize>>,
+    current: usize,
+}
+
+impl Session {
+    /// Start a session for an expansion inserted at `offset`, or `None` if it
+    /// has no tab stops.
+    pub fn new(offset: usize, expansion: &Expansion) -> Option<Self> {
+        if expansion.stops.is_empty() {
+            return None;
+        }
+        let stops = expansion
+            .stops
+            .iter()
+            .map(|stop| offset + stop.start..offset + stop.end)
+            .collect();
+        Some(Session { stops, current: 0 })
+    }
+
+    /// The range of the current tab stop.
+    pub fn current(&self) -> Range<usize> {
+        self.stops[self.current].clone()
+    }
+
+    /// Move to the next tab stop, or return `None` if this was the last one.
+    pub fn next_stop(&mut self) -> Option<Range<usize>> {
+        if self.current + 1 < self.stops.len() {
+            self.current += 1;
+            Some(self.current())
+        } else {
+            None
+        }
+    }
+
+    /// Move to the previous tab stop, staying at the first one.
+    pub fn prev_stop(&mut self) -> Range<usize> {
+        self.current = self.current.saturating_sub(1);
+        self.current()
+    }
+
+    /// Account for an edit that changed the document length by `delta` and
+    /// left the caret at `caret`. Returns `false` if the edit happened outside
+    /// of the current tab stop, ending the session.
+    pub fn edited(&mut self, caret: usize, delta: isize) -> bool {
+        let current = self.current();
+        let end = (current.end as isize + delta) as usize;
+        if caret < current.start || caret > end {
+            return false;
+        }
+        for stop in &mut self.stops {
+            if stop.start >= current.end && *stop != current {
+                stop.start = (stop.start as isize + delta) as usize;
+                stop.end = (stop.end as isize + delta) as usize;
+            }
+        }
+        self.stops[self.current].end = end;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_stops() {
+        assert_eq!(parse_stop("1x"), Some((1, "", "x")));
+        assert_eq!(parse_stop("{2:ab}c"), Some((2, "ab", "c")));
+        assert_eq!(parse_stop("{3}"), Some((3, "", "")));
+        assert_eq!(parse_stop("x"), None);
+        assert_eq!(parse_stop("{4:open"), None);
+    }
+
+    #[test]
+    fn expand_stops() {
+        let expansion = expand("a$1b${2:x}c$0", "");
+        assert_eq!(expansion.text, "abxc");
+        assert_eq!(expansion.stops, [1..1, 2..3, 4..4]);
+
+        let expansion = expand("$0a${1:x}", "");
+        assert_eq!(expansion.text, "ax");
+        assert_eq!(expansion.stops, [1..2, 0..0]);
+
+        let expansion = expand("cost: $x", "");
+        assert_eq!(expansion.text, "cost: $x");
+        assert!(expansion.stops.is_empty());
+    }
+
+    #[test]
+    fn expand_indent() {
+        let expansion = expand("if ${1:c}:\n    ${2:pass}", "  ");
+        assert_eq!(expansion.text, "if c:\n      pass");
+        assert_eq!(expansion.stops, [3..4, 12..16]);
+    }
+
+    #[test]
+    fn session_shifts_stops() {
+        let expansion = expand("${1:a} ${2:b}", "");
+        let mut session = Session::new(10, &expansion).unwrap();
+        assert_eq!(session.current(), 10..11);
+        // Replace "a" with "abc".
+        assert!(session.edited(13, 2));
+        assert_eq!(session.current(), 10..13);
+        assert_eq!(session.next_stop(), Some(14..15));
+        assert_eq!(session.next_stop(), None);
+        assert_eq!(session.prev_stop(), 10..13);
+        assert!(!session.edited(20, 1));
+    }
+}
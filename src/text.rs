@@ -0,0 +1,1039 @@
+//! Helpers operating on plain text.
+
+use std::mem;
+use std::ops::Range;
+
+/// The offset of the start of the line containing `offset`.
+pub fn line_start(text: &str, offset: usize) -> usize {
+    text[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+/// The offset of the end of the line containing `offset`, excluding the line
+/// break.
+pub fn line_end(text: &str, offset: usize) -> usize {
+    text[offset..].find('\n').map_or(text.len(), |i| offset + i)
+}
+
+/// The offsets of the starts of all lines in `text`.
+pub fn line_starts(text: &str) -> impl Iterator<Item = usize> + '_ {
+    std::iter::once(0).chain(text.match_indices('\n').map(|(i, _)| i + 1))
+}
+
+/// The zero-based index of the line containing `offset`, clamped to the end
+/// of the text.
+pub fn line_index(text: &str, offset: usize) -> usize {
+    let offset = offset.min(text.len());
+    text.as_bytes()[..offset]
+        .iter()
+        .filter(|&&b| b == b'\n')
+        .count()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+fn indent_width(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// The start of the next blank line that ends the paragraph at `from`, or the
+/// end of the text if there is none. Blank lines directly at `from` are
+/// skipped, so that repeated calls move from paragraph to paragraph.
+pub fn next_blank_line_offset(text: &str, from: usize) -> usize {
+    let mut end = line_end(text, from);
+    let mut seen_content = !is_blank(&text[line_start(text, from)..end]);
+    while end < text.len() {
+        let start = end + 1;
+        end = line_end(text, start);
+        let blank = is_blank(&text[start..end]);
+        if blank && seen_content {
+            return start;
+        }
+        seen_content |= !blank;
+    }
+    text.len()
+}
+
+/// The start of the previous blank line that begins the paragraph at `from`,
+/// or the start of the text if there is none. Blank lines directly at `from`
+/// are skipped, so that repeated calls move from paragraph to paragraph.
+pub fn prev_blank_line_offset(text: &str, from: usize) -> usize {
+    let mut start = line_start(text, from);
+    let mut seen_content = !is_blank(&text[start..line_end(text, from)]);
+    while start > 0 {
+        let end = start - 1;
+        start = line_start(text, end);
+        let blank = is_blank(&text[start..end]);
+        if blank && seen_content {
+            return start;
+        }
+        seen_content |= !blank;
+    }
+    0
+}
+
+/// The whole lines around `offset` that are indented at least as far as the
+/// line containing it, i.e. the indented block it belongs to. Blank lines
+/// within the block are included, blank lines around it are not.
+pub fn indented_block(text: &str, offset: usize) -> Range<usize> {
+    let start = line_start(text, offset);
+    let end = line_end(text, offset);
+    if is_blank(&text[start..end]) {
+        return start..end;
+    }
+    let indent = indent_width(&text[start..end]);
+    let belongs = |line: &str| is_blank(line) || indent_width(line) >= indent;
+    let mut block_start = start;
+    let mut cursor = start;
+    while cursor > 0 {
+        let prev_start = line_start(text, cursor - 1);
+        let prev = &text[prev_start..cursor - 1];
+        if !belongs(prev) {
+            break;
+        }
+        if !is_blank(prev) {
+            block_start = prev_start;
+        }
+        cursor = prev_start;
+    }
+    let mut block_end = end;
+    let mut cursor = end;
+    while cursor < text.len() {
+        let next_end = line_end(text, cursor + 1);
+        let next = &text[cursor + 1..next_end];
+        if !belongs(next) {
+            break;
+        }
+        if !is_blank(next) {
+            block_end = next_end;
+        }
+        cursor = next_end;
+    }
+    block_start..block_end
+}
+
+/// The leading whitespace of `line`.
+pub fn indentation(line: &str) -> &str {
+    &line[..indent_width(line)]
+}
+
+/// The zero-based indices of the non-blank lines of `text` whose indentation
+/// mixes tabs and spaces.
+pub fn mixed_indentation_lines(text: &str) -> Vec<usize> {
+    text.split('\n')
+        .enumerate()
+        .filter(|&(_, line)| {
+            let indent = indentation(line);
+            !is_blank(line) && indent.contains('\t') && indent.contains(' ')
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// `indent` made of only spaces, or of tabs followed by as few spaces as
+/// possible, keeping its width when tabs are `tab_width` columns wide.
+pub fn normalize_indentation(indent: &str, tab_width: usize, use_tabs: bool) -> String {
+    let tab_width = tab_width.max(1);
+    let width = indent.chars().fold(0, |column, c| {
+        if c == '\t' {
+            (column / tab_width + 1) * tab_width
+        } else {
+            column + 1
+        }
+    });
+    if use_tabs {
+        "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+    } else {
+        " ".repeat(width)
+    }
+}
+
+/// The first words of the Python clauses continuing a compound statement,
+/// which are indented like the clause they continue.
+pub const PYTHON_CLAUSE_KEYWORDS: &[&str] = &["else", "elif", "except", "finally", "case"];
+
+/// The indentation of a line starting with the Python clause `keyword`, one
+/// of [`PYTHON_CLAUSE_KEYWORDS`], starting at `start`: that of the closest clause
+/// above it that it may continue and that is indented less than it, e.g. of
+/// the `if` an `else` belongs to. Lines indented less than the line, but not
+/// starting such a clause, only limit the search to clauses indented even
+/// less. Returns `None` if there is no such clause.
+pub fn clause_indent<'a>(text: &'a str, start: usize, keyword: &str) -> Option<&'a str> {
+    let openers: &[&str] = match keyword {
+        "else" => &["if", "elif", "for", "while", "try", "except"],
+        "elif" => &["if", "elif"],
+        "except" => &["try", "except"],
+        "finally" => &["try", "except", "else"],
+        "case" => &["case"],
+        _ => return None,
+    };
+    let mut limit = indent_width(&text[start..line_end(text, start)]);
+    let mut end = start.checked_sub(1)?;
+    loop {
+        let start = line_start(text, end);
+        let line = &text[start..end];
+        let code = line.trim_start();
+        if !code.is_empty() && !code.starts_with('#') && indent_width(line) < limit {
+            let word = code
+                .split(|c: char| !c.is_alphanumeric() && c != '_')
+                .next()
+                .unwrap_or("");
+            if openers.contains(&word) {
+                return Some(indentation(line));
+            }
+            limit = indent_width(line);
+            if limit == 0 {
+                return None;
+            }
+        }
+        end = start.checked_sub(1)?;
+    }
+}
+
+/// The smallest indentation of the non-blank lines of `text`.
+pub fn base_indent(text: &str) -> &str {
+    text.lines()
+        .filter(|line| !is_blank(line))
+        .map(indentation)
+        .min_by_key(|indent| indent.len())
+        .unwrap_or("")
+}
+
+/// Re-indent the lines of `text` from the base indentation `base_from` (see
+/// [`base_indent`]) to `base_to`, keeping their relative indentation.
+///
+/// The first line is only stripped of `base_from`, as it is meant to be
+/// inserted after existing indentation. Blank lines are emptied.
+pub fn reindent_block(text: &str, base_from: &str, base_to: &str) -> String {
+    let lines: Vec<String> = text
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| {
+            let code = line
+                .strip_prefix(base_from)
+                .unwrap_or_else(|| line.trim_start_matches(|c| c == ' ' || c == '\t'));
+            if i == 0 {
+                code.to_owned()
+            } else if is_blank(line) {
+                line.trim_start_matches(|c| c == ' ' || c == '\t')
+                    .to_owned()
+            } else {
+                format!("{}{}", base_to, code)
+            }
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Returns `true` for characters that are invisible or easily confused with
+/// regular whitespace, such as no-break and zero-width spaces.
+fn is_suspicious(c: char) -> bool {
+    match c {
+        '\n' | '\r' | '\t' => false,
+        '\u{a0}' | '\u{ad}' | '\u{feff}' | '\u{3000}' => true,
+        '\u{2000}'..='\u{200f}' | '\u{2028}'..='\u{202f}' | '\u{205f}'..='\u{206f}' => true,
+        c => c.is_control(),
+    }
+}
+
+/// The byte ranges of all invisible or control characters in `text` that are
+/// likely to be there by accident.
+pub fn suspicious_characters(text: &str) -> Vec<(Range<usize>, char)> {
+    text.char_indices()
+        .filter(|&(_, c)| is_suspicious(c))
+        .map(|(i, c)| (i..i + c.len_utf8(), c))
+        .collect()
+}
+
+/// The byte ranges of the parts of the lines of `text` that extend past
+/// `column` characters, excluding line breaks.
+pub fn overlong_line_ranges(text: &str, column: usize) -> Vec<Range<usize>> {
+    line_starts(text)
+        .filter_map(|start| {
+            let line = &text[start..line_end(text, start)];
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            let (i, _) = line.char_indices().nth(column)?;
+            Some(start + i..start + line.len())
+        })
+        .collect()
+}
+
+/// The start of the whitespace directly before `offset` on its line, or
+/// `offset` if it follows a non-whitespace character.
+pub fn trailing_whitespace_start(text: &str, offset: usize) -> usize {
+    let start = line_start(text, offset);
+    start + text[start..offset].trim_end().len()
+}
+
+/// The range replaced and its replacement when Enter is pressed with `range`
+/// selected, trimming the trailing whitespace before it: a line break in
+/// the style of `text` followed by the indentation of the line.
+pub fn trimmed_line_break(text: &str, range: Range<usize>) -> (Range<usize>, String) {
+    let start = trailing_whitespace_start(text, range.start);
+    let indent = indentation(&text[line_start(text, range.start)..range.start]);
+    (start..range.end, format!("{}{}", detect_eol(text), indent))
+}
+
+/// The spaces Backspace at `offset` removes to get back to the previous
+/// indentation level, if only spaces precede `offset` on its line. Returns
+/// `None` elsewhere, where Backspace removes a single character.
+pub fn unindent_range(text: &str, offset: usize, tab_width: usize) -> Option<Range<usize>> {
+    let before = &text[line_start(text, offset)..offset];
+    if before.is_empty() || before.contains(|c| c != ' ') || tab_width == 0 {
+        return None;
+    }
+    let remove = match before.len() % tab_width {
+        0 => tab_width,
+        partial => partial,
+    };
+    Some(offset - remove..offset)
+}
+
+/// The byte ranges of the backslash escape sequences in `literal`, the source
+/// of a string literal, e.g. `\n`, `\x41`, `\u00e9`, `\u{1f600}`, `\N{DASH}`
+/// and the octal `\101`.
+pub fn escape_sequences(literal: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while let Some(backslash) = literal[i..].find('\\') {
+        let start = i + backslash;
+        let end = start + 1 + escape_len(&literal[start + 1..]);
+        if end > start + 1 {
+            ranges.push(start..end);
+        }
+        i = end;
+    }
+    ranges
+}
+
+/// The length of the escape sequence at the start of `rest`, following a
+/// backslash.
+fn escape_len(rest: &str) -> usize {
+    let hex_digits = |max: usize| {
+        rest.bytes()
+            .skip(1)
+            .take(max)
+            .take_while(u8::is_ascii_hexdigit)
+            .count()
+    };
+    match rest.bytes().next() {
+        None => 0,
+        Some(b'u' | b'N') if rest[1..].starts_with('{') => {
+            rest.find('}').map_or(rest.len(), |i| i + 1)
+        }
+        Some(b'x') => 1 + hex_digits(2),
+        Some(b'u') => 1 + hex_digits(4),
+        Some(b'U') => 1 + hex_digits(8),
+        Some(b'0'..=b'7') => rest
+            .bytes()
+            .take(3)
+            .take_while(|b| (b'0'..=b'7').contains(b))
+            .count(),
+        Some(_) => rest.chars().next().map_or(0, char::len_utf8),
+    }
+}
+
+/// The parts of the source of a quoted string literal.
+struct StringLiteral<'a> {
+    /// The prefix, e.g. `f` or `rb`.
+    prefix: &'a str,
+    /// The opening and closing quotes, e.g. `'` or `"""`.
+    delimiter: &'a str,
+    content: &'a str,
+}
+
+impl<'a> StringLiteral<'a> {
+    /// Split `literal` into its parts, returning `None` if it is not a
+    /// complete string literal.
+    fn parse(literal: &'a str) -> Option<Self> {
+        let (prefix, quoted) = literal.split_at(literal.find(|c| c == '\'' || c == '"')?);
+        if !prefix.chars().all(|c| c.is_ascii_alphabetic()) {
+            return None;
+        }
+        let quote = quoted.chars().next()?;
+        let delimiter = if quoted.len() >= 6 && quoted.starts_with(&quote.to_string().repeat(3)) {
+            &quoted[..3]
+        } else {
+            &quoted[..1]
+        };
+        if quoted.len() < 2 * delimiter.len() || !quoted.ends_with(delimiter) {
+            return None;
+        }
+        Some(StringLiteral {
+            prefix,
+            delimiter,
+            content: &quoted[delimiter.len()..quoted.len() - delimiter.len()],
+        })
+    }
+}
+
+/// `literal`, the source of a string literal with an optional prefix such as
+/// `f` or `b`, with its single quotes switched to double quotes or the other
+/// way around. Quotes in the content are escaped or unescaped as needed.
+///
+/// Returns `None` if `literal` is not a complete string literal, for raw
+/// strings, whose backslashes do not escape quotes, and for f-strings whose
+/// content contains the new quote, which may be part of an expression.
+pub fn toggle_quotes(literal: &str) -> Option<String> {
+    let StringLiteral {
+        prefix,
+        delimiter,
+        content,
+    } = StringLiteral::parse(literal)?;
+    if prefix.contains(|c| c == 'r' || c == 'R') {
+        return None;
+    }
+    let (old, new) = if delimiter.starts_with('\'') {
+        ('\'', '"')
+    } else {
+        ('"', '\'')
+    };
+    let new_delimiter = new.to_string().repeat(delimiter.len());
+    if delimiter.len() == 3 {
+        // Quotes need no escaping in triple-quoted strings, unless they would
+        // end the string.
+        if content.contains(&new.to_string().repeat(3)) || content.ends_with(new) {
+            return None;
+        }
+        return Some(format!(
+            "{}{}{}{}",
+            prefix, new_delimiter, content, new_delimiter
+        ));
+    }
+    if prefix.contains(|c| c == 'f' || c == 'F') && content.contains(new) {
+        return None;
+    }
+    let mut toggled = String::with_capacity(literal.len() + 2);
+    toggled.push_str(prefix);
+    toggled.push(new);
+    let mut chars = content.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(escaped) if escaped == old => toggled.push(old),
+                Some(escaped) => {
+                    toggled.push('\\');
+                    toggled.push(escaped);
+                }
+                None => toggled.push('\\'),
+            },
+            c if c == new => {
+                toggled.push('\\');
+                toggled.push(new);
+            }
+            c => toggled.push(c),
+        }
+    }
+    toggled.push(new);
+    Some(toggled)
+}
+
+/// `literal`, the source of a string literal, split at `at` into two adjacent
+/// literals that Python concatenates, e.g. `'ab'` at 2 into `'a' 'b'`.
+/// Returns `None` if `at` is not in the content, or is in an escape sequence
+/// or a replacement field of an f-string.
+pub fn split_string(literal: &str, at: usize) -> Option<String> {
+    let StringLiteral {
+        prefix,
+        delimiter,
+        content,
+    } = StringLiteral::parse(literal)?;
+    let at = at.checked_sub(prefix.len() + delimiter.len())?;
+    if at > content.len() || !content.is_char_boundary(at) {
+        return None;
+    }
+    let raw = prefix.contains(|c| c == 'r' || c == 'R');
+    let in_escape = escape_sequences(content)
+        .iter()
+        .any(|escape| escape.start < at && at < escape.end);
+    if !raw && in_escape {
+        return None;
+    }
+    let (before, after) = content.split_at(at);
+    if prefix.contains(|c| c == 'f' || c == 'F') {
+        let braces = before.replace("{{", "").replace("}}", "");
+        if braces.matches('{').count() != braces.matches('}').count() {
+            return None;
+        }
+    }
+    Some(format!(
+        "{p}{d}{before}{d} {p}{d}{after}{d}",
+        p = prefix,
+        d = delimiter,
+        before = before,
+        after = after
+    ))
+}
+
+/// The adjacent string `literals` joined into one, e.g. `'a'` and `'b'` into
+/// `'ab'`. Returns `None` unless all of them have the same prefix and quotes.
+pub fn join_strings(literals: &[&str]) -> Option<String> {
+    let literals = literals
+        .iter()
+        .map(|literal| StringLiteral::parse(literal))
+        .collect::<Option<Vec<_>>>()?;
+    let first = literals.first()?;
+    let compatible = literals.iter().all(|literal| {
+        literal.prefix.eq_ignore_ascii_case(first.prefix) && literal.delimiter == first.delimiter
+    });
+    if !compatible {
+        return None;
+    }
+    let content: String = literals.iter().map(|literal| literal.content).collect();
+    Some(format!(
+        "{}{}{}{}",
+        first.prefix, first.delimiter, content, first.delimiter
+    ))
+}
+
+/// The UTF-8 byte order mark some editors put at the start of a file.
+pub const BOM: char = '\u{feff}';
+
+/// `text` without a leading byte order mark, and whether it had one.
+pub fn strip_bom(text: &str) -> (&str, bool) {
+    match text.strip_prefix(BOM) {
+        Some(text) => (text, true),
+        None => (text, false),
+    }
+}
+
+/// Make `text` end with exactly one line break, removing any trailing blank
+/// lines. Text without any content is returned unchanged.
+pub fn ensure_final_newline(text: &str) -> String {
+    let content_end = text.trim_end().len();
+    if content_end == 0 {
+        return text.to_owned();
+    }
+    let last_line = &text[..line_end(text, content_end)];
+    let last_line = last_line.strip_suffix('\r').unwrap_or(last_line);
+    format!("{}{}", last_line, detect_eol(text))
+}
+
+/// The line ending of `text`, `\r\n` if its first line ends with one and
+/// `\n` otherwise.
+pub fn detect_eol(text: &str) -> &'static str {
+    match text.find('\n') {
+        Some(i) if text[..i].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+/// `text` with all its line endings, `\r\n`, `\n` or a lone `\r`, replaced by
+/// `eol`.
+pub fn normalize_eol(text: &str, eol: &str) -> String {
+    text.replace("\r\n", "\n")
+        .replace('\r', "\n")
+        .replace('\n', eol)
+}
+
+/// The number literal `literal` with the digits before its decimal point or
+/// exponent grouped by thousands with underscores, e.g. `1_000_000.5` for
+/// `1000000.5`. Literals with a base prefix such as `0x` and literals that
+/// already have underscores are returned as they are.
+pub fn group_digits(literal: &str) -> String {
+    let prefixed = literal.starts_with('0')
+        && literal[1..].starts_with(|c: char| c.is_ascii_alphabetic() && c != 'e' && c != 'E');
+    if prefixed || literal.contains('_') {
+        return literal.to_owned();
+    }
+    let end = literal
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(literal.len());
+    let (digits, rest) = literal.split_at(end);
+    let mut grouped = String::with_capacity(literal.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            grouped.push('_');
+        }
+        grouped.push(digit);
+    }
+    grouped + rest
+}
+
+/// The number literal `literal` without the underscores separating its
+/// digits.
+pub fn ungroup_digits(literal: &str) -> String {
+    literal.replace('_', "")
+}
+
+/// Reflow the line comments of `block`, lines with the same indentation
+/// followed by `prefix` such as `#`, to lines of at most `width` columns as
+/// far as the words allow. Lines with nothing but the prefix separate
+/// paragraphs. Lines indented further after the prefix, e.g. code examples,
+/// are kept as they are.
+pub fn reflow_comment(block: &str, prefix: &str, width: usize) -> String {
+    let indent = indentation(block);
+    let lead = format!("{}{} ", indent, prefix);
+    let wrap = |words: &mut Vec<&str>, lines: &mut Vec<String>| {
+        let mut line = lead.clone();
+        for word in words.drain(..) {
+            let filled = line.len() > lead.len();
+            if filled && line.chars().count() + 1 + word.chars().count() > width {
+                lines.push(mem::replace(&mut line, lead.clone()));
+            } else if filled {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if line.len() > lead.len() {
+            lines.push(line);
+        }
+    };
+    let mut lines = Vec::new();
+    let mut words = Vec::new();
+    for line in block.lines() {
+        let content = line.trim_start();
+        let content = content.strip_prefix(prefix).unwrap_or(content);
+        if content.trim().is_empty() {
+            wrap(&mut words, &mut lines);
+            lines.push(format!("{}{}", indent, prefix));
+        } else if content.starts_with("  ") || content.starts_with('\t') {
+            wrap(&mut words, &mut lines);
+            lines.push(line.to_owned());
+        } else {
+            words.extend(content.split_whitespace());
+        }
+    }
+    wrap(&mut words, &mut lines);
+    lines.join(detect_eol(block))
+}
+
+/// Remove the spaces and tabs at the end of every line of `text`.
+pub fn trim_trailing_whitespace(text: &str) -> String {
+    text.split_inclusive('\n')
+        .map(|line| {
+            let (content, eol) = match line.strip_suffix("\r\n") {
+                Some(content) => (content, "\r\n"),
+                None => match line.strip_suffix('\n') {
+                    Some(content) => (content, "\n"),
+                    None => (line, ""),
+                },
+            };
+            format!("{}{}", content.trim_end_matches(&[' ', '\t'][..]), eol)
+        })
+        .collect()
+}
+
+/// The whole lines touched by `range`, without the final line break, cut off
+/// after `max_lines` lines.
+pub fn lines_excerpt(text: &str, range: Range<usize>, max_lines: usize) -> &str {
+    let start = line_start(text, range.start);
+    let end = line_end(text, range.end);
+    let end = text[start..end]
+        .match_indices('\n')
+        .nth(max_lines.saturating_sub(1))
+        .map_or(end, |(i, _)| start + i);
+    &text[start..end]
+}
+
+/// `offset` clamped to the length of `text` and moved back to the start of
+/// the character it is in.
+pub fn clamp_offset(text: &str, offset: usize) -> usize {
+    let mut offset = offset.min(text.len());
+    while !text.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// The offset `column` bytes into the zero-based line `line`, clamped to the
+/// end of the line and of the text.
+pub fn line_column_offset(text: &str, line: usize, column: usize) -> usize {
+    let start = match line_starts(text).nth(line) {
+        Some(start) => start,
+        None => return text.len(),
+    };
+    clamp_offset(text, (start + column).min(line_end(text, start)))
+}
+
+/// The lengths in bytes of the longest common prefix of `a` and `b` and of
+/// the longest common suffix of what remains of them.
+pub fn common_affixes(a: &str, b: &str) -> (usize, usize) {
+    let prefix = a
+        .char_indices()
+        .zip(b.chars())
+        .find(|&((_, x), y)| x != y)
+        .map_or(a.len().min(b.len()), |((i, _), _)| i);
+    let suffix = a[prefix..]
+        .chars()
+        .rev()
+        .zip(b[prefix..].chars().rev())
+        .take_while(|(x, y)| x == y)
+        .map(|(x, _)| x.len_utf8())
+        .sum();
+    (prefix, suffix)
+}
+
+/// `text` with its last number, a run of ASCII digits, incremented by one,
+/// keeping leading zeros: `item_09` becomes `item_10`. The sign is not taken
+/// into account, so `-1` becomes `-2`. Returns `None` if there is no number.
+pub fn increment_last_number(text: &str) -> Option<String> {
+    let end = text.rfind(|c: char| c.is_ascii_digit())? + 1;
+    let start = text[..end]
+        .trim_end_matches(|c: char| c.is_ascii_digit())
+        .len();
+    let mut digits = text[start..end].as_bytes().to_vec();
+    let mut carry = true;
+    for digit in digits.iter_mut().rev() {
+        if *digit == b'9' {
+            *digit = b'0';
+        } else {
+            *digit += 1;
+            carry = false;
+            break;
+        }
+    }
+    if carry {
+        digits.insert(0, b'1');
+    }
+    let digits = String::from_utf8(digits).expect("ASCII digits");
+    Some(format!("{}{}{}", &text[..start], digits, &text[end..]))
+}
+
+/// The placeholder for the expression in debug print templates.
+pub const DEBUG_PRINT_PLACEHOLDER: &str = "$expr";
+
+/// The default template of debug print statements, printing the expression
+/// along with its value.
+pub const DEFAULT_DEBUG_PRINT: &str = "print(f\"{$expr=}\")";
+
+/// The text to insert at the end of a line indented with `indent` to print
+/// `expr` on a new line after it, by filling in [`DEBUG_PRINT_PLACEHOLDER`]
+/// in `template`. The new line starts with the line ending `eol`. Line
+/// breaks in `expr` are joined with spaces.
+pub fn debug_print_line(template: &str, expr: &str, indent: &str, eol: &str) -> String {
+    let expr = expr.split_whitespace().collect::<Vec<_>>().join(" ");
+    format!(
+        "{}{}{}",
+        eol,
+        indent,
+        template.replace(DEBUG_PRINT_PLACEHOLDER, &expr)
+    )
+}
+
+/// The range to delete to remove the text in `range`: the whole lines with
+/// their line break if nothing else is on them, otherwise `range` itself.
+pub fn removal_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let start = line_start(text, range.start);
+    let end = line_end(text, range.end);
+    if !text[start..range.start].trim().is_empty() || !text[range.end..end].trim().is_empty() {
+        return range;
+    }
+    let end = if end < text.len() { end + 1 } else { end };
+    start..end
+}
+
+/// `range` without the whitespace, including line breaks, at its start and
+/// end. A range of only whitespace is returned unchanged.
+pub fn trim_selection(text: &str, range: Range<usize>) -> Range<usize> {
+    let selected = &text[range.clone()];
+    let trimmed = selected.trim();
+    if trimmed.is_empty() {
+        return range;
+    }
+    let start = range.start + selected.len() - selected.trim_start().len();
+    start..start + trimmed.len()
+}
+
+/// The number of characters in `text`.
+pub fn count_chars(text: &str) -> usize {
+    text.chars().count()
+}
+
+/// The number of whitespace-separated words in `text`.
+pub fn count_words(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+/// The number of lines in `text`, not counting an empty line after a final
+/// line break.
+pub fn count_lines(text: &str) -> usize {
+    text.lines().count()
+}
+
+/// Comment out the lines touched by `range` using the line comment `prefix`
+/// (e.g. `"# "`), or uncomment them if they are all commented out already.
+///
+/// The prefix is inserted at the smallest indentation of the lines, so that
+/// the comment markers form a column. Blank lines are left untouched and are
+/// ignored when deciding whether the lines are commented out, so that
+/// commenting never leaves a comment marker followed by trailing whitespace.
+/// Returns the range of the touched lines and their replacement.
+pub fn toggle_line_comment(
+    text: &str,
+    range: Range<usize>,
+    prefix: &str,
+) -> (Range<usize>, String) {
+    let start = line_start(text, range.start);
+    // A selection ending at the start of a line does not include that line.
+    let last = if range.end > start && range.end == line_start(text, range.end) {
+        range.end - 1
+    } else {
+        range.end
+    };
+    let end = line_end(text, last);
+    let marker = prefix.trim_end();
+    let lines: Vec<&str> = text[start..end].split('\n').collect();
+    // Only ASCII indentation counts, so that the column is a char boundary
+    // on every line.
+    let column = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .map(|line| line.len() - line.trim_start_matches(&[' ', '\t'][..]).len())
+        .min()
+        .unwrap_or(0);
+    let uncomment = lines
+        .iter()
+        .filter(|line| !is_blank(line))
+        .all(|line| line.trim_start().starts_with(marker));
+    let lines: Vec<String> = lines
+        .into_iter()
+        .map(|line| {
+            if is_blank(line) {
+                line.to_owned()
+            } else if uncomment {
+                let indent = line.len() - line.trim_start().len();
+                let code = &line[indent..];
+                let code = code
+                    .strip_prefix(prefix)
+                    .or_else(|| code.strip_prefix(marker))
+                    .unwrap_or(code);
+                format!("{}{}", &line[..indent], code)
+            } else {
+                format!("{}{}{}", &line[..column], prefix, &line[column..])
+            }
+        })
+        .collect();
+    (start..end, lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixed_indentation() {
+        let text = "a\n\t  b\n    c\n\t\td\n \t\n  \te";
+        assert_eq!(mixed_indentation_lines(text), [1, 5]);
+    }
+
+    #[test]
+    fn toggle_quotes_non_ascii() {
+        assert_eq!(toggle_quotes("'€'").as_deref(), Some("\"€\""));
+        assert_eq!(toggle_quotes("f'€ é'").as_deref(), Some("f\"€ é\""));
+        assert_eq!(toggle_quotes("'''€'''").as_deref(), Some("\"\"\"€\"\"\""));
+    }
+
+    #[test]
+    fn clause_indent() {
+        for (typed, indent) in [
+            ("if ready:\n    go()\n    else:", Some("")),
+            (
+                "for x in y:\n    if x:\n        pass\n        else:",
+                Some("    "),
+            ),
+            ("go()\n    else:", None),
+        ] {
+            let start = line_start(typed, typed.len());
+            assert_eq!(
+                super::clause_indent(typed, start, "else"),
+                indent,
+                "{:?}",
+                typed
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_eol_mixed() {
+        let text = "a\r\nb\nc\rd\r\n";
+        assert_eq!(normalize_eol(text, "\n"), "a\nb\nc\nd\n");
+        assert_eq!(normalize_eol(text, "\r\n"), "a\r\nb\r\nc\r\nd\r\n");
+    }
+
+    #[test]
+    fn reflow() {
+        let comment = "    # Reflow the words of this\n    # comment to fit the width.\n    #\n    #     kept(as, is)\n";
+        let reflowed = "    # Reflow the words\n    # of this comment to\n    # fit the width.\n    #\n    #     kept(as, is)";
+        assert_eq!(reflow_comment(comment, "#", 24), reflowed);
+    }
+
+    #[test]
+    fn clamp_offset_to_char_boundary() {
+        for (offset, clamped) in [(0, 0), (2, 1), (3, 3), (10, 3)] {
+            assert_eq!(clamp_offset("aé", offset), clamped, "{}", offset);
+        }
+    }
+
+    #[test]
+    fn group_and_ungroup_digits() {
+        for (literal, grouped) in [
+            ("1000000", "1_000_000"),
+            ("1234567.891", "1_234_567.891"),
+            ("100", "100"),
+            ("25000j", "25_000j"),
+            ("1000e10", "1_000e10"),
+            ("0x1F0000", "0x1F0000"),
+        ] {
+            assert_eq!(group_digits(literal), grouped);
+            assert_eq!(ungroup_digits(grouped), literal);
+        }
+    }
+
+    #[test]
+    fn blank_line_offsets() {
+        let text = "a\nb\n\nc\n\n\nd";
+        assert_eq!(next_blank_line_offset(text, 0), 4);
+        assert_eq!(next_blank_line_offset(text, 4), 7);
+        assert_eq!(next_blank_line_offset(text, 7), text.len());
+        assert_eq!(prev_blank_line_offset(text, 9), 8);
+        assert_eq!(prev_blank_line_offset(text, 8), 4);
+        assert_eq!(prev_blank_line_offset(text, 4), 0);
+        // Lines of whitespace count as blank.
+        assert_eq!(next_blank_line_offset("a\n \t\nb", 0), 2);
+    }
+
+    #[test]
+    fn suspicious_spaces() {
+        let text = "a\u{a0}b\u{200b}c\td\r\n";
+        assert_eq!(
+            suspicious_characters(text),
+            [(1..3, '\u{a0}'), (4..7, '\u{200b}')]
+        );
+        assert!(suspicious_characters("plain\ttext\r\n").is_empty());
+    }
+
+    #[test]
+    fn final_newline() {
+        assert_eq!(ensure_final_newline("a\nb"), "a\nb\n");
+        assert_eq!(ensure_final_newline("a\r\nb"), "a\r\nb\r\n");
+        assert_eq!(ensure_final_newline("a\r\n\r\n\r\n"), "a\r\n");
+        assert_eq!(ensure_final_newline("a\n  \n\n"), "a\n");
+        assert_eq!(ensure_final_newline("a\nb\n"), "a\nb\n");
+        assert_eq!(ensure_final_newline("\n\n"), "\n\n");
+    }
+
+    #[test]
+    fn comment_column() {
+        let text = "    a\n  b\n      c\n";
+        let (range, commented) = toggle_line_comment(text, 0..text.len(), "# ");
+        assert_eq!(range, 0..text.len() - 1);
+        assert_eq!(commented, "  #   a\n  # b\n  #     c");
+
+        let text = "  if a:\n      b\n  \u{3000}c";
+        let (_, commented) = toggle_line_comment(text, 0..text.len(), "# ");
+        assert_eq!(commented, "  # if a:\n  #     b\n  # \u{3000}c");
+        let (_, uncommented) = toggle_line_comment(&commented, 0..commented.len(), "# ");
+        assert_eq!(uncommented, text);
+    }
+
+    #[test]
+    fn comment_skips_blank_lines() {
+        let text = "a\n\n  \nb";
+        let (range, commented) = toggle_line_comment(text, 0..text.len(), "# ");
+        assert_eq!(range, 0..text.len());
+        assert_eq!(commented, "# a\n\n  \n# b");
+        let (_, uncommented) = toggle_line_comment(&commented, 0..commented.len(), "# ");
+        assert_eq!(uncommented, text);
+    }
+
+    #[test]
+    fn reindent() {
+        let block = "if a:\n    b()\n\n  \nc()";
+        assert_eq!(
+            reindent_block(block, "", "        "),
+            "if a:\n            b()\n\n\n        c()"
+        );
+        let block = "if a:\n            b()\n        \n        c()";
+        assert_eq!(
+            reindent_block(block, "        ", "    "),
+            "if a:\n        b()\n\n    c()"
+        );
+    }
+
+    #[test]
+    fn byte_order_mark() {
+        assert_eq!(strip_bom("\u{feff}x = 1\n"), ("x = 1\n", true));
+        assert_eq!(strip_bom("x = 1\n"), ("x = 1\n", false));
+        assert_eq!(strip_bom("x\u{feff}"), ("x\u{feff}", false));
+    }
+
+    #[test]
+    fn trimmed_enter() {
+        let text = "    foo   \n";
+        assert_eq!(
+            trimmed_line_break(text, 10..10),
+            (7..10, "\n    ".to_owned())
+        );
+        let text = "foo  bar\r\n";
+        assert_eq!(trimmed_line_break(text, 5..8), (3..8, "\r\n".to_owned()));
+        // A blank line is emptied, keeping its indentation for the next one.
+        let text = "if a:\n    ";
+        assert_eq!(
+            trimmed_line_break(text, 10..10),
+            (6..10, "\n    ".to_owned())
+        );
+    }
+
+    #[test]
+    fn toggle_quotes_escapes() {
+        let single = r"'it\'s'";
+        let double = "\"it's\"";
+        assert_eq!(toggle_quotes(single).as_deref(), Some(double));
+        assert_eq!(toggle_quotes(double).as_deref(), Some(single));
+        assert_eq!(
+            toggle_quotes(r#"b"say \"hi\"""#).as_deref(),
+            Some(r#"b'say "hi"'"#)
+        );
+    }
+
+    #[test]
+    fn split_and_join_strings() {
+        assert_eq!(split_string("'ab'", 2).as_deref(), Some("'a' 'b'"));
+        assert_eq!(join_strings(&["'a'", "'b'"]).as_deref(), Some("'ab'"));
+        assert_eq!(split_string("'ab'", 0), None);
+
+        // Escape sequences are not torn apart.
+        assert_eq!(split_string(r"'a\nb'", 3), None);
+        assert_eq!(split_string(r"'a\nb'", 4).as_deref(), Some(r"'a\n' 'b'"));
+        assert_eq!(join_strings(&[r"'a\n'", "'b'"]).as_deref(), Some(r"'a\nb'"));
+
+        // Nor are the replacement fields of f-strings, unlike escaped braces.
+        assert_eq!(split_string("f'{x}y'", 3), None);
+        assert_eq!(split_string("f'{x}y'", 5).as_deref(), Some("f'{x}' f'y'"));
+        assert_eq!(split_string("f'{{a'", 4).as_deref(), Some("f'{{' f'a'"));
+        assert_eq!(
+            join_strings(&["f'{x}'", "F'y'"]).as_deref(),
+            Some("f'{x}y'")
+        );
+
+        assert_eq!(join_strings(&["'a'", "\"b\""]), None);
+        assert_eq!(join_strings(&["b'a'", "'b'"]), None);
+    }
+
+    #[test]
+    fn debug_print() {
+        assert_eq!(
+            debug_print_line(DEFAULT_DEBUG_PRINT, "a +\n    b", "    ", "\n"),
+            "\n    print(f\"{a + b=}\")"
+        );
+        assert_eq!(
+            debug_print_line("console.log($expr)", "x", "", "\r\n"),
+            "\r\nconsole.log(x)"
+        );
+    }
+
+    #[test]
+    fn increment_number() {
+        assert_eq!(increment_last_number("item_09").as_deref(), Some("item_10"));
+        assert_eq!(increment_last_number("x = 99").as_deref(), Some("x = 100"));
+        assert_eq!(
+            increment_last_number("a1 = b1;").as_deref(),
+            Some("a1 = b2;")
+        );
+        assert_eq!(increment_last_number("-1").as_deref(), Some("-2"));
+        assert_eq!(increment_last_number("é9").as_deref(), Some("é10"));
+        assert_eq!(increment_last_number("no numbers"), None);
+    }
+}
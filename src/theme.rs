@@ -0,0 +1,201 @@
+//! Editor color themes.
+
+use std::sync::Arc;
+
+use druid::{Color, Data, Env};
+
+use crate::codetext;
+use crate::config::{self, ConfigError};
+use crate::diagnostics::Severity;
+
+/// The highest opacity of token backgrounds, which would hide the text
+/// otherwise.
+const MAX_BACKGROUND_ALPHA: f64 = 0.4;
+
+/// The colors used to render the editor chrome.
+///
+/// Themes can be loaded from files in the format described in
+/// [`config`](crate::config), using the field names of this struct as keys:
+///
+/// ```text
+/// background = #282c34
+/// caret = #f8f8f0
+/// ```
+///
+/// Fields that are not specified keep their default value.
+///
+/// Tokens are given a background color with keys of the form
+/// `background.<capture>`, e.g. `background.comment = #e5c07b33`. Backgrounds
+/// are drawn over the text, so more opaque ones are made translucent.
+#[derive(Clone, Data)]
+pub struct Theme {
+    pub background: Color,
+    pub foreground: Color,
+    pub selection_background: Color,
+    pub selection_foreground: Color,
+    pub caret: Color,
+    pub line_number: Color,
+    /// The color of the scrollbar marks of syntax errors.
+    pub error: Color,
+    /// The color of the scrollbar marks of warnings, e.g. inconsistent
+    /// indentation.
+    pub warning: Color,
+    /// The color of the column ruler.
+    pub ruler: Color,
+    /// The color of the bookmark markers in the gutter.
+    pub bookmark: Color,
+    /// The color of invisible and control characters shown with Show
+    /// Invisible Characters.
+    pub invisible: Color,
+    /// The color of tokens whose highlight capture has no color mapping.
+    pub unmapped_capture: Color,
+    /// The colors of variables bound in the function they are used in, in
+    /// an enclosing function and in the module, with the
+    /// `color_variable_scopes` setting on.
+    pub variable_local: Color,
+    pub variable_enclosing: Color,
+    pub variable_global: Color,
+    /// The background color of each highlight capture that has one.
+    pub capture_backgrounds: Arc<Vec<(String, Color)>>,
+}
+
+impl Default for Theme {
+    /// One Monokai: https://github.com/azemoh/vscode-one-monokai
+    fn default() -> Self {
+        Theme {
+            background: Color::rgb8(0x28, 0x2c, 0x34),
+            foreground: Color::rgb8(0xab, 0xb2, 0xbf),
+            selection_background: Color::rgb8(0x3e, 0x44, 0x51),
+            selection_foreground: Color::rgb8(0xf8, 0xf8, 0xf0),
+            caret: Color::rgb8(0xf8, 0xf8, 0xf0),
+            line_number: Color::rgb8(0x49, 0x51, 0x62),
+            error: Color::rgb8(0xe0, 0x6c, 0x75),
+            warning: Color::rgb8(0xe5, 0xc0, 0x7b),
+            ruler: Color::rgb8(0x3b, 0x40, 0x48),
+            bookmark: Color::rgb8(0x61, 0xaf, 0xef),
+            invisible: Color::rgb8(0xf4, 0x47, 0x47),
+            unmapped_capture: Color::rgb8(0xab, 0xb2, 0xbf),
+            variable_local: Color::rgb8(0xab, 0xb2, 0xbf),
+            variable_enclosing: Color::rgb8(0x56, 0xb6, 0xc2),
+            variable_global: Color::rgb8(0xd1, 0x9a, 0x66),
+            capture_backgrounds: Arc::new(Vec::new()),
+        }
+    }
+}
+
+impl Theme {
+    /// Parse a theme from the contents of a theme file.
+    pub fn parse(source: &str) -> Result<Self, ConfigError> {
+        let mut theme = Theme::default();
+        let mut backgrounds = Vec::new();
+        for (line, key, value) in config::parse_entries(source)? {
+            let color = Color::from_hex_str(value)
+                .map_err(|_| ConfigError::new(line, format!("invalid color `{}`", value)))?;
+            if let Some(capture) = key.strip_prefix("background.") {
+                let alpha = (color.as_rgba_u32() & 0xff) as f64 / 255.0;
+                let color = color.with_alpha(alpha.min(MAX_BACKGROUND_ALPHA));
+                backgrounds.retain(|(other, _)| other != capture);
+                backgrounds.push((capture.to_owned(), color));
+                continue;
+            }
+            match key {
+                "background" => theme.background = color,
+                "foreground" => theme.foreground = color,
+                "selection_background" => theme.selection_background = color,
+                "selection_foreground" => theme.selection_foreground = color,
+                "caret" => theme.caret = color,
+                "line_number" => theme.line_number = color,
+                "error" => theme.error = color,
+                "warning" => theme.warning = color,
+                "ruler" => theme.ruler = color,
+                "bookmark" => theme.bookmark = color,
+                "invisible" => theme.invisible = color,
+                "unmapped_capture" => theme.unmapped_capture = color,
+                "variable_local" => theme.variable_local = color,
+                "variable_enclosing" => theme.variable_enclosing = color,
+                "variable_global" => theme.variable_global = color,
+                _ => return Err(ConfigError::new(line, format!("unknown key `{}`", key))),
+            }
+        }
+        theme.capture_backgrounds = Arc::new(backgrounds);
+        Ok(theme)
+    }
+
+    /// Load the user's theme from the config directory, falling back to the
+    /// default theme if there is none. Returns why loading failed if it is
+    /// invalid.
+    pub fn load_user() -> Result<Self, String> {
+        config::load_user("theme", Theme::parse)
+    }
+
+    /// The color of the marks of diagnostics of `severity`.
+    pub fn diagnostic(&self, severity: Severity) -> &Color {
+        match severity {
+            Severity::Error => &self.error,
+            Severity::Warning => &self.warning,
+            Severity::Information | Severity::Hint => &self.line_number,
+        }
+    }
+
+    /// Apply the theme's colors to the druid environment.
+    pub fn apply(&self, env: &mut Env) {
+        env.set(druid::theme::BACKGROUND_LIGHT, self.background.clone());
+        env.set(druid::theme::TEXT_COLOR, self.foreground.clone());
+        env.set(
+            druid::theme::SELECTED_TEXT_BACKGROUND_COLOR,
+            self.selection_background.clone(),
+        );
+        env.set(
+            druid::theme::SELECTION_TEXT_COLOR,
+            self.selection_foreground.clone(),
+        );
+        env.set(druid::theme::CURSOR_COLOR, self.caret.clone());
+        env.set(codetext::INVISIBLE_COLOR, self.invisible.clone());
+        env.set(
+            codetext::UNMAPPED_CAPTURE_COLOR,
+            self.unmapped_capture.clone(),
+        );
+        env.set(codetext::VARIABLE_LOCAL_COLOR, self.variable_local.clone());
+        env.set(
+            codetext::VARIABLE_ENCLOSING_COLOR,
+            self.variable_enclosing.clone(),
+        );
+        env.set(
+            codetext::VARIABLE_GLOBAL_COLOR,
+            self.variable_global.clone(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codetext::CodeText;
+
+    #[test]
+    fn capture_backgrounds() {
+        // The last background given for a capture applies.
+        let source = "background.keyword = #ff000040
+background.comment = #e5c07b33
+background.keyword = #e06c7540
+";
+        let theme = Theme::parse(source).unwrap();
+        let keyword = Color::from_hex_str("#e06c7540").unwrap();
+        assert_eq!(theme.capture_backgrounds.len(), 2);
+        let code = CodeText::new("import os\n".to_owned());
+        let backgrounds: Vec<_> = code
+            .capture_backgrounds(&theme.capture_backgrounds)
+            .into_iter()
+            .map(|(range, color)| (range, color.as_rgba_u32()))
+            .collect();
+        assert_eq!(backgrounds, [(0..6, keyword.as_rgba_u32())]);
+    }
+
+    #[test]
+    fn opaque_backgrounds_are_translucent() {
+        let theme = Theme::parse("background.string = #98c379").unwrap();
+        let translucent = Color::from_hex_str("#98c379").unwrap().with_alpha(0.4);
+        let (_, color) = &theme.capture_backgrounds[0];
+        assert_eq!(color.as_rgba_u32(), translucent.as_rgba_u32());
+    }
+}
@@ -0,0 +1,85 @@
+//! Formatting of the current date and time for insertion into the document.
+
+use time::OffsetDateTime;
+
+/// ISO 8601 with the local UTC offset, e.g. `2021-06-01T14:30:00+02:00`.
+pub const DEFAULT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S%z";
+
+/// The current time in the local time zone, or in UTC if the local offset
+/// cannot be determined.
+pub fn now() -> OffsetDateTime {
+    OffsetDateTime::now_local().unwrap_or_else(|_| OffsetDateTime::now_utc())
+}
+
+/// Format `time` according to `format`, in which `%Y`, `%m`, `%d`, `%H`, `%M`
+/// and `%S` are replaced by the zero-padded year, month, day, hour, minute and
+/// second, `%z` by the UTC offset as `+hh:mm` and `%%` by `%`. Other
+/// characters, including unknown specifiers, are copied as they are.
+pub fn format(format: &str, time: OffsetDateTime) -> String {
+    let mut formatted = String::with_capacity(format.len());
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            formatted.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('Y') => formatted += &format!("{:04}", time.year()),
+            Some('m') => formatted += &format!("{:02}", u8::from(time.month())),
+            Some('d') => formatted += &format!("{:02}", time.day()),
+            Some('H') => formatted += &format!("{:02}", time.hour()),
+            Some('M') => formatted += &format!("{:02}", time.minute()),
+            Some('S') => formatted += &format!("{:02}", time.second()),
+            Some('z') => {
+                let minutes = time.offset().whole_minutes();
+                let sign = if minutes < 0 { '-' } else { '+' };
+                let minutes = minutes.abs();
+                formatted += &format!("{}{:02}:{:02}", sign, minutes / 60, minutes % 60);
+            }
+            Some('%') => formatted.push('%'),
+            Some(other) => {
+                formatted.push('%');
+                formatted.push(other);
+            }
+            None => formatted.push('%'),
+        }
+    }
+    formatted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::{Date, Month, UtcOffset};
+
+    fn june_first(offset: UtcOffset) -> OffsetDateTime {
+        Date::from_calendar_date(2021, Month::June, 1)
+            .unwrap()
+            .with_hms(4, 5, 6)
+            .unwrap()
+            .assume_offset(offset)
+    }
+
+    #[test]
+    fn specifiers() {
+        let cet = UtcOffset::from_hms(2, 0, 0).unwrap();
+        assert_eq!(
+            format(DEFAULT_FORMAT, june_first(cet)),
+            "2021-06-01T04:05:06+02:00"
+        );
+        assert_eq!(
+            format("%d.%m.%Y %H:%M", june_first(cet)),
+            "01.06.2021 04:05"
+        );
+        let newfoundland = UtcOffset::from_hms(-3, -30, 0).unwrap();
+        assert_eq!(format("%z", june_first(newfoundland)), "-03:30");
+        assert_eq!(format("%z", june_first(UtcOffset::UTC)), "+00:00");
+    }
+
+    #[test]
+    fn literal_percent() {
+        let utc = june_first(UtcOffset::UTC);
+        assert_eq!(format("100%% at %H", utc), "100% at 04");
+        assert_eq!(format("%q %", utc), "%q %");
+    }
+}
@@ -0,0 +1,61 @@
+//! Typewriter scrolling, which keeps the caret line vertically centered.
+
+use druid::widget::prelude::*;
+use druid::widget::Scroll;
+use druid::Vec2;
+
+use crate::editor;
+use crate::AppState;
+
+/// Wraps the vertical [`Scroll`] of an editor and centers the caret when the
+/// editor asks for it with [`CENTER_CARET`](editor::CENTER_CARET). Near the
+/// start and end of the document the caret stays as close to the center as
+/// the scroll range allows.
+pub struct Typewriter<W> {
+    scroll: Scroll<AppState, W>,
+}
+
+impl<W> Typewriter<W> {
+    pub fn new(scroll: Scroll<AppState, W>) -> Self {
+        Typewriter { scroll }
+    }
+}
+
+impl<W: Widget<AppState>> Widget<AppState> for Typewriter<W> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        if let Event::Notification(notification) = event {
+            if let Some(caret) = notification.get(editor::CENTER_CARET) {
+                let caret_y = caret.center().y - ctx.window_origin().y;
+                let delta = caret_y - ctx.size().height / 2.0;
+                if self.scroll.scroll_by(Vec2::new(0.0, delta)) {
+                    ctx.request_paint();
+                }
+                ctx.set_handled();
+                return;
+            }
+        }
+        self.scroll.event(ctx, event, data, env);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &AppState, env: &Env) {
+        self.scroll.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &AppState, data: &AppState, env: &Env) {
+        self.scroll.update(ctx, old_data, data, env);
+    }
+
+    fn layout(
+        &mut self,
+        ctx: &mut LayoutCtx,
+        bc: &BoxConstraints,
+        data: &AppState,
+        env: &Env,
+    ) -> Size {
+        self.scroll.layout(ctx, bc, data, env)
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &AppState, env: &Env) {
+        self.scroll.paint(ctx, data, env);
+    }
+}